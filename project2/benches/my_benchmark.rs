@@ -1,96 +1,319 @@
-use core::fmt::Debug;
-use std::iter;
+use project2::avl::AVLTree;
+use project2::btree::BTree;
+use project2::ordered_set::OrderedSet;
+use project2::rb::RedBlackTree;
+use project2::rng::Rng;
+use project2::skiplist::SkipList;
+use project2::treap::Treap;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::BTreeSet;
+use std::hint::black_box;
+use std::ops::Bound;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-#[path = "../src/avl.rs"]
-mod avl;
-#[path = "../src/rb.rs"]
-mod rb;
-use crate::avl::AVLTree;
-use crate::rb::RedBlackTree;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
 
-use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
-fn bench_rb(c: &mut Criterion) {
-    let mut group = c.benchmark_group("bench_rb");
+// Median times before and after moving the nodes from Rc<RefCell<_>> into the arena (arena.rs), taken
+// back to back on the same machine with `--warm-up-time 1 --measurement-time 3`. These come from the
+// earlier benches, which kept one tree across iterations: every insert after the first iteration hit a
+// key that was already there, so both columns mostly measured lookups.
+//
+//   size     | rb insert        | rb search          | avl insert       | avl search
+//            | Rc      arena    | Rc       arena     | Rc      arena    | Rc       arena
+//   10000    | 1.19ms  1.19ms   |   68us     76us    | 1.02ms  1.29ms   |  113us     77us
+//   40000    | 5.75ms  5.30ms   |  409us    372us    | 5.69ms  5.56ms   |  454us    348us
+//   70000    | 9.12ms  9.85ms   |  805us    838us    | 10.2ms  10.4ms   |  829us    756us
+//   100000   | 14.9ms  14.7ms   | 1.14ms   1.20ms    | 16.0ms  15.0ms   | 1.21ms   1.07ms
+//   130000   | 20.4ms  19.4ms   | 1.56ms   1.66ms    | 19.8ms  20.1ms   | 1.74ms   1.36ms
+//
+// Lookups come out about even, the handles cost an index and a bounds check where Rc followed a pointer
+// and touched the borrow flag and refcount. What the arena removes is the per node allocation, the
+// refcount and borrow flag, and the parent <-> child Rc cycles in the RB tree.
+//
+// Now every workload has a group of its own with one line per structure and size, so `cargo bench`
+// and the HTML report put the structures side by side, std's BTreeSet among them as the baseline.
+// Workloads that change the tree get a fresh one for every iteration, built outside the timing.
+// Filter with `cargo bench -- insert_random` or `cargo bench -- "Treap"`.
 
-    for tree_size in [10000, 40000, 70000, 100000, 130000].iter() {
-        let mut rb_tree: RedBlackTree = RedBlackTree::new();
+const SIZES: [u32; 3] = [1_000, 10_000, 100_000];
 
-        // These will pass the AVL tree to do benchmarking by first benching the inserts, then benching the searches
-        group.bench_with_input(
-            BenchmarkId::new("insert", tree_size),
-            tree_size,
-            |b, &tree_size| b.iter(|| insert_rb_tree(&mut rb_tree, tree_size)),
-        );
-        group.bench_with_input(
-            BenchmarkId::new("search", tree_size),
-            tree_size,
-            |b, &tree_size| b.iter(|| contains_rb_tree(&mut rb_tree, tree_size)),
-        );
+// The values 0..n in an order that is random but the same every run
+fn shuffled(n: u32, seed: u64) -> Vec<u32> {
+    let mut rng = Rng::with_seed(seed);
+    let mut keys: Vec<u32> = (0..n).collect();
+    for i in (1..keys.len()).rev() {
+        keys.swap(i, (rng.next_u64() % (i as u64 + 1)) as usize);
     }
-    group.finish();
+    keys
 }
 
-fn contains_rb_tree(rb_tree: &mut RedBlackTree, n: u32) {
-    for i in 0..(n / 10) {
-        rb_tree.contains(i.into());
+fn build<S: OrderedSet<u32>>(keys: &[u32]) -> S {
+    let mut tree = S::new();
+    for key in keys {
+        tree.insert(*key);
     }
+    tree
 }
 
-fn insert_rb_tree(rb_tree: &mut RedBlackTree, n: u32) {
-    for i in 0..n {
-        rb_tree.tree_insert(i.into());
+#[derive(Clone, Copy)]
+enum Op {
+    Contains(u32),
+    Insert(u32),
+    Remove(u32),
+}
+
+// A mix of lookups and updates on values from 0..2n, so about half of each kind miss. `reads` is
+// the percentage of lookups, the updates are split evenly between inserts and removes so the size
+// of the tree stays about the same.
+fn mixed_ops(n: u32, reads: u64) -> Vec<Op> {
+    let mut rng = Rng::with_seed(u64::from(n) + reads);
+    (0..n)
+        .map(|_| {
+            let key = (rng.next_u64() % (2 * u64::from(n))) as u32;
+            match rng.next_u64() % 100 {
+                roll if roll < reads => Op::Contains(key),
+                roll if roll % 2 == 0 => Op::Insert(key),
+                _ => Op::Remove(key),
+            }
+        })
+        .collect()
+}
+
+fn run_ops<S: OrderedSet<u32>>(tree: &mut S, ops: &[Op]) -> usize {
+    let mut hits = 0;
+    for op in ops {
+        let hit = match *op {
+            Op::Contains(key) => tree.contains(&key),
+            Op::Insert(key) => tree.insert(key),
+            Op::Remove(key) => tree.remove(&key),
+        };
+        hits += usize::from(hit);
     }
+    hits
 }
-fn bench_avl<
-    T: std::default::Default
-        + std::clone::Clone
-        + std::fmt::Debug
-        + std::cmp::Ord
-        + std::convert::From<i32>,
->(
-    c: &mut Criterion,
-) {
-    let mut group = c.benchmark_group("bench_avl");
-
-    for tree_size in [10000, 40000, 70000, 100000, 130000].iter() {
-        let mut avl_tree: AVLTree<T> = AVLTree::new();
-
-        // These will pass the AVL tree to do benchmarking by first benching the inserts, then benching the searches
-        group.bench_with_input(
-            BenchmarkId::new("insert", tree_size),
-            tree_size,
-            |b, &tree_size| b.iter(|| insert_avl_tree(&mut avl_tree, tree_size)),
-        );
-        group.bench_with_input(
-            BenchmarkId::new("search", tree_size),
-            tree_size,
-            |b, &tree_size| b.iter(|| contains_avl_tree(&mut avl_tree, tree_size)),
-        );
+
+// Every workload for one structure. The groups are named after the workload, so running this for
+// each structure fills every group with one line per structure.
+fn bench_structure<S: OrderedSet<u32>>(c: &mut Criterion) {
+    for n in SIZES {
+        let id = BenchmarkId::new(S::NAME, n);
+        let keys = shuffled(n, 1);
+        let lookups = shuffled(n, 2);
+        let sequential: Vec<u32> = (0..n).collect();
+        let tree: S = build(&keys);
+
+        let mut group = c.benchmark_group("insert_sequential");
+        group.bench_function(id.clone(), |b| {
+            b.iter_batched(
+                S::new,
+                |mut tree| {
+                    for key in &sequential {
+                        tree.insert(*key);
+                    }
+                    tree
+                },
+                BatchSize::LargeInput,
+            )
+        });
+        group.finish();
+
+        let mut group = c.benchmark_group("insert_random");
+        group.bench_function(id.clone(), |b| {
+            b.iter_batched(
+                S::new,
+                |mut tree| {
+                    for key in &keys {
+                        tree.insert(*key);
+                    }
+                    tree
+                },
+                BatchSize::LargeInput,
+            )
+        });
+        group.finish();
+
+        // every value in a different random order than it went in, then as many misses
+        let mut group = c.benchmark_group("contains");
+        group.bench_function(id.clone(), |b| {
+            b.iter(|| {
+                let hits = lookups.iter().filter(|key| tree.contains(key)).count();
+                let misses = lookups
+                    .iter()
+                    .filter(|key| tree.contains(&(*key + n)))
+                    .count();
+                black_box(hits + misses)
+            })
+        });
+        group.finish();
+
+        let mut group = c.benchmark_group("delete_random");
+        group.bench_function(id.clone(), |b| {
+            b.iter_batched(
+                || build::<S>(&keys),
+                |mut tree| {
+                    for key in &lookups {
+                        tree.remove(key);
+                    }
+                    tree
+                },
+                BatchSize::LargeInput,
+            )
+        });
+        group.finish();
+
+        for (name, reads) in [
+            ("mixed_90_read", 90),
+            ("mixed_50_read", 50),
+            ("mixed_10_read", 10),
+        ] {
+            let ops = mixed_ops(n, reads);
+            let mut group = c.benchmark_group(name);
+            group.bench_function(id.clone(), |b| {
+                b.iter_batched(
+                    || build::<S>(&keys),
+                    |mut tree| {
+                        black_box(run_ops(&mut tree, &ops));
+                        tree
+                    },
+                    BatchSize::LargeInput,
+                )
+            });
+            group.finish();
+        }
+
+        let mut group = c.benchmark_group("iterate");
+        group.bench_function(id.clone(), |b| {
+            b.iter(|| black_box(tree.iter().fold(0u64, |sum, key| sum + u64::from(*key))))
+        });
+        group.finish();
+
+        // a thousand ranges of a hundred values each, walked to the end
+        let mut group = c.benchmark_group("range_100");
+        let starts = shuffled(n.saturating_sub(100).max(1), 3);
+        group.bench_function(id, |b| {
+            b.iter(|| {
+                let mut total = 0;
+                for start in starts.iter().take(1000) {
+                    let end = start + 100;
+                    total += tree
+                        .range(Bound::Included(start), Bound::Excluded(&end))
+                        .count();
+                }
+                black_box(total)
+            })
+        });
+        group.finish();
     }
-    group.finish();
 }
 
-fn contains_avl_tree<T: std::cmp::Ord + std::convert::From<i32>>(avl_tree: &mut AVLTree<T>, n: i32)
-where
-    T: Clone,
-    T: Debug,
-    T: Default,
-{
-    for i in 0..(n / 10) {
-        avl_tree.contains(i.into());
+// ---------------------------------------- Memory -------------------------------------------------
+// Counts the bytes the bench binary has allocated, and the most it ever had at once since the last
+// reset, so a build can be measured by the peak it caused and the memory it kept
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let now = ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(now, Ordering::Relaxed);
+        }
+        ptr
     }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = System.realloc(ptr, layout, new_size);
+        if !new.is_null() {
+            if new_size > layout.size() {
+                let grown = new_size - layout.size();
+                let now = ALLOCATED.fetch_add(grown, Ordering::Relaxed) + grown;
+                PEAK.fetch_max(now, Ordering::Relaxed);
+            } else {
+                ALLOCATED.fetch_sub(layout.size() - new_size, Ordering::Relaxed);
+            }
+        }
+        new
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+// Peak and retained bytes of building a structure out of `keys` in random order. A realloc counts
+// as the change in size only, so the arenas, which grow one Vec by doubling, peak at what they
+// keep, even where the allocator had to copy the Vec to grow it.
+fn measure_memory<S: OrderedSet<u32>>(keys: &[u32]) -> (usize, usize) {
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    PEAK.store(before, Ordering::Relaxed);
+    let tree: S = build(keys);
+    let peak = PEAK.load(Ordering::Relaxed) - before;
+    let retained = ALLOCATED.load(Ordering::Relaxed) - before;
+    drop(black_box(tree));
+    (peak, retained)
 }
 
-fn insert_avl_tree<T: std::cmp::Ord + std::convert::From<i32>>(avl_tree: &mut AVLTree<T>, n: i32)
-where
-    T: Clone,
-    T: Debug,
-    T: Default,
-{
-    for i in 0..n {
-        avl_tree.insert(i.into());
+// Criterion only measures time, so memory is printed as a table instead
+fn report_memory<S: OrderedSet<u32>>() {
+    for n in SIZES {
+        let keys = shuffled(n, 1);
+        let (peak, retained) = measure_memory::<S>(&keys);
+        println!(
+            "memory/{:<14} {:>7} values: peak {:>10} bytes, retained {:>10} bytes, {:>5.1} bytes per value",
+            S::NAME,
+            n,
+            peak,
+            retained,
+            retained as f64 / f64::from(n)
+        );
     }
 }
 
-criterion_group!(benches, bench_avl::<i32>, bench_rb);
+fn bench_memory(_: &mut Criterion) {
+    report_memory::<BTreeSet<u32>>();
+    report_memory::<RedBlackTree<u32>>();
+    report_memory::<AVLTree<u32>>();
+    report_memory::<BTree<u32>>();
+    report_memory::<Treap<u32>>();
+    report_memory::<SkipList<u32>>();
+}
+
+fn bench_btreeset(c: &mut Criterion) {
+    bench_structure::<BTreeSet<u32>>(c);
+}
+
+fn bench_rb(c: &mut Criterion) {
+    bench_structure::<RedBlackTree<u32>>(c);
+}
+
+fn bench_avl(c: &mut Criterion) {
+    bench_structure::<AVLTree<u32>>(c);
+}
+
+fn bench_btree(c: &mut Criterion) {
+    bench_structure::<BTree<u32>>(c);
+}
+
+// The randomized structures, for comparing expected against guaranteed balance
+fn bench_treap(c: &mut Criterion) {
+    bench_structure::<Treap<u32>>(c);
+}
+
+fn bench_skiplist(c: &mut Criterion) {
+    bench_structure::<SkipList<u32>>(c);
+}
+
+criterion_group! {
+    name = benches;
+    // the larger workloads take long per iteration, fewer samples keep a full run in minutes
+    config = Criterion::default().sample_size(20);
+    targets = bench_memory, bench_btreeset, bench_avl, bench_rb, bench_btree, bench_treap, bench_skiplist
+}
 criterion_main!(benches);
@@ -1,520 +1,1689 @@
-use std::rc::Rc;
-use std::cell::RefCell;
+use crate::arena::{Arena, NodeId};
+use crate::export;
+use crate::invariant::{Invariant, InvariantViolation};
+use crate::map::{self, EntryMap, MapNode};
+use crate::ordered_set::OrderedSet;
+use crate::snapshot::{self, Codec, SnapshotError};
+use crate::stats::{self, TreeStats};
+use crate::trace::{Observer, Step};
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::fs;
+use std::io::{self, Write};
+use std::mem;
+use std::ops::{Bound, RangeBounds};
+use std::path::Path;
 
-#[derive(Debug)]
-struct Node<T> {
-    data: Option<T>,
-    height: i32,
-    left: Option<Rc<RefCell<Node<T>>>>,
-    right: Option<Rc<RefCell<Node<T>>>>,
-}
+mod node {
+    use crate::arena::NodeId;
 
+    // Public only inside this private module, so that the iterator types below can name it without it
+    // becoming part of the API
+    #[derive(Debug)]
+    pub struct Node<K, V> {
+        pub(super) key: K,
+        pub(super) value: V,
+        pub(super) height: i32,
+        // number of nodes in the subtree rooted here, used for rank and select
+        pub(super) size: usize,
+        pub(super) left: Option<NodeId>,
+        pub(super) right: Option<NodeId>,
+    }
+}
+use node::Node;
 
-impl<T> Node<T> {
-    fn new(data: Option<T>) -> Rc<RefCell<Self>> {
-        Rc::new(RefCell::new(Node {
-            data,
+impl<K, V> Node<K, V> {
+    fn new(key: K, value: V) -> Self {
+        Node {
+            key,
+            value,
             height: 1,
+            size: 1,
             left: None,
             right: None,
-        }))
+        }
+    }
+}
+
+impl<K, V> MapNode for Node<K, V> {
+    type Key = K;
+    type Value = V;
+
+    fn left(&self) -> Option<NodeId> {
+        self.left
+    }
+
+    fn right(&self) -> Option<NodeId> {
+        self.right
+    }
+
+    fn entry(&self) -> (&K, &V) {
+        (&self.key, &self.value)
+    }
+
+    fn into_entry(self) -> (K, V) {
+        (self.key, self.value)
     }
 }
 
 // Gets the height for the update_height function
-fn height<T>(node: &Option<Rc<RefCell<Node<T>>>>) -> i32 {
-    node.as_ref().map_or(0, |n| n.borrow().height)
+fn height<K, V>(nodes: &Arena<Node<K, V>>, node: Option<NodeId>) -> i32 {
+    node.map_or(0, |n| nodes[n].height)
 }
 
 // Checks the balance of the nodes by left - right
-fn balance_factor<T>(node: &Option<Rc<RefCell<Node<T>>>>) -> i32 {
-    height(&node.as_ref().unwrap().borrow().left) - height(&node.as_ref().unwrap().borrow().right)
+fn balance_factor<K, V>(nodes: &Arena<Node<K, V>>, node: NodeId) -> i32 {
+    height(nodes, nodes[node].left) - height(nodes, nodes[node].right)
+}
+
+// Gets the number of nodes below and including the given one
+fn size<K, V>(nodes: &Arena<Node<K, V>>, node: Option<NodeId>) -> usize {
+    node.map_or(0, |n| nodes[n].size)
 }
 
 // Check heights of left and right node, take the larger one and adds 1.
-fn update_height<T>(node: &Option<Rc<RefCell<Node<T>>>>) {
-    let height = std::cmp::max(height(&node.as_ref().unwrap().borrow().left), height(&node.as_ref().unwrap().borrow().right)) + 1;
-    node.as_ref().unwrap().borrow_mut().height = height;
+// The subtree size is refreshed here as well since it changes in the same places as the height.
+fn update_height<K, V>(nodes: &mut Arena<Node<K, V>>, node: NodeId) {
+    let height = std::cmp::max(height(nodes, nodes[node].left), height(nodes, nodes[node].right)) + 1;
+    let size = size(nodes, nodes[node].left) + size(nodes, nodes[node].right) + 1;
+    let node = &mut nodes[node];
+    node.height = height;
+    node.size = size;
 }
 
 // Rotates the given node to the left
-fn rotate_left<T>(node: Rc<RefCell<Node<T>>>) -> Rc<RefCell<Node<T>>> {
-    // maps are used in case the value is none on the right side.
-    let right = node.borrow().right.as_ref().unwrap().clone();
-    let left = right.borrow().left.as_ref().map(|node| node.clone());
-    node.borrow_mut().right = left.clone();
-    right.borrow_mut().left = Some(node.clone());
-    update_height(&right.borrow().left);
+fn rotate_left<K, V>(nodes: &mut Arena<Node<K, V>>, node: NodeId) -> NodeId {
+    let right = nodes[node].right.unwrap();
+    // the left child of the right node can be empty, it moves over either way
+    let left = nodes[right].left;
+    nodes[node].right = left;
+    nodes[right].left = Some(node);
+    // the old root is now below the new one, so its height has to be fixed first
+    update_height(nodes, node);
+    update_height(nodes, right);
     right
 }
 
 // Rotates the given node to the right
-fn rotate_right<T>(node: Rc<RefCell<Node<T>>>) -> Rc<RefCell<Node<T>>> {
-    // maps are used in case the value is none on the left side
-    let left = node.borrow().left.as_ref().unwrap().clone();
-    let right = left.borrow().right.as_ref().map(|node| node.clone());
-    node.borrow_mut().left = right.clone();
-    left.borrow_mut().right = Some(node.clone());
-    update_height(&left.borrow().right);
+fn rotate_right<K, V>(nodes: &mut Arena<Node<K, V>>, node: NodeId) -> NodeId {
+    let left = nodes[node].left.unwrap();
+    // the right child of the left node can be empty, it moves over either way
+    let right = nodes[left].right;
+    nodes[node].left = right;
+    nodes[left].right = Some(node);
+    // the old root is now below the new one, so its height has to be fixed first
+    update_height(nodes, node);
+    update_height(nodes, left);
     left
 }
 // Rotates the given node left and then right
-fn rotate_left_right<T>(node: Rc<RefCell<Node<T>>>) -> Rc<RefCell<Node<T>>> {
-    let left = node.borrow().left.as_ref().unwrap().clone();
-    let new_left = rotate_left(left.clone());
-    node.borrow_mut().left = Some(new_left.clone());
-    rotate_right(node)
+fn rotate_left_right<K, V>(nodes: &mut Arena<Node<K, V>>, node: NodeId) -> NodeId {
+    let left = nodes[node].left.unwrap();
+    let new_left = rotate_left(nodes, left);
+    nodes[node].left = Some(new_left);
+    rotate_right(nodes, node)
 }
 
 // Rotates the given node right and then left
-fn rotate_right_left<T>(node: Rc<RefCell<Node<T>>>) -> Rc<RefCell<Node<T>>> {
-    let right = node.borrow().right.as_ref().unwrap().clone();
-    let new_right = rotate_right(right.clone());
-    node.borrow_mut().right = Some(new_right.clone());
-    rotate_left(node)
+fn rotate_right_left<K, V>(nodes: &mut Arena<Node<K, V>>, node: NodeId) -> NodeId {
+    let right = nodes[node].right.unwrap();
+    let new_right = rotate_right(nodes, right);
+    nodes[node].right = Some(new_right);
+    rotate_left(nodes, node)
+}
+
+// The four ways a node can be out of balance, named after the path from it down to the taller grandchild
+#[derive(Clone, Copy)]
+enum Imbalance {
+    LeftLeft,
+    LeftRight,
+    RightRight,
+    RightLeft,
+}
+
+impl Imbalance {
+    // The name an observer sees for the case
+    fn name(self) -> &'static str {
+        match self {
+            Imbalance::LeftLeft => "LeftLeft",
+            Imbalance::LeftRight => "LeftRight",
+            Imbalance::RightRight => "RightRight",
+            Imbalance::RightLeft => "RightLeft",
+        }
+    }
+}
+
+// Works out which case applies at the node, None if it is balanced
+fn imbalance<K, V>(nodes: &Arena<Node<K, V>>, node: NodeId) -> Option<Imbalance> {
+    let balance = balance_factor(nodes, node);
+    if balance > 1 {
+        // When the left child leans right, it is a left-right case, otherwise a left-left case
+        let left_balance = balance_factor(nodes, nodes[node].left.unwrap());
+        Some(if left_balance < 0 { Imbalance::LeftRight } else { Imbalance::LeftLeft })
+    } else if balance < -1 {
+        // When the right child leans left, it is a right-left case, otherwise a right-right case
+        let right_balance = balance_factor(nodes, nodes[node].right.unwrap());
+        Some(if right_balance > 0 { Imbalance::RightLeft } else { Imbalance::RightRight })
+    } else {
+        None
+    }
 }
 
 // https://www.youtube.com/watch?v=vRwi_UcZGjU for explanation on rotation balance logic
-fn rebalance<T: Clone>(node: Rc<RefCell<Node<T>>>) -> Rc<RefCell<Node<T>>> {
+fn rebalance<K, V>(nodes: &mut Arena<Node<K, V>>, node: NodeId) -> NodeId {
     // If the tree is unbalanced in one direction then rotatation will be used to self correct the AVL tree
-    let balance = balance_factor(&Some(node.clone()));
-    if balance > 1 {
-        // When tree is right-heavy, perform left rotation then right rotation, otherwise just do a right rotation
-        let left_balance = balance_factor(&node.borrow().left);
-        if left_balance < 0 {
-            rotate_left_right(node)
-        } else {
-            rotate_right(node)
+    match imbalance(nodes, node) {
+        Some(Imbalance::LeftLeft) => rotate_right(nodes, node),
+        Some(Imbalance::LeftRight) => rotate_left_right(nodes, node),
+        Some(Imbalance::RightRight) => rotate_left(nodes, node),
+        Some(Imbalance::RightLeft) => rotate_right_left(nodes, node),
+        None => node,
+    }
+}
+
+// ---------------------------------------- Join ------------------------------------------------
+// Hangs both subtrees under the node and returns it
+fn link<K, V>(nodes: &mut Arena<Node<K, V>>, left: Option<NodeId>, node: NodeId, right: Option<NodeId>) -> NodeId {
+    nodes[node].left = left;
+    nodes[node].right = right;
+    update_height(nodes, node);
+    node
+}
+
+// Joins two subtrees with `node` in between, every key in `left` is smaller than the node's and every key in
+// `right` larger. Runs in the difference of the heights.
+fn join<K, V>(nodes: &mut Arena<Node<K, V>>, left: Option<NodeId>, node: NodeId, right: Option<NodeId>) -> NodeId {
+    let (left_height, right_height) = (height(nodes, left), height(nodes, right));
+    if left_height > right_height + 1 {
+        join_right(nodes, left.unwrap(), node, right)
+    } else if right_height > left_height + 1 {
+        join_left(nodes, left, node, right.unwrap())
+    } else {
+        link(nodes, left, node, right)
+    }
+}
+
+// Walks down the right spine of the taller `left` until the rest fits next to `right`, and rebalances on the way
+// back up the same way an insert does
+fn join_right<K, V>(nodes: &mut Arena<Node<K, V>>, left: NodeId, node: NodeId, right: Option<NodeId>) -> NodeId {
+    let spine = nodes[left].right;
+    let joined = if height(nodes, spine) <= height(nodes, right) + 1 {
+        link(nodes, spine, node, right)
+    } else {
+        join_right(nodes, spine.unwrap(), node, right)
+    };
+    nodes[left].right = Some(joined);
+    update_height(nodes, left);
+    rebalance(nodes, left)
+}
+
+// Mirror of join_right for a taller `right`
+fn join_left<K, V>(nodes: &mut Arena<Node<K, V>>, left: Option<NodeId>, node: NodeId, right: NodeId) -> NodeId {
+    let spine = nodes[right].left;
+    let joined = if height(nodes, spine) <= height(nodes, left) + 1 {
+        link(nodes, left, node, spine)
+    } else {
+        join_left(nodes, left, node, spine.unwrap())
+    };
+    nodes[right].left = Some(joined);
+    update_height(nodes, right);
+    rebalance(nodes, right)
+}
+
+// Takes the largest node out of a subtree, returns what is left and the node
+fn split_last<K, V>(nodes: &mut Arena<Node<K, V>>, node: NodeId) -> (Option<NodeId>, NodeId) {
+    let (left, right) = (nodes[node].left.take(), nodes[node].right.take());
+    match right {
+        None => (left, node),
+        Some(right) => {
+            let (rest, last) = split_last(nodes, right);
+            (Some(join(nodes, left, node, rest)), last)
         }
+    }
+}
 
-    } else if balance < -1 {
-        // When tree is left-heavy, perform right rotation then left rotation, otherwise just do a left rotation
-        let right_balance = balance_factor(&node.borrow().right);
-        if right_balance > 0 {
-            rotate_right_left(node)
-        } else {
-            rotate_left(node)
+// Joins two subtrees without a node in between
+fn join_pair<K, V>(nodes: &mut Arena<Node<K, V>>, left: Option<NodeId>, right: Option<NodeId>) -> Option<NodeId> {
+    match left {
+        None => right,
+        Some(left) => {
+            let (rest, last) = split_last(nodes, left);
+            Some(join(nodes, rest, last, right))
         }
-        
-    } else {
-        node
     }
 }
 
+// Frees every node of a subtree
+fn free_subtree<K, V>(nodes: &mut Arena<Node<K, V>>, node: Option<NodeId>) {
+    let mut stack: Vec<NodeId> = node.into_iter().collect();
+    while let Some(node) = stack.pop() {
+        let freed = nodes.free(node);
+        stack.extend(freed.left);
+        stack.extend(freed.right);
+    }
+}
+
+// Moves a subtree from one arena into another and returns its new root, heights and sizes come along unchanged
+fn adopt<K, V>(nodes: &mut Arena<Node<K, V>>, other: &mut Arena<Node<K, V>>, node: Option<NodeId>) -> Option<NodeId> {
+    let mut moved = other.free(node?);
+    let (left, right) = (moved.left.take(), moved.right.take());
+    moved.left = adopt(nodes, other, left);
+    moved.right = adopt(nodes, other, right);
+    Some(nodes.alloc(moved))
+}
+
+// What the set operations get back from splitting one map at the root of the other
+struct SplitAtRoot {
+    a_left: Option<NodeId>,
+    found: Option<NodeId>,
+    a_right: Option<NodeId>,
+    pivot: NodeId,
+    b_left: Option<NodeId>,
+    b_right: Option<NodeId>,
+}
+
+// An ordered map kept balanced with the same rotations as AVLTree, which is just an AVLMap without values.
+// The nodes live in an Arena owned by the map and point at each other by NodeId, so the references the map
+// hands out (get, get_mut, entries, iterators) are plain borrows of the arena.
 #[derive(Debug)]
-pub struct AVLTree<T: std::cmp::Ord> {
-    root: Option<Rc<RefCell<Node<T>>>>,
+pub struct AVLMap<K, V> {
+    nodes: Arena<Node<K, V>>,
+    root: Option<NodeId>,
+    // told about every insert, remove and rotation, see set_observer
+    observer: Observer<AVLMap<K, V>, K>,
 }
 
-impl<T: std::cmp::Ord + Clone + Default + std::fmt::Debug> AVLTree<T> {
+impl<K: Ord, V> AVLMap<K, V> {
     pub fn new() -> Self {
-        AVLTree { root: None }
+        AVLMap {
+            nodes: Arena::new(),
+            root: None,
+            observer: Observer::new(),
+        }
     }
 
-    pub fn contains(&self, data: T) -> bool {
-        let data_node = Node::new(Some(data));
-        Self::contains_recursive(&self.root, data_node)
-    }
-
-    fn contains_recursive(root: &Option<Rc<RefCell<Node<T>>>>, data1: Rc<RefCell<Node<T>>>) -> bool {
-        match root {
-            Some(node) => {
-                let node_data = node.borrow().data.clone();
-                let data_data = data1.borrow().data.clone();
-                if node_data == data_data {
-                    true
-                } else if node_data > data_data {
-                    Self::contains_recursive(&node.borrow().left, data1)
-                } else {
-                    Self::contains_recursive(&node.borrow().right, data1)
-                }
+    // Walks down from the root comparing keys until the node is found
+    fn find_node(&self, key: &K) -> Option<NodeId> {
+        let mut current = self.root;
+        while let Some(node) = current {
+            let node_ref = &self.nodes[node];
+            current = match key.cmp(&node_ref.key) {
+                std::cmp::Ordering::Equal => return Some(node),
+                std::cmp::Ordering::Less => node_ref.left,
+                std::cmp::Ordering::Greater => node_ref.right,
+            };
+        }
+        None
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.find_node(key).is_some()
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.find_node(key).map(|node| self.node_value(node))
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let node = self.find_node(key)?;
+        Some(self.node_value_mut(node))
+    }
+
+    // Inserts the key with its value. If the key was already in the map the value is replaced and the old
+    // one is returned, the key itself is left untouched.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.entry(key) {
+            Entry::Occupied(mut entry) => Some(entry.insert(value)),
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+                None
+            }
+        }
+    }
+
+    // Hangs a new node under the root and rebalances on the way back up, returns the new node.
+    // Callers make sure the key is not in the map yet.
+    fn insert_node(&mut self, key: K, value: V) -> NodeId {
+        let node = self.nodes.alloc(Node::new(key, value));
+        match self.root {
+            None => {
+                self.root = Some(node);
+                self.notify(Step::Insert(node));
             }
-            None => false,
+            Some(root) => self.root = Some(self.insert_recursive(None, root, node)),
         }
+        node
     }
 
-    // This is the insert function that main will call. If the root is none, then the node is created as the root
-    pub fn insert(&mut self, data: T) {
-        let node = Node::new(Some(data));
-        self.root = match self.root.take() {
-            None => Some(node.clone()),
-            Some(root) => Some(Self::insert_recursive(root, node))
+    fn insert_recursive(&mut self, parent: Option<NodeId>, root: NodeId, node: NodeId) -> NodeId {
+        // Travese left if the key is smaller than the current root, otherwise traverse right
+        let go_left = self.nodes[node].key < self.nodes[root].key;
+        let child = if go_left { self.nodes[root].left } else { self.nodes[root].right };
+        // An empty spot is where the new node goes
+        let (child, placed) = match child {
+            None => (node, true),
+            Some(child) => (self.insert_recursive(Some(root), child, node), false),
         };
+        if go_left {
+            self.nodes[root].left = Some(child);
+        } else {
+            self.nodes[root].right = Some(child);
+        }
+        if placed {
+            self.notify(Step::Insert(node));
+        }
+        update_height(&mut self.nodes, root);
+        self.rebalance_at(parent, root)
     }
 
-    fn insert_recursive(root: Rc<RefCell<Node<T>>>, node: Rc<RefCell<Node<T>>>) -> Rc<RefCell<Node<T>>> {
-        let root_data = root.borrow().data.clone();
-        let node_data = node.borrow().data.clone();
-        // Travese left if the data is smaller than the current root
-        if node_data < root_data {
-             root.replace_with(|old| 
-                match old {
-                    Node{data, height, left: None, right: Some(y)} => {
-                        Node {
-                            data: data.clone(),
-                            height: height.clone(),
-                            left: Some(node.clone()),
-                            right: Some(Rc::clone(y)),
-                        }
-                    },
-                    Node{data, height, left: Some(x), right: Some(y)} => {
-                        Node {
-                            data: data.clone(),
-                            height: height.clone(),
-                            left: Some(Self::insert_recursive(x.clone(), node.clone())),
-                            right: Some(Rc::clone(y)),
-                        }
-                    },
-                    Node{data, height, left: None, right: None} => {
-                        Node {
-                            data: data.clone(),
-                            height: height.clone(),
-                            left: Some(node.clone()),
-                            right: None,
-                        }
-                    },
-                    Node{data, height, left: Some(x), right: None} => {
-                        Node {
-                            data: data.clone(),
-                            height: height.clone(),
-                            left: Some(Self::insert_recursive(x.clone(), node.clone())),
-                            right: None,
-                        }
-                    },
-                }
-             );  
-        // Travese right if the data is larger than the current root
-        } else if node_data > root_data {
-            root.replace_with(|old| 
-                match old {
-                    Node{data, height, left: Some(y), right: None} => {
-                        Node {
-                            data: data.clone(),
-                            height: height.clone(),
-                            left: Some(Rc::clone(y)),
-                            right: Some(node.clone()),
-                        }
-                    },
-                    Node{data, height, left: Some(y), right: Some(x)} => {
-                        Node {
-                            data: data.clone(),
-                            height: height.clone(),
-                            left: Some(Rc::clone(y)),
-                            right: Some(Self::insert_recursive(x.clone(), node.clone())),
-                        }
-                    },
-                    Node{data, height, left: None, right: None} => {
-                        Node {
-                            data: data.clone(),
-                            height: height.clone(),
-                            left: None,
-                            right: Some(node.clone()),
-                        }
-                    },
-                    Node{data, height, left: None, right: Some(x)} => {
-                        Node {
-                            data: data.clone(),
-                            height: height.clone(),
-                            left: None,
-                            right: Some(Self::insert_recursive(x.clone(), node.clone())),
+    // Removes the key from the map and hands back its value
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove_entry(key).map(|(_, value)| value)
+    }
+
+    pub fn remove_entry(&mut self, key: &K) -> Option<(K, V)> {
+        self.remove_located(&|nodes: &Arena<Node<K, V>>, node: NodeId| key.cmp(&nodes[node].key))
+    }
+
+    // Removes the node that `locate` steers towards, `locate` tells which side of a node the wanted one is on
+    fn remove_located<F>(&mut self, locate: &F) -> Option<(K, V)>
+    where
+        F: Fn(&Arena<Node<K, V>>, NodeId) -> std::cmp::Ordering,
+    {
+        let mut removed = None;
+        if let Some(root) = self.root {
+            self.root = self.remove_recursive(None, root, locate, &mut removed);
+        }
+        // the removed node is only freed now, so that the observer can still read its key
+        removed.map(|node| self.nodes.free(node).into_entry())
+    }
+
+    fn remove_recursive<F>(
+        &mut self,
+        parent: Option<NodeId>,
+        root: NodeId,
+        locate: &F,
+        removed: &mut Option<NodeId>,
+    ) -> Option<NodeId>
+    where
+        F: Fn(&Arena<Node<K, V>>, NodeId) -> std::cmp::Ordering,
+    {
+        // Check which side the deleted node is going to be on
+        let ordering = locate(&self.nodes, root);
+        match ordering {
+            // Key is smaller than current node, so travers left
+            std::cmp::Ordering::Less => {
+                let left = self.nodes[root].left;
+                self.nodes[root].left = left.and_then(|left| self.remove_recursive(Some(root), left, locate, removed));
+            }
+            // Key is larger than current node so traverse right
+            std::cmp::Ordering::Greater => {
+                let right = self.nodes[root].right;
+                self.nodes[root].right = right.and_then(|right| self.remove_recursive(Some(root), right, locate, removed));
+            }
+            // Node to be deleted found on the current node
+            std::cmp::Ordering::Equal => {
+                *removed = Some(root);
+                let replacement = match (self.nodes[root].left, self.nodes[root].right) {
+                    // Node has no children
+                    (None, None) => None,
+                    // Node has only one child, which just moves up
+                    (Some(child), None) | (None, Some(child)) => Some(child),
+                    // Node has both left and right children
+                    // The current node will be replaced with the lowest value node that is on the right side.
+                    // Note: Other versions of AVL tree will find the highest value node on the left side. The result may differ because of this
+                    (Some(left), Some(right)) => {
+                        let mut min = None;
+                        let new_right = self.remove_min(root, right, &mut min);
+                        let min = min.expect("a non-empty subtree has a minimum");
+                        self.nodes[min].left = Some(left);
+                        self.nodes[min].right = new_right;
+                        update_height(&mut self.nodes, min);
+                        if self.observer.is_set() {
+                            self.relink(parent, root, Some(min));
+                            self.notify(Step::Replace { removed: root, by: min });
+                            self.notify(Step::Remove(root));
                         }
-                    },
+                        return Some(self.rebalance_at(parent, min));
+                    }
+                };
+                if self.observer.is_set() {
+                    self.relink(parent, root, replacement);
+                    self.notify(Step::Remove(root));
                 }
-            );
+                return replacement;
+            }
         }
-        // If the value inserted already exists in the tree then nothing happens
-        update_height(&Some(root.clone()));
-        rebalance(root)
+        update_height(&mut self.nodes, root);
+        Some(self.rebalance_at(parent, root))
     }
 
-    // This will be the function that main calls to delete a Node
-    pub fn delete(&mut self, data: T) {
-        let node = Node::new(Some(data));
-        if let Some(root) = self.root.take() {
-            let new_root = Self::delete_recursive(Rc::clone(&root), node);
-            // Deletes the root if the only value is None, this will happen if the root is the only node in the tree and wants to be deleted
-            self.root = if new_root.borrow().data.is_some() {
-                Some(new_root)
+    // Unlinks the smallest node below the given one into `min` and returns the rebalanced subtree
+    fn remove_min(&mut self, parent: NodeId, node: NodeId, min: &mut Option<NodeId>) -> Option<NodeId> {
+        match self.nodes[node].left {
+            None => {
+                let right = self.nodes[node].right.take();
+                *min = Some(node);
+                right
+            }
+            Some(left) => {
+                let new_left = self.remove_min(node, left, min);
+                self.nodes[node].left = new_left;
+                update_height(&mut self.nodes, node);
+                Some(self.rebalance_at(Some(parent), node))
+            }
+        }
+    }
+
+    // ---------------------------------------- Observer ---------------------------------------------
+    // Calls the observer after every step of every insert and remove from now on, with the step and the map
+    // right after it: the new node being hung in, the node being taken out and the one taking its place, and
+    // each rotation case followed by its rotations, one at a time. Heights and sizes above the step are only
+    // brought up to date on the way back up, so until the operation returns len and the iterators can be off by
+    // one while walking the nodes (write_structure, to_dot) shows them all. Replaces the observer set before.
+    pub fn set_observer<F>(&mut self, observer: F)
+    where
+        F: FnMut(Step<&K>, &Self) + Send + Sync + 'static,
+    {
+        self.observer.set(observer);
+    }
+
+    pub fn clear_observer(&mut self) {
+        self.observer.clear();
+    }
+
+    fn notify(&mut self, step: Step<NodeId>) {
+        // the observer is taken out while it runs, so that it can look at the map
+        if let Some(mut observer) = self.observer.take() {
+            observer(step.map(|node| &self.nodes[node].key), self);
+            self.observer.put_back(observer);
+        }
+    }
+
+    // Rebalances the subtree at the node below `parent` (None for the root) and returns its new top.
+    // The recursion normally links the new top in on its way back up, but with an observer every rotation is
+    // linked in right away, so that the observer sees a whole tree after each one.
+    fn rebalance_at(&mut self, parent: Option<NodeId>, node: NodeId) -> NodeId {
+        if !self.observer.is_set() {
+            return rebalance(&mut self.nodes, node);
+        }
+        let case = match imbalance(&self.nodes, node) {
+            Some(case) => case,
+            None => return node,
+        };
+        self.notify(Step::Case { name: case.name(), at: node });
+        match case {
+            Imbalance::LeftLeft => self.rotate_at(parent, node, false),
+            Imbalance::LeftRight => {
+                let left = self.nodes[node].left.unwrap();
+                self.rotate_at(Some(node), left, true);
+                self.rotate_at(parent, node, false)
+            }
+            Imbalance::RightRight => self.rotate_at(parent, node, true),
+            Imbalance::RightLeft => {
+                let right = self.nodes[node].right.unwrap();
+                self.rotate_at(Some(node), right, false);
+                self.rotate_at(parent, node, true)
+            }
+        }
+    }
+
+    // One rotation of rebalance_at, linked in below `parent` and shown to the observer
+    fn rotate_at(&mut self, parent: Option<NodeId>, node: NodeId, left: bool) -> NodeId {
+        let top = if left { rotate_left(&mut self.nodes, node) } else { rotate_right(&mut self.nodes, node) };
+        self.relink(parent, node, Some(top));
+        self.notify(if left { Step::RotateLeft(node) } else { Step::RotateRight(node) });
+        top
+    }
+
+    // Puts `new` in the place of `old` below `parent`, or at the root when there is no parent
+    fn relink(&mut self, parent: Option<NodeId>, old: NodeId, new: Option<NodeId>) {
+        match parent {
+            None => self.root = new,
+            Some(parent) if self.nodes[parent].left == Some(old) => self.nodes[parent].left = new,
+            Some(parent) => self.nodes[parent].right = new,
+        }
+    }
+
+    // Gets the entry for the key so it can be looked at or filled in with a single search
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+        map::entry(self, key)
+    }
+
+    // ---------------------------------------- Navigation ------------------------------------------
+    // Iterates over the entries whose keys fall in the range, from either end
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Range<'_, K, V> {
+        Range::new(&self.nodes, self.root, range.start_bound(), range.end_bound())
+    }
+
+    // Entry with the smallest key
+    pub fn first(&self) -> Option<(&K, &V)> {
+        let node = Self::find_min(&self.nodes, self.root?);
+        Some((self.node_key(node), self.node_value(node)))
+    }
+
+    // Entry with the largest key
+    pub fn last(&self) -> Option<(&K, &V)> {
+        let node = Self::find_max(&self.nodes, self.root?);
+        Some((self.node_key(node), self.node_value(node)))
+    }
+
+    pub fn pop_first(&mut self) -> Option<(K, V)> {
+        // the smallest node is the one with nothing on its left
+        self.remove_located(&|nodes: &Arena<Node<K, V>>, node: NodeId| {
+            if nodes[node].left.is_none() {
+                std::cmp::Ordering::Equal
             } else {
-                None
+                std::cmp::Ordering::Less
+            }
+        })
+    }
+
+    pub fn pop_last(&mut self) -> Option<(K, V)> {
+        // the largest node is the one with nothing on its right
+        self.remove_located(&|nodes: &Arena<Node<K, V>>, node: NodeId| {
+            if nodes[node].right.is_none() {
+                std::cmp::Ordering::Equal
+            } else {
+                std::cmp::Ordering::Greater
+            }
+        })
+    }
+
+    // Entry with the largest key that is less than or equal to the given one
+    pub fn floor(&self, key: &K) -> Option<(&K, &V)> {
+        self.range(..=key).next_back()
+    }
+
+    // Entry with the smallest key that is greater than or equal to the given one
+    pub fn ceiling(&self, key: &K) -> Option<(&K, &V)> {
+        self.range(key..).next()
+    }
+
+    // Entry with the largest key that is strictly less than the given one
+    pub fn predecessor(&self, key: &K) -> Option<(&K, &V)> {
+        self.range(..key).next_back()
+    }
+
+    // Entry with the smallest key that is strictly greater than the given one
+    pub fn successor(&self, key: &K) -> Option<(&K, &V)> {
+        self.range((Bound::Excluded(key), Bound::Unbounded)).next()
+    }
+
+    // ---------------------------------------- Order statistics ------------------------------------
+    // Number of keys in the map that are strictly less than the given one
+    pub fn rank(&self, key: &K) -> usize {
+        let mut rank = 0;
+        let mut current = self.root;
+        while let Some(node) = current {
+            let node_ref = &self.nodes[node];
+            current = if node_ref.key < *key {
+                // everything on the left and the node itself come before the key
+                rank += size(&self.nodes, node_ref.left) + 1;
+                node_ref.right
+            } else {
+                node_ref.left
             };
         }
+        rank
     }
 
-    fn delete_recursive(root: Rc<RefCell<Node<T>>>, node: Rc<RefCell<Node<T>>>) -> Rc<RefCell<Node<T>>> {
-        let root_data = root.borrow().data.clone();
-        let node_data = node.borrow().data.clone();
-        // Check which side the deleted node is going to be on
-        // Data is smaller than current node, so travers left
-        if node_data < root_data {
-            // These will continuously traverse the tree if there continues to be children on the left, until the wanted data is found
-            root.replace_with(|old| 
-                match old {
-                    Node{data, height, left: Some(x), right: Some(y)} => {
-                        let temp = Self::delete_recursive(x.clone(), node.clone());
-                        if temp.borrow().data.clone().is_none(){
-                            Node {
-                                data: data.clone(),
-                                height: height.clone(),
-                                left: None,
-                                right: Some(Rc::clone(y)),
-                            } 
-                        } else {
-                            Node {
-                                data: data.clone(),
-                                height: height.clone(),
-                                left: Some(temp),
-                                right: Some(Rc::clone(y)),
-                            } 
-                        }
-                    },
-                    Node{data, height, left: Some(x), right: None} => {
-                        let temp = Self::delete_recursive(x.clone(), node.clone());
-                        if temp.borrow().data.clone().is_none(){
-                            Node {
-                                data: data.clone(),
-                                height: height.clone(),
-                                left: None,
-                                right: None,
-                            } 
-                        } else {
-                            Node {
-                                data: data.clone(),
-                                height: height.clone(),
-                                left: Some(temp),
-                                right: None,
-                            } 
-                        }
-                    },
-                    Node{data, height, left: None, right: None} => {
-                        Node {
-                            data: data.clone(),
-                            height: height.clone(),
-                            left: None,
-                            right: None,
-                        }
-                    },
-                    Node{data, height, left: None, right: Some(y)} => {
-                        Node {
-                            data: data.clone(),
-                            height: height.clone(),
-                            left: None,
-                            right: Some(Rc::clone(y)),
-                        }
-                    },
-                }
-             ); 
-             update_height(&Some(root.clone()));
-             rebalance(root.clone())
-        // Data is larger than current node so traverse right
-        } else if node_data > root_data {
-            root.replace_with(|old|
-                // These will continuously traverse the tree if there continues to be children on the right, until the wanted data is found
-                match old {
-                    Node{data, height, left: Some(x), right: Some(y)} => {
-                        let temp = Self::delete_recursive(y.clone(), node.clone());
-                        if temp.borrow().data.clone().is_none(){
-                            Node {
-                                data: data.clone(),
-                                height: height.clone(),
-                                left: Some(Rc::clone(x)),
-                                right: None,
-                            } 
-                        } else {
-                            Node {
-                                data: data.clone(),
-                                height: height.clone(),
-                                left: Some(Rc::clone(x)),
-                                right: Some(temp),
-                            } 
-                        }
-                    },
-                    Node{data, height, left: None, right: Some(y)} => {
-                        let temp = Self::delete_recursive(y.clone(), node.clone());
-                        if temp.borrow().data.clone().is_none(){
-                            Node {
-                                data: data.clone(),
-                                height: height.clone(),
-                                left: None,
-                                right: None,
-                            } 
-                        } else {
-                            Node {
-                                data: data.clone(),
-                                height: height.clone(),
-                                left: None,
-                                right: Some(temp),
-                            } 
-                        }
-                    },
-                    Node{data, height, left: None, right: None} => {
-                        Node {
-                            data: data.clone(),
-                            height: height.clone(),
-                            left: None,
-                            right: None,
-                        }
-                    },
-                    Node{data, height, left: Some(x), right: None} => {
-                        Node {
-                            data: data.clone(),
-                            height: height.clone(),
-                            left: Some(Rc::clone(x)),
-                            right: None,
-                        }
-                    },
+    // Entry with the index-th smallest key, counting from 0
+    pub fn select(&self, index: usize) -> Option<(&K, &V)> {
+        let mut index = index;
+        let mut current = self.root;
+        while let Some(node) = current {
+            let left_size = size(&self.nodes, self.nodes[node].left);
+            current = match index.cmp(&left_size) {
+                std::cmp::Ordering::Equal => return Some((self.node_key(node), self.node_value(node))),
+                std::cmp::Ordering::Less => self.nodes[node].left,
+                std::cmp::Ordering::Greater => {
+                    index -= left_size + 1;
+                    self.nodes[node].right
                 }
-             ); 
-             update_height(&Some(root.clone()));
-             rebalance(root.clone())
+            };
+        }
+        None
+    }
+
+    // ---------------------------------------- Bulk construction -----------------------------------
+    // Builds a map out of entries that are already sorted by key in O(n), without a single rotation.
+    // Panics if the keys are not strictly ascending.
+    pub fn from_sorted_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let entries: Vec<(K, V)> = iter.into_iter().collect();
+        assert!(entries.windows(2).all(|pair| pair[0].0 < pair[1].0), "from_sorted_iter needs keys in strictly ascending order");
+        Self::from_sorted_vec(entries)
+    }
+
+    fn from_sorted_vec(entries: Vec<(K, V)>) -> Self {
+        let mut map = AVLMap::new();
+        let len = entries.len();
+        map.root = Self::build_sorted(&mut map.nodes, &mut entries.into_iter(), len);
+        map
+    }
+
+    // Builds a subtree out of the next `len` entries and returns its root. Both halves get the same number
+    // of entries give or take one, so their heights never differ by more than one.
+    fn build_sorted<I: Iterator<Item = (K, V)>>(nodes: &mut Arena<Node<K, V>>, entries: &mut I, len: usize) -> Option<NodeId> {
+        if len == 0 {
+            return None;
+        }
+        let left_len = (len - 1) / 2;
+        let left = Self::build_sorted(nodes, entries, left_len);
+        let (key, value) = entries.next().expect("there are len entries left");
+        let node = nodes.alloc(Node::new(key, value));
+        let right = Self::build_sorted(nodes, entries, len - 1 - left_len);
+        nodes[node].left = left;
+        nodes[node].right = right;
+        update_height(nodes, node);
+        Some(node)
+    }
+
+    // ---------------------------------------- Split & join ----------------------------------------
+    // Splits the map at the key, the left map gets the keys below it and the right map the rest. Cutting
+    // the tree apart takes O(log n), but the smaller of the two halves then moves to an arena of its own,
+    // so a split costs O(log n + min(|left|, |right|)) in all.
+    pub fn split(mut self, key: &K) -> (Self, Self) {
+        let root = self.root.take();
+        let (left, found, right) =
+            Self::split_subtree(&mut self.nodes, root, &|nodes: &Arena<Node<K, V>>, node: NodeId| key.cmp(&nodes[node].key));
+        // the key itself goes to the right map
+        let right = match found {
+            Some(found) => Some(join(&mut self.nodes, None, found, right)),
+            None => right,
+        };
+        self.separate(left, right)
+    }
 
+    // Puts two maps back together around a new entry. Every key in `left` has to be smaller than the key and
+    // every key in `right` larger. The smaller map moves into the arena of the larger one.
+    pub fn join(left: Self, key: K, value: V, right: Self) -> Self {
+        assert!(
+            left.last().is_none_or(|(last, _)| *last < key) && right.first().is_none_or(|(first, _)| key < *first),
+            "join needs the keys of left below the key and the keys of right above it"
+        );
+        let (mut map, left, right) = Self::combine(left, right);
+        let node = map.nodes.alloc(Node::new(key, value));
+        map.root = Some(join(&mut map.nodes, left, node, right));
+        map
+    }
+
+    // Splits a subtree around the node `locate` steers towards. Returns the part before it, the node itself if
+    // it is there, and the part after it.
+    fn split_subtree<F>(nodes: &mut Arena<Node<K, V>>, root: Option<NodeId>, locate: &F) -> (Option<NodeId>, Option<NodeId>, Option<NodeId>)
+    where
+        F: Fn(&Arena<Node<K, V>>, NodeId) -> std::cmp::Ordering,
+    {
+        let root = match root {
+            Some(root) => root,
+            None => return (None, None, None),
+        };
+        let (left, right) = (nodes[root].left.take(), nodes[root].right.take());
+        match locate(nodes, root) {
+            std::cmp::Ordering::Equal => (left, Some(root), right),
+            std::cmp::Ordering::Less => {
+                let (before, found, after) = Self::split_subtree(nodes, left, locate);
+                (before, found, Some(join(nodes, after, root, right)))
+            }
+            std::cmp::Ordering::Greater => {
+                let (before, found, after) = Self::split_subtree(nodes, right, locate);
+                (Some(join(nodes, left, root, before)), found, after)
+            }
+        }
+    }
+
+    // Puts the nodes of both maps into one arena, the smaller map moves into the arena of the larger one.
+    // Returns the map owning that arena, with its root taken out, and the roots of both maps in it.
+    fn combine(a: Self, b: Self) -> (Self, Option<NodeId>, Option<NodeId>) {
+        let a_is_larger = a.len() >= b.len();
+        let (mut larger, mut smaller) = if a_is_larger { (a, b) } else { (b, a) };
+        let larger_root = larger.root.take();
+        let smaller_root = adopt(&mut larger.nodes, &mut smaller.nodes, smaller.root);
+        if a_is_larger {
+            (larger, larger_root, smaller_root)
         } else {
-            // Node to be deleted found on the current node
-            // Now have to replace the node with a new node
-            // The current node will be replaced with the lowest value node that is on the right side. Therefore find_min always looks at the right side of the Tree
-            // Note: Other versions of AVL tree will find the highest value node on the left side. The result may differ because of this
-            let _ = &root.replace_with(|old| 
-                match old {
-                    // Case 1: Node has no children
-                    // The data is set to None so that the parent can see its child has no value and delete it
-                    Node{data, height, left: None, right: None} => {
-                        Node {
-                            data: None,
-                            height: 0,
-                            left: None,
-                            right: None,
-                        }
-                    },
-                    // Case 2: Node has both left and right children
-                    Node{data, height, left: Some(x), right: Some(y)} => {
-                        let min = Self::find_min(Rc::clone(y));
-                        // If the child that is being taken has no children then copy the value and set child to None
-                        if y.borrow().left.clone().is_none() && y.borrow().right.clone().is_none(){
-                            let temp = Node {
-                                data: min.borrow().data.clone(),
-                                height: height.clone(),
-                                left: Some(Rc::clone(x)),
-                                right: None,
-                            }; temp
-                        // Otherwise, copy the value and delete the other child
-                        } else {
-                            let temp = Node {
-                                data: min.borrow().data.clone(),
-                                height: height.clone(),
-                                left: Some(Rc::clone(x)),
-                                right: Some(Rc::clone(y)),
-                            };
-                            Self::delete_recursive(y.clone(), min);
-                            temp
-                        }
-                    },
-                    // Case 3: Node has no left child
-                    Node{data, height, left: None, right: Some(y)} => {
-                        let min = Self::find_min(Rc::clone(y));
-                        let result = Node {
-                            data: min.borrow().data.clone(),
-                            height: height.clone(),
-                            left: None,
-                            right: None,
-                        }; result
-                    },
-                    // Case 4: Node has no right child
-                    Node{data, height, left: Some(y), right: None} => {
-                        let min = Self::find_min(Rc::clone(y));
-                        let result = Node {
-                            data: min.borrow().data.clone(),
-                            height: height.clone(),
-                            left: None,
-                            right: None,
-                        }; result
-                    }
-                }
-             );
-             update_height(&Some(root.clone()));
-             rebalance(root.clone())
+            (larger, smaller_root, larger_root)
+        }
+    }
+
+    // Splits the map into two maps made of the two subtrees, the smaller one moves to a new arena
+    fn separate(mut self, left: Option<NodeId>, right: Option<NodeId>) -> (Self, Self) {
+        let mut moved = AVLMap::new();
+        if size(&self.nodes, left) <= size(&self.nodes, right) {
+            moved.root = adopt(&mut moved.nodes, &mut self.nodes, left);
+            self.root = right;
+            (moved, self)
+        } else {
+            moved.root = adopt(&mut moved.nodes, &mut self.nodes, right);
+            self.root = left;
+            (self, moved)
+        }
+    }
+
+    // ---------------------------------------- Set operations --------------------------------------
+    // All four take one map apart around its root and split the other at that key, which comes to
+    // O(m log(n/m + 1)) for maps of sizes m <= n. Entries that do not make it into the result are dropped.
+    // Where both maps have a key the entry of `self` is kept.
+
+    // Entries whose key is in either map
+    pub fn union(self, other: Self) -> Self {
+        let (mut map, a, b) = Self::combine(self, other);
+        map.root = Self::union_subtrees(&mut map.nodes, a, b);
+        map
+    }
+
+    // Entries whose key is in both maps
+    pub fn intersection(self, other: Self) -> Self {
+        let (mut map, a, b) = Self::combine(self, other);
+        map.root = Self::intersection_subtrees(&mut map.nodes, a, b);
+        map
+    }
+
+    // Entries of `self` whose key is not in `other`
+    pub fn difference(self, other: Self) -> Self {
+        let (mut map, a, b) = Self::combine(self, other);
+        map.root = Self::difference_subtrees(&mut map.nodes, a, b);
+        map
+    }
+
+    // Entries whose key is in exactly one of the maps
+    pub fn symmetric_difference(self, other: Self) -> Self {
+        let (mut map, a, b) = Self::combine(self, other);
+        map.root = Self::symmetric_difference_subtrees(&mut map.nodes, a, b);
+        map
+    }
+
+    // Takes `b` apart at its root and splits `a` at the root's key. Returns the two halves of `a`, the node of
+    // `a` with that key if there is one, the root of `b` and the two halves of `b`.
+    fn split_at_root(nodes: &mut Arena<Node<K, V>>, a: Option<NodeId>, b: NodeId) -> SplitAtRoot {
+        let (b_left, b_right) = (nodes[b].left.take(), nodes[b].right.take());
+        let (a_left, found, a_right) =
+            Self::split_subtree(nodes, a, &|nodes: &Arena<Node<K, V>>, node: NodeId| nodes[b].key.cmp(&nodes[node].key));
+        SplitAtRoot { a_left, found, a_right, pivot: b, b_left, b_right }
+    }
+
+    fn union_subtrees(nodes: &mut Arena<Node<K, V>>, a: Option<NodeId>, b: Option<NodeId>) -> Option<NodeId> {
+        let b = match (a, b) {
+            (_, None) => return a,
+            (None, _) => return b,
+            (Some(_), Some(b)) => b,
+        };
+        let split = Self::split_at_root(nodes, a, b);
+        let left = Self::union_subtrees(nodes, split.a_left, split.b_left);
+        let right = Self::union_subtrees(nodes, split.a_right, split.b_right);
+        let node = match split.found {
+            Some(found) => {
+                nodes.free(split.pivot);
+                found
+            }
+            None => split.pivot,
+        };
+        Some(join(nodes, left, node, right))
+    }
+
+    fn intersection_subtrees(nodes: &mut Arena<Node<K, V>>, a: Option<NodeId>, b: Option<NodeId>) -> Option<NodeId> {
+        let b = match (a, b) {
+            (Some(_), Some(b)) => b,
+            _ => {
+                free_subtree(nodes, a);
+                free_subtree(nodes, b);
+                return None;
+            }
+        };
+        let split = Self::split_at_root(nodes, a, b);
+        nodes.free(split.pivot);
+        let left = Self::intersection_subtrees(nodes, split.a_left, split.b_left);
+        let right = Self::intersection_subtrees(nodes, split.a_right, split.b_right);
+        match split.found {
+            Some(found) => Some(join(nodes, left, found, right)),
+            None => join_pair(nodes, left, right),
+        }
+    }
 
+    fn difference_subtrees(nodes: &mut Arena<Node<K, V>>, a: Option<NodeId>, b: Option<NodeId>) -> Option<NodeId> {
+        let b = match (a, b) {
+            (Some(_), Some(b)) => b,
+            _ => {
+                free_subtree(nodes, b);
+                return a;
+            }
+        };
+        let split = Self::split_at_root(nodes, a, b);
+        nodes.free(split.pivot);
+        free_subtree(nodes, split.found);
+        let left = Self::difference_subtrees(nodes, split.a_left, split.b_left);
+        let right = Self::difference_subtrees(nodes, split.a_right, split.b_right);
+        join_pair(nodes, left, right)
+    }
+
+    fn symmetric_difference_subtrees(nodes: &mut Arena<Node<K, V>>, a: Option<NodeId>, b: Option<NodeId>) -> Option<NodeId> {
+        let b = match (a, b) {
+            (_, None) => return a,
+            (None, _) => return b,
+            (Some(_), Some(b)) => b,
+        };
+        let split = Self::split_at_root(nodes, a, b);
+        let left = Self::symmetric_difference_subtrees(nodes, split.a_left, split.b_left);
+        let right = Self::symmetric_difference_subtrees(nodes, split.a_right, split.b_right);
+        match split.found {
+            Some(found) => {
+                nodes.free(split.pivot);
+                nodes.free(found);
+                join_pair(nodes, left, right)
+            }
+            None => Some(join(nodes, left, split.pivot, right)),
         }
     }
 
     // Will find the min from a given node by continually traversing left
-    fn find_min(node: Rc<RefCell<Node<T>>>) -> Rc<RefCell<Node<T>>> {
-        if let Some(left) = node.borrow().left.clone() {
-            return Self::find_min(left);
+    fn find_min(nodes: &Arena<Node<K, V>>, node: NodeId) -> NodeId {
+        if let Some(left) = nodes[node].left {
+            return Self::find_min(nodes, left);
+        }
+        node
+    }
+
+    // Will find the max from a given node by continually traversing right
+    fn find_max(nodes: &Arena<Node<K, V>>, node: NodeId) -> NodeId {
+        if let Some(right) = nodes[node].right {
+            return Self::find_max(nodes, right);
         }
         node
     }
 
+    fn node_key(&self, node: NodeId) -> &K {
+        &self.nodes[node].key
+    }
+
+    fn node_value(&self, node: NodeId) -> &V {
+        &self.nodes[node].value
+    }
+
+    fn node_value_mut(&mut self, node: NodeId) -> &mut V {
+        &mut self.nodes[node].value
+    }
 
     // Checks if the root is none, and if it is then the tree is empty
     pub fn is_empty(&self) -> bool {
         self.root.is_none()
     }
 
+    // Number of entries, read off the size of the root
+    pub fn len(&self) -> usize {
+        size(&self.nodes, self.root)
+    }
+
     // Returns the height of the root
     pub fn get_height(&self) -> i32 {
-        self.root.as_ref().map_or(0, |n| n.borrow().height)
+        height(&self.nodes, self.root)
     }
 
     // Counts the number of leaves by checking if a node has no children and uses recursion
     pub fn count_leaves(&self) -> usize {
-        fn count_leaves_helper<T: std::cmp::Ord + Clone + Default + std::fmt::Debug>(node: &Option<Rc<RefCell<Node<T>>>>) -> usize {
+        fn count_leaves_helper<K, V>(nodes: &Arena<Node<K, V>>, node: Option<NodeId>) -> usize {
             match node {
                 None => 0,
                 Some(n) => {
-                    let node_borrow = n.borrow();
-                    if node_borrow.left.is_none() && node_borrow.right.is_none() {
+                    let node_ref = &nodes[n];
+                    if node_ref.left.is_none() && node_ref.right.is_none() {
                         1
                     } else {
-                        count_leaves_helper(&node_borrow.left) + count_leaves_helper(&node_borrow.right)
+                        count_leaves_helper(nodes, node_ref.left) + count_leaves_helper(nodes, node_ref.right)
                     }
                 }
             }
         }
 
-        count_leaves_helper(&self.root)
+        count_leaves_helper(&self.nodes, self.root)
     }
 
-    // This is the function that is called by the main program, then uses the resursive function to get the rest of the nodes in order
-    pub fn in_order_traversal(&self){
-        if let Some(node) = &self.root {
-            let node_borrow = node.borrow();
-            Self::in_order_traversal_recursive(node_borrow.left.as_ref());
-            println!("{:?}", node_borrow.data.clone().unwrap());
-            Self::in_order_traversal_recursive(node_borrow.right.as_ref());
-        }
+    // Node count, leaf depths, path length and how many nodes lean which way, see TreeStats
+    pub fn stats(&self) -> TreeStats {
+        let mut balance_factors = BTreeMap::new();
+        let mut stats = stats::collect(self.root, |node| {
+            *balance_factors.entry(balance_factor(&self.nodes, node)).or_insert(0) += 1;
+            [self.nodes[node].left, self.nodes[node].right].into_iter().flatten()
+        });
+        stats.balance_factors = Some(balance_factors);
+        stats
     }
+}
 
-    // Uses recursion to print out he in order traversal of the AVL tree by traversing through the tree to the left first, then the right
-    fn in_order_traversal_recursive(node: Option<&Rc<RefCell<Node<T>>>>) {
-        if let Some(n) = node {
-            let node_borrow = n.borrow();
-            Self::in_order_traversal_recursive(node_borrow.left.as_ref());
-            println!("{:?}", node_borrow.data.clone().unwrap());
-            Self::in_order_traversal_recursive(node_borrow.right.as_ref());
-        }
+impl<K: Ord + Codec, V: Codec> AVLMap<K, V> {
+    // ---------------------------------------- Snapshots -------------------------------------------
+    // The entries in the binary snapshot format, see the snapshot module
+    pub fn to_snapshot(&self) -> Vec<u8> {
+        snapshot::encode(self.len(), self.iter())
     }
 
-    // This will be the function that main calls to print the structure of the tree
-    pub fn print_structure(&self) {
-        if let Some(n) = &self.root {
-            Self::print_node(&n.borrow().right,  0);
-            println!("{:?}", n.borrow().data.clone().unwrap());
-            Self::print_node(&n.borrow().left,  0);
-        }
+    // Rebuilds a map from a snapshot in O(n), the entries are already in order
+    pub fn from_snapshot(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        snapshot::decode(bytes).map(Self::from_sorted_vec)
     }
 
-    // This uses preorder traversal to print out the tree with structure, where the height of the tree is the widge that is used to space out the strucutre. 
-    fn print_node(node: &Option<Rc<RefCell<Node<T>>>>, node_height: i32) {
-        if let Some(n) = node {
-            Self::print_node(&n.borrow().right, node_height + 1);
-            println!("{:>width$}|-----{:?}", "", n.borrow().data.clone().unwrap(), width = ((node_height) * 7) as usize);
-            Self::print_node(&n.borrow().left, node_height + 1);
-            
-        }
+    pub fn save_to<P: AsRef<Path>>(&self, path: P) -> Result<(), SnapshotError> {
+        fs::write(path, self.to_snapshot())?;
+        Ok(())
     }
 
+    pub fn load_from<P: AsRef<Path>>(path: P) -> Result<Self, SnapshotError> {
+        Self::from_snapshot(&fs::read(path)?)
+    }
 }
 
+impl<K, V> AVLMap<K, V> {
+    // Iterates over the entries in key order, from either end
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter::new(&self.nodes, self.root, size(&self.nodes, self.root))
+    }
+
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys { inner: self.iter() }
+    }
+
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values { inner: self.iter() }
+    }
+
+    pub fn into_keys(self) -> IntoKeys<K, V> {
+        IntoKeys { inner: self.into_iter() }
+    }
+
+    pub fn into_values(self) -> IntoValues<K, V> {
+        IntoValues { inner: self.into_iter() }
+    }
+}
 
-fn main(){
+impl<K: Ord + Debug, V> AVLMap<K, V> {
+    // Checks that the map is a valid AVL tree: keys in order, cached heights and sizes, and children
+    // heights that differ by at most one. The first broken rule is reported together with the node
+    // it was found at.
+    pub fn validate(&self) -> Result<(), InvariantViolation> {
+        self.validate_node(self.root, None, None).map(|_| ())
+    }
+
+    // Checks the subtree below `node`, its keys have to stay strictly between `lower` and `upper`.
+    // Returns the height and the size of the subtree.
+    fn validate_node(
+        &self,
+        node: Option<NodeId>,
+        lower: Option<&K>,
+        upper: Option<&K>,
+    ) -> Result<(i32, usize), InvariantViolation> {
+        let node = match node {
+            Some(node) => &self.nodes[node],
+            None => return Ok((0, 0)),
+        };
+        let violation = |invariant| Err(InvariantViolation::new(invariant, &node.key));
+
+        if lower.is_some_and(|lower| node.key <= *lower) || upper.is_some_and(|upper| node.key >= *upper) {
+            return violation(Invariant::Order);
+        }
+        let (left_height, left_size) = self.validate_node(node.left, lower, Some(&node.key))?;
+        let (right_height, right_size) = self.validate_node(node.right, Some(&node.key), upper)?;
+        if (left_height - right_height).abs() > 1 {
+            return violation(Invariant::Balance);
+        }
+        let height = std::cmp::max(left_height, right_height) + 1;
+        if node.height != height {
+            return violation(Invariant::Height);
+        }
+        let size = left_size + right_size + 1;
+        if node.size != size {
+            return violation(Invariant::Size);
+        }
+        Ok((height, size))
+    }
+}
+
+impl<K: Debug, V> AVLMap<K, V> {
+    // This is the function that is called by the main program, then uses the resursive function to get the rest of the nodes in order
+    pub fn in_order_traversal(&self){
+        self.in_order_traversal_recursive(self.root);
+    }
+
+    // Uses recursion to print out he in order traversal of the AVL tree by traversing through the tree to the left first, then the right
+    fn in_order_traversal_recursive(&self, node: Option<NodeId>) {
+        if let Some(n) = node {
+            let node_ref = &self.nodes[n];
+            self.in_order_traversal_recursive(node_ref.left);
+            println!("{:?}", node_ref.key);
+            self.in_order_traversal_recursive(node_ref.right);
+        }
+    }
+
+    // This will be the function that main calls to print the structure of the tree
+    pub fn print_structure(&self) {
+        self.write_structure(io::stdout().lock()).expect("failed to write to stdout");
+    }
+
+    // The lines print_structure shows, right subtrees above their parent and left ones below
+    pub fn write_structure<W: Write>(&self, mut out: W) -> io::Result<()> {
+        if let Some(n) = self.root {
+            self.print_node(&mut out, self.nodes[n].right,  0)?;
+            writeln!(out, "{:?}", self.nodes[n].key)?;
+            self.print_node(&mut out, self.nodes[n].left,  0)?;
+        }
+        Ok(())
+    }
+
+    // This uses preorder traversal to print out the tree with structure, where the height of the tree is the widge that is used to space out the strucutre.
+    fn print_node<W: Write>(&self, out: &mut W, node: Option<NodeId>, node_height: i32) -> io::Result<()> {
+        if let Some(n) = node {
+            self.print_node(out, self.nodes[n].right, node_height + 1)?;
+            writeln!(out, "{:>width$}|-----{:?}", "", self.nodes[n].key, width = ((node_height) * 7) as usize)?;
+            self.print_node(out, self.nodes[n].left, node_height + 1)?;
+
+        }
+        Ok(())
+    }
+
+    // ---------------------------------------- Export -----------------------------------------------
+    // The map in Graphviz DOT with the balance factor (left height minus right height) next to every node,
+    // `dot -Tsvg` renders it. With `null_leaves` the empty children are drawn as points.
+    pub fn to_dot(&self, null_leaves: bool) -> String {
+        export::to_string(|out| self.write_dot(out, null_leaves))
+    }
+
+    pub fn write_dot<W: Write>(&self, mut out: W, null_leaves: bool) -> io::Result<()> {
+        writeln!(out, "digraph AVLTree {{")?;
+        writeln!(out, "    node [shape=circle];")?;
+        if let Some(root) = self.root {
+            self.write_dot_node(&mut out, root, null_leaves, &mut 0)?;
+        }
+        writeln!(out, "}}")
+    }
+
+    // Writes the subtree and returns the number its root was written under. Nodes are numbered in preorder, so
+    // two maps of the same shape give the same output whatever their arenas look like.
+    fn write_dot_node<W: Write>(&self, out: &mut W, node: NodeId, null_leaves: bool, next: &mut usize) -> io::Result<usize> {
+        let id = *next;
+        *next += 1;
+        let balance = balance_factor(&self.nodes, node);
+        let node = &self.nodes[node];
+        writeln!(out, "    n{} [label={}, xlabel=\"{}\"];", id, export::dot_string(&node.key), balance)?;
+        for child in [node.left, node.right] {
+            match child {
+                Some(child) => {
+                    let child = self.write_dot_node(out, child, null_leaves, next)?;
+                    writeln!(out, "    n{} -> n{};", id, child)?;
+                }
+                None if null_leaves => {
+                    writeln!(out, "    n{} [shape=point];", *next)?;
+                    writeln!(out, "    n{} -> n{};", id, *next)?;
+                    *next += 1;
+                }
+                // an invisible placeholder keeps an only child on its own side
+                None if node.left.is_some() || node.right.is_some() => {
+                    writeln!(out, "    n{} [style=invis];", *next)?;
+                    writeln!(out, "    n{} -> n{} [style=invis];", id, *next)?;
+                    *next += 1;
+                }
+                None => {}
+            }
+        }
+        Ok(id)
+    }
+
+    // The structure of the map as JSON, one node per object with its key, height, balance factor, subtree size
+    // and children. The output is indented so two dumps can be compared line by line.
+    pub fn to_json(&self) -> String {
+        export::to_string(|out| self.write_json(out))
+    }
+
+    pub fn write_json<W: Write>(&self, mut out: W) -> io::Result<()> {
+        writeln!(out, "{{")?;
+        writeln!(out, "  \"kind\": \"avl\",")?;
+        writeln!(out, "  \"len\": {},", size(&self.nodes, self.root))?;
+        write!(out, "  \"root\": ")?;
+        self.write_json_node(&mut out, self.root, 1)?;
+        writeln!(out)?;
+        writeln!(out, "}}")
+    }
+
+    fn write_json_node<W: Write>(&self, out: &mut W, node: Option<NodeId>, depth: usize) -> io::Result<()> {
+        let (id, node) = match node {
+            Some(id) => (id, &self.nodes[id]),
+            None => return write!(out, "null"),
+        };
+        let pad = export::pad(depth + 1);
+        writeln!(out, "{{")?;
+        writeln!(out, "{}\"key\": {},", pad, export::json_string(&node.key))?;
+        writeln!(out, "{}\"height\": {},", pad, node.height)?;
+        writeln!(out, "{}\"balance\": {},", pad, balance_factor(&self.nodes, id))?;
+        writeln!(out, "{}\"size\": {},", pad, node.size)?;
+        write!(out, "{}\"left\": ", pad)?;
+        self.write_json_node(out, node.left, depth + 1)?;
+        writeln!(out, ",")?;
+        write!(out, "{}\"right\": ", pad)?;
+        self.write_json_node(out, node.right, depth + 1)?;
+        writeln!(out)?;
+        write!(out, "{}}}", export::pad(depth))
+    }
+}
+
+// ---------------------------------------- Entry ------------------------------------------------
+// Mirrors std::collections::btree_map::Entry, shared with the red-black tree (see map.rs)
+pub type Entry<'a, K, V> = map::Entry<'a, AVLMap<K, V>>;
+pub type VacantEntry<'a, K, V> = map::VacantEntry<'a, AVLMap<K, V>>;
+pub type OccupiedEntry<'a, K, V> = map::OccupiedEntry<'a, AVLMap<K, V>>;
+
+impl<K: Ord, V> EntryMap for AVLMap<K, V> {
+    type Key = K;
+    type Value = V;
+
+    fn find_node(&self, key: &K) -> Option<NodeId> {
+        AVLMap::find_node(self, key)
+    }
+
+    fn insert_node(&mut self, key: K, value: V) -> NodeId {
+        AVLMap::insert_node(self, key, value)
+    }
+
+    fn remove_node(&mut self, target: NodeId) -> (K, V) {
+        // Steer by the node's own key, the node itself is recognised by its id so no key is compared to itself
+        self.remove_located(&|nodes: &Arena<Node<K, V>>, node: NodeId| {
+            if node == target {
+                std::cmp::Ordering::Equal
+            } else {
+                nodes[target].key.cmp(&nodes[node].key)
+            }
+        })
+        .expect("occupied entry is in the map")
+    }
+
+    fn node_key(&self, node: NodeId) -> &K {
+        AVLMap::node_key(self, node)
+    }
+
+    fn node_value(&self, node: NodeId) -> &V {
+        AVLMap::node_value(self, node)
+    }
+
+    fn node_value_mut(&mut self, node: NodeId) -> &mut V {
+        AVLMap::node_value_mut(self, node)
+    }
+}
+
+// A set of values kept in an AVLMap with no payload
+#[derive(Debug)]
+pub struct AVLTree<T> {
+    map: AVLMap<T, ()>,
+}
+
+impl<T: std::cmp::Ord> AVLTree<T> {
+    pub fn new() -> Self {
+        AVLTree { map: AVLMap::new() }
+    }
+
+    pub fn contains(&self, data: T) -> bool {
+        self.map.contains_key(&data)
+    }
+
+    // This is the insert function that main will call. Returns true if the value was new; if it already exists in the tree then
+    // nothing happens and false is returned
+    pub fn insert(&mut self, data: T) -> bool {
+        match self.map.entry(data) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(());
+                true
+            }
+        }
+    }
+
+    // This will be the function that main calls to delete a Node
+    pub fn delete(&mut self, data: T) {
+        self.map.remove(&data);
+    }
+
+    // Calls the observer after every step of every insert and delete, see AVLMap::set_observer
+    pub fn set_observer<F>(&mut self, observer: F)
+    where
+        F: FnMut(Step<&T>, &AVLMap<T, ()>) + Send + Sync + 'static,
+    {
+        self.map.set_observer(observer);
+    }
+
+    pub fn clear_observer(&mut self) {
+        self.map.clear_observer();
+    }
+
+    // Checks if the root is none, and if it is then the tree is empty
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    // Returns the height of the root
+    pub fn get_height(&self) -> i32 {
+        self.map.get_height()
+    }
+
+    // Counts the number of leaves by checking if a node has no children and uses recursion
+    pub fn count_leaves(&self) -> usize {
+        self.map.count_leaves()
+    }
+
+    pub fn stats(&self) -> TreeStats {
+        self.map.stats()
+    }
+
+    // Iterates over the values that fall in the range, from either end
+    pub fn range<R: RangeBounds<T>>(&self, range: R) -> impl DoubleEndedIterator<Item = &T> {
+        self.map.range(range).map(|(data, _)| data)
+    }
+
+    pub fn first(&self) -> Option<&T> {
+        self.map.first().map(|(data, _)| data)
+    }
+
+    pub fn last(&self) -> Option<&T> {
+        self.map.last().map(|(data, _)| data)
+    }
+
+    pub fn pop_first(&mut self) -> Option<T> {
+        self.map.pop_first().map(|(data, _)| data)
+    }
+
+    pub fn pop_last(&mut self) -> Option<T> {
+        self.map.pop_last().map(|(data, _)| data)
+    }
+
+    // Largest value that is less than or equal to the given one
+    pub fn floor(&self, data: &T) -> Option<&T> {
+        self.map.floor(data).map(|(data, _)| data)
+    }
+
+    // Smallest value that is greater than or equal to the given one
+    pub fn ceiling(&self, data: &T) -> Option<&T> {
+        self.map.ceiling(data).map(|(data, _)| data)
+    }
+
+    // Largest value that is strictly less than the given one
+    pub fn predecessor(&self, data: &T) -> Option<&T> {
+        self.map.predecessor(data).map(|(data, _)| data)
+    }
+
+    // Smallest value that is strictly greater than the given one
+    pub fn successor(&self, data: &T) -> Option<&T> {
+        self.map.successor(data).map(|(data, _)| data)
+    }
+
+    // Builds a tree out of values that are already sorted in O(n), see AVLMap::from_sorted_iter
+    pub fn from_sorted_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        AVLTree { map: AVLMap::from_sorted_iter(iter.into_iter().map(|data| (data, ()))) }
+    }
+
+    // Splits the tree at the value, the left tree gets the values below it and the right tree the rest
+    pub fn split(self, data: &T) -> (Self, Self) {
+        let (left, right) = self.map.split(data);
+        (AVLTree { map: left }, AVLTree { map: right })
+    }
+
+    // Puts two trees back together around a value that is larger than everything in `left` and smaller than
+    // everything in `right`
+    pub fn join(left: Self, data: T, right: Self) -> Self {
+        AVLTree { map: AVLMap::join(left.map, data, (), right.map) }
+    }
+
+    // Values in either tree, see AVLMap::union
+    pub fn union(self, other: Self) -> Self {
+        AVLTree { map: self.map.union(other.map) }
+    }
+
+    // Values in both trees
+    pub fn intersection(self, other: Self) -> Self {
+        AVLTree { map: self.map.intersection(other.map) }
+    }
+
+    // Values in this tree that are not in `other`
+    pub fn difference(self, other: Self) -> Self {
+        AVLTree { map: self.map.difference(other.map) }
+    }
+
+    // Values in exactly one of the trees
+    pub fn symmetric_difference(self, other: Self) -> Self {
+        AVLTree { map: self.map.symmetric_difference(other.map) }
+    }
+
+    // Number of values in the tree that are strictly less than the given one
+    pub fn rank(&self, data: &T) -> usize {
+        self.map.rank(data)
+    }
+
+    // The index-th smallest value, counting from 0
+    pub fn select(&self, index: usize) -> Option<&T> {
+        self.map.select(index).map(|(data, _)| data)
+    }
+}
+
+// Sets are snapshots of maps with () values, so a set snapshot also loads into a RedBlackTree<T>
+impl<T: Ord + Codec> AVLTree<T> {
+    pub fn to_snapshot(&self) -> Vec<u8> {
+        self.map.to_snapshot()
+    }
+
+    pub fn from_snapshot(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        AVLMap::from_snapshot(bytes).map(|map| AVLTree { map })
+    }
+
+    pub fn save_to<P: AsRef<Path>>(&self, path: P) -> Result<(), SnapshotError> {
+        self.map.save_to(path)
+    }
+
+    pub fn load_from<P: AsRef<Path>>(path: P) -> Result<Self, SnapshotError> {
+        AVLMap::load_from(path).map(|map| AVLTree { map })
+    }
+}
+
+impl<T> AVLTree<T> {
+    // Iterates over the values in order, from either end
+    pub fn iter(&self) -> Keys<'_, T, ()> {
+        self.map.keys()
+    }
+}
+
+impl<T: std::cmp::Ord + std::fmt::Debug> AVLTree<T> {
+    // Checks the AVL rules on the underlying map, see AVLMap::validate
+    pub fn validate(&self) -> Result<(), InvariantViolation> {
+        self.map.validate()
+    }
+}
+
+impl<T: std::fmt::Debug> AVLTree<T> {
+    // This is the function that is called by the main program, then uses the resursive function to get the rest of the nodes in order
+    pub fn in_order_traversal(&self){
+        self.map.in_order_traversal();
+    }
+
+    // This will be the function that main calls to print the structure of the tree
+    pub fn print_structure(&self) {
+        self.map.print_structure();
+    }
+
+    pub fn write_structure<W: Write>(&self, out: W) -> io::Result<()> {
+        self.map.write_structure(out)
+    }
+
+    // See AVLMap::to_dot
+    pub fn to_dot(&self, null_leaves: bool) -> String {
+        self.map.to_dot(null_leaves)
+    }
+
+    pub fn write_dot<W: Write>(&self, out: W, null_leaves: bool) -> io::Result<()> {
+        self.map.write_dot(out, null_leaves)
+    }
+
+    // See AVLMap::to_json
+    pub fn to_json(&self) -> String {
+        self.map.to_json()
+    }
+
+    pub fn write_json<W: Write>(&self, out: W) -> io::Result<()> {
+        self.map.write_json(out)
+    }
+}
+
+// ---------------------------------------- Iterators ------------------------------------------------
+// The in order iterators are shared with the red-black tree, see map.rs
+pub type Iter<'a, K, V> = map::Iter<'a, Node<K, V>>;
+pub type Range<'a, K, V> = map::Range<'a, Node<K, V>>;
+pub type Keys<'a, K, V> = map::Keys<'a, Node<K, V>>;
+pub type Values<'a, K, V> = map::Values<'a, Node<K, V>>;
+pub use crate::map::{IntoIter, IntoKeys, IntoValues};
+
+impl<K, V> IntoIterator for AVLMap<K, V> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter::new(self.nodes, self.root)
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a AVLMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<K: Ord, V> Default for AVLMap<K, V> {
+    fn default() -> Self {
+        AVLMap::new()
+    }
+}
+
+// Entries can come in any order, they are sorted first and then built in one go. For a key that shows up more
+// than once the first key is kept with the last value, the same as inserting them one by one.
+impl<K: Ord, V> FromIterator<(K, V)> for AVLMap<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut entries: Vec<(K, V)> = iter.into_iter().collect();
+        // stable, so entries with the same key stay in the order they came in
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        entries.dedup_by(|next, kept| {
+            let duplicate = next.0 == kept.0;
+            if duplicate {
+                mem::swap(&mut next.1, &mut kept.1);
+            }
+            duplicate
+        });
+        Self::from_sorted_vec(entries)
+    }
+}
+
+impl<K: Ord, V> Extend<(K, V)> for AVLMap<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<T: Ord> Default for AVLTree<T> {
+    fn default() -> Self {
+        AVLTree::new()
+    }
+}
+
+impl<T: Ord> FromIterator<T> for AVLTree<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        AVLTree { map: iter.into_iter().map(|data| (data, ())).collect() }
+    }
+}
+
+impl<T: Ord> Extend<T> for AVLTree<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for data in iter {
+            self.insert(data);
+        }
+    }
+}
+
+impl<T: Ord> OrderedSet<T> for AVLTree<T> {
+    type Iter<'a> = Keys<'a, T, ()> where T: 'a;
+
+    const NAME: &'static str = "AVL Tree";
+
+    fn new() -> Self {
+        AVLTree::new()
+    }
+
+    fn insert(&mut self, value: T) -> bool {
+        AVLTree::insert(self, value)
+    }
+
+    fn remove(&mut self, value: &T) -> bool {
+        self.map.remove(value).is_some()
+    }
+
+    fn contains(&self, value: &T) -> bool {
+        self.map.contains_key(value)
+    }
+
+    fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    fn height(&self) -> i32 {
+        self.map.get_height()
+    }
+
+    fn count_leaves(&self) -> usize {
+        self.map.count_leaves()
+    }
+
+    fn stats(&self) -> Option<TreeStats> {
+        Some(self.map.stats())
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        self.map.keys()
+    }
+
+    fn range<'a>(&'a self, start: Bound<&T>, end: Bound<&T>) -> Box<dyn DoubleEndedIterator<Item = &'a T> + 'a> {
+        Box::new(self.map.range((start, end)).map(|(data, _)| data))
+    }
+
+    fn rank(&self, value: &T) -> usize {
+        self.map.rank(value)
+    }
+
+    fn select(&self, index: usize) -> Option<&T> {
+        self.map.select(index).map(|(data, _)| data)
+    }
+
+    fn validate(&self) -> Result<(), InvariantViolation> where T: Debug {
+        self.map.validate()
+    }
+
+    fn print(&self) where T: Debug {
+        self.map.print_structure();
+    }
+
+    fn save_to(&self, path: &Path) -> Result<(), SnapshotError> where T: Codec {
+        self.map.save_to(path)
+    }
+
+    fn load_from(path: &Path) -> Result<Self, SnapshotError> where T: Codec {
+        AVLMap::load_from(path).map(|map| AVLTree { map })
+    }
+}
+
+impl<T> IntoIterator for AVLTree<T> {
+    type Item = T;
+    type IntoIter = IntoKeys<T, ()>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.into_keys()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a AVLTree<T> {
+    type Item = &'a T;
+    type IntoIter = Keys<'a, T, ()>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+// ---------------------------------------- Serde ------------------------------------------------
+// With the serde feature a map is written as the sequence of its (key, value) pairs in key order and a tree as
+// the sequence of its values, both are read back with a bulk build
+#[cfg(feature = "serde")]
+impl<K: serde::Serialize, V: serde::Serialize> serde::Serialize for AVLMap<K, V> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        map::serialize(self.iter(), serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K: Ord + serde::Deserialize<'de>, V: serde::Deserialize<'de>> serde::Deserialize<'de> for AVLMap<K, V> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        map::deserialize(deserializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> serde::Serialize for AVLTree<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T: Ord + serde::Deserialize<'de>> serde::Deserialize<'de> for AVLTree<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let values: Vec<T> = serde::Deserialize::deserialize(deserializer)?;
+        Ok(values.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // Each test breaks one rule by hand in a valid tree and checks that validate names that rule
+    // and the node it is broken at. AVL nodes have no parent links, so there is no such rule here.
+    use super::*;
+
+    // 1 to 7 inserted in order make a perfect tree: 4 at the root, 2 over 1 and 3, 6 over 5 and 7
+    fn tree() -> AVLMap<i32, ()> {
+        let mut tree = AVLMap::new();
+        for key in 1..=7 {
+            tree.insert(key, ());
+        }
+        let root = tree.root.expect("the tree is not empty");
+        assert_eq!((tree.nodes[root].key, tree.nodes[root].height), (4, 3));
+        assert_eq!(tree.validate(), Ok(()));
+        tree
+    }
+
+    fn node(tree: &AVLMap<i32, ()>, key: i32) -> NodeId {
+        tree.find_node(&key).expect("the key is in the tree")
+    }
+
+    fn broken_at(tree: &AVLMap<i32, ()>, invariant: Invariant, key: i32) {
+        assert_eq!(tree.validate(), Err(InvariantViolation::new(invariant, &key)));
+    }
+
+    #[test]
+    fn stored_height() {
+        let mut tree = tree();
+        let three = node(&tree, 3);
+        tree.nodes[three].height = 2;
+        broken_at(&tree, Invariant::Height, 3);
+    }
+
+    #[test]
+    fn subtree_size() {
+        let mut tree = tree();
+        let six = node(&tree, 6);
+        tree.nodes[six].size = 4;
+        broken_at(&tree, Invariant::Size, 6);
+    }
+
+    #[test]
+    fn key_order() {
+        let mut tree = tree();
+        let (three, five) = (node(&tree, 3), node(&tree, 5));
+        let (three, five) = tree.nodes.pair_mut(three, five);
+        mem::swap(&mut three.key, &mut five.key);
+        // 5 now sits right of 2 and left of 4, where only 3 belongs
+        broken_at(&tree, Invariant::Order, 5);
+    }
+
+    #[test]
+    fn children_heights_more_than_one_apart() {
+        let mut tree = tree();
+        let root = node(&tree, 4);
+        // 2, 1 and 3 are cut off, so the left side is empty and the right side two high
+        tree.nodes[root].left = None;
+        broken_at(&tree, Invariant::Balance, 4);
+    }
 }
@@ -0,0 +1,763 @@
+use crate::arena::{Arena, NodeId};
+use crate::invariant::{Invariant, InvariantViolation};
+use crate::ordered_set::OrderedSet;
+use crate::snapshot::{self, Codec, SnapshotError};
+use std::fmt::Debug;
+use std::fs;
+use std::iter::FusedIterator;
+use std::mem;
+use std::ops::{Bound, RangeBounds};
+use std::path::Path;
+use std::ptr;
+
+// Order used by BTree::new(): nodes hold up to 15 values, a few cache lines of i32s or u32s
+pub const DEFAULT_ORDER: usize = 16;
+
+// A node keeps its values sorted in one Vec. Inner nodes have one more child than values, the
+// values of children[i] all lie between values[i - 1] and values[i].
+#[derive(Debug)]
+struct Node<T> {
+    keys: Vec<T>,
+    // empty for a leaf
+    children: Vec<NodeId>,
+}
+
+impl<T> Node<T> {
+    fn leaf(keys: Vec<T>) -> Self {
+        Node {
+            keys,
+            children: Vec::new(),
+        }
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+}
+
+// What inserting into a subtree did
+enum Insert<T> {
+    // the value was there already
+    Present,
+    Done,
+    // the subtree root overflowed and was split, the middle value and the new right half go up
+    Split(T, NodeId),
+}
+
+// A B-tree of a given order m: every node holds at most m - 1 values, every node but the root at
+// least ceil(m / 2) - 1 of them, and all leaves are on the same level. Looking a value up is a
+// binary search through one short Vec per level, so a tree of a larger order touches far fewer
+// nodes, and cache lines, than the binary trees do for the same values.
+//
+// The nodes live in an arena owned by the tree, the same as in the red-black tree, see arena.rs.
+#[derive(Debug)]
+pub struct BTree<T> {
+    nodes: Arena<Node<T>>,
+    root: Option<NodeId>,
+    order: usize,
+    len: usize,
+}
+
+impl<T> BTree<T> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // The largest number of children a node can have
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    fn max_keys(&self) -> usize {
+        self.order - 1
+    }
+
+    fn min_keys(&self) -> usize {
+        self.order.div_ceil(2) - 1
+    }
+
+    // Number of levels, every leaf is on the last one
+    pub fn get_height(&self) -> i32 {
+        let mut height = 0;
+        let mut current = self.root;
+        while let Some(node) = current {
+            height += 1;
+            current = self.nodes[node].children.first().copied();
+        }
+        height
+    }
+
+    // Number of leaf nodes, not values
+    pub fn count_leaves(&self) -> usize {
+        fn count_leaves_helper<T>(nodes: &Arena<Node<T>>, node: NodeId) -> usize {
+            let node = &nodes[node];
+            if node.is_leaf() {
+                1
+            } else {
+                node.children
+                    .iter()
+                    .map(|child| count_leaves_helper(nodes, *child))
+                    .sum()
+            }
+        }
+
+        self.root
+            .map_or(0, |root| count_leaves_helper(&self.nodes, root))
+    }
+
+    pub fn clear(&mut self) {
+        self.nodes = Arena::new();
+        self.root = None;
+        self.len = 0;
+    }
+}
+
+impl<T: Ord> BTree<T> {
+    pub fn new() -> Self {
+        Self::with_order(DEFAULT_ORDER)
+    }
+
+    // Panics if the order is below 3, a node has to be able to split into two with a value between
+    pub fn with_order(order: usize) -> Self {
+        assert!(
+            order >= 3,
+            "a B-tree needs an order of at least 3, got {}",
+            order
+        );
+        BTree {
+            nodes: Arena::new(),
+            root: None,
+            order,
+            len: 0,
+        }
+    }
+
+    pub fn contains(&self, value: &T) -> bool {
+        let mut current = self.root;
+        while let Some(node) = current {
+            let node = &self.nodes[node];
+            match node.keys.binary_search(value) {
+                Ok(_) => return true,
+                Err(index) => current = node.children.get(index).copied(),
+            }
+        }
+        false
+    }
+
+    pub fn first(&self) -> Option<&T> {
+        self.iter().next()
+    }
+
+    pub fn last(&self) -> Option<&T> {
+        self.iter().next_back()
+    }
+
+    // ---------------------------------------- Insert -----------------------------------------------
+    // Returns false if the value was in the tree already. The value goes into a leaf, and a node that
+    // ends up with too many values is split on the way back up; when the root splits the tree grows
+    // a new root.
+    pub fn insert(&mut self, value: T) -> bool {
+        let root = match self.root {
+            Some(root) => root,
+            None => {
+                self.root = Some(self.nodes.alloc(Node::leaf(vec![value])));
+                self.len = 1;
+                return true;
+            }
+        };
+        match self.insert_into(root, value) {
+            Insert::Present => return false,
+            Insert::Done => {}
+            Insert::Split(middle, right) => {
+                self.root = Some(self.nodes.alloc(Node {
+                    keys: vec![middle],
+                    children: vec![root, right],
+                }));
+            }
+        }
+        self.len += 1;
+        true
+    }
+
+    fn insert_into(&mut self, node: NodeId, value: T) -> Insert<T> {
+        let index = match self.nodes[node].keys.binary_search(&value) {
+            Ok(_) => return Insert::Present,
+            Err(index) => index,
+        };
+        if self.nodes[node].is_leaf() {
+            self.nodes[node].keys.insert(index, value);
+        } else {
+            let child = self.nodes[node].children[index];
+            match self.insert_into(child, value) {
+                Insert::Split(middle, right) => {
+                    let node = &mut self.nodes[node];
+                    node.keys.insert(index, middle);
+                    node.children.insert(index + 1, right);
+                }
+                done => return done,
+            }
+        }
+        if self.nodes[node].keys.len() > self.max_keys() {
+            self.split(node)
+        } else {
+            Insert::Done
+        }
+    }
+
+    // Splits a node with one value too many in two, the right half becomes a new node
+    fn split(&mut self, node: NodeId) -> Insert<T> {
+        let left = &mut self.nodes[node];
+        let middle = left.keys.len() / 2;
+        let right_keys = left.keys.split_off(middle + 1);
+        let right_children = if left.is_leaf() {
+            Vec::new()
+        } else {
+            left.children.split_off(middle + 1)
+        };
+        let middle = left.keys.pop().expect("a split node has a middle value");
+        let right = self.nodes.alloc(Node {
+            keys: right_keys,
+            children: right_children,
+        });
+        Insert::Split(middle, right)
+    }
+
+    // ---------------------------------------- Delete -----------------------------------------------
+    // Returns false if the value was not in the tree. A value in an inner node is swapped for the
+    // largest value of its left subtree, so only leaves ever lose values. A node left with too few
+    // values borrows one from a sibling through the parent, or is merged with a sibling, on the way
+    // back up; when the root loses its last value its only child becomes the root.
+    pub fn remove(&mut self, value: &T) -> bool {
+        let root = match self.root {
+            Some(root) => root,
+            None => return false,
+        };
+        if self.remove_from(root, value).is_none() {
+            return false;
+        }
+        self.len -= 1;
+        if self.nodes[root].keys.is_empty() {
+            self.root = self.nodes[root].children.first().copied();
+            self.nodes.free(root);
+        }
+        true
+    }
+
+    // Removes the value from the subtree below `node` and returns it. `node` itself may be left with
+    // too few values, its parent takes care of that.
+    fn remove_from(&mut self, node: NodeId, value: &T) -> Option<T> {
+        let search = self.nodes[node].keys.binary_search(value);
+        if self.nodes[node].is_leaf() {
+            return search.ok().map(|index| self.nodes[node].keys.remove(index));
+        }
+        let (removed, index) = match search {
+            Ok(index) => {
+                let child = self.nodes[node].children[index];
+                let predecessor = self.remove_last(child);
+                (
+                    mem::replace(&mut self.nodes[node].keys[index], predecessor),
+                    index,
+                )
+            }
+            Err(index) => {
+                let child = self.nodes[node].children[index];
+                (self.remove_from(child, value)?, index)
+            }
+        };
+        self.fix_underflow(node, index);
+        Some(removed)
+    }
+
+    // Removes and returns the largest value below `node`
+    fn remove_last(&mut self, node: NodeId) -> T {
+        if self.nodes[node].is_leaf() {
+            return self.nodes[node]
+                .keys
+                .pop()
+                .expect("only the root can be empty");
+        }
+        let index = self.nodes[node].children.len() - 1;
+        let last = self.remove_last(self.nodes[node].children[index]);
+        self.fix_underflow(node, index);
+        last
+    }
+
+    // Tops up children[index] of `node` if a removal left it with fewer values than the minimum
+    fn fix_underflow(&mut self, node: NodeId, index: usize) {
+        let min = self.min_keys();
+        let children = &self.nodes[node].children;
+        let child = children[index];
+        if self.nodes[child].keys.len() >= min {
+            return;
+        }
+        let left = index.checked_sub(1).map(|left| children[left]);
+        let right = children.get(index + 1).copied();
+        if let Some(left) = left.filter(|left| self.nodes[*left].keys.len() > min) {
+            self.borrow_from_left(node, index, left, child);
+        } else if let Some(right) = right.filter(|right| self.nodes[*right].keys.len() > min) {
+            self.borrow_from_right(node, index, child, right);
+        } else if left.is_some() {
+            self.merge(node, index - 1);
+        } else {
+            self.merge(node, index);
+        }
+    }
+
+    // The value between the two children moves down into `child` and the last value of `left`
+    // moves up to take its place, together with the child that hung to the right of it
+    fn borrow_from_left(&mut self, parent: NodeId, index: usize, left: NodeId, child: NodeId) {
+        let left = &mut self.nodes[left];
+        let up = left.keys.pop().expect("the sibling has values to spare");
+        let moved = left.children.pop();
+        let down = mem::replace(&mut self.nodes[parent].keys[index - 1], up);
+        let child = &mut self.nodes[child];
+        child.keys.insert(0, down);
+        if let Some(moved) = moved {
+            child.children.insert(0, moved);
+        }
+    }
+
+    // The mirror image of borrow_from_left
+    fn borrow_from_right(&mut self, parent: NodeId, index: usize, child: NodeId, right: NodeId) {
+        let right = &mut self.nodes[right];
+        let up = right.keys.remove(0);
+        let moved = (!right.is_leaf()).then(|| right.children.remove(0));
+        let down = mem::replace(&mut self.nodes[parent].keys[index], up);
+        let child = &mut self.nodes[child];
+        child.keys.push(down);
+        if let Some(moved) = moved {
+            child.children.push(moved);
+        }
+    }
+
+    // Merges children[index + 1] of `parent` and the value in front of it into children[index].
+    // Both children are at or below the minimum, so the merged node is never too full.
+    fn merge(&mut self, parent: NodeId, index: usize) {
+        let parent = &mut self.nodes[parent];
+        let down = parent.keys.remove(index);
+        let right = parent.children.remove(index + 1);
+        let left = parent.children[index];
+        let Node { keys, children } = self.nodes.free(right);
+        let left = &mut self.nodes[left];
+        left.keys.push(down);
+        left.keys.extend(keys);
+        left.children.extend(children);
+    }
+
+    // ---------------------------------------- Iterators --------------------------------------------
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter::new(&self.nodes, self.root, Bound::Unbounded, Bound::Unbounded)
+    }
+
+    // The values within the range, in order. Bounds the wrong way round give an empty iterator.
+    pub fn range<R: RangeBounds<T>>(&self, range: R) -> Iter<'_, T> {
+        Iter::new(
+            &self.nodes,
+            self.root,
+            range.start_bound(),
+            range.end_bound(),
+        )
+    }
+
+    // ---------------------------------------- Snapshot ---------------------------------------------
+    // The values in the binary snapshot format, see the snapshot module
+    pub fn to_snapshot(&self) -> Vec<u8>
+    where
+        T: Codec,
+    {
+        snapshot::encode(self.len(), self.iter().map(|value| (value, &())))
+    }
+
+    // Rebuilds a tree of the default order from a snapshot. The snapshot holds only the values, see
+    // from_snapshot_with_order to get back a tree of another order.
+    pub fn from_snapshot(bytes: &[u8]) -> Result<Self, SnapshotError>
+    where
+        T: Codec,
+    {
+        Self::from_snapshot_with_order(bytes, DEFAULT_ORDER)
+    }
+
+    // Rebuilds a tree of the given order from a snapshot. Panics like with_order for an order
+    // below 3.
+    pub fn from_snapshot_with_order(bytes: &[u8], order: usize) -> Result<Self, SnapshotError>
+    where
+        T: Codec,
+    {
+        let mut tree = Self::with_order(order);
+        for (value, ()) in snapshot::decode::<T, ()>(bytes)? {
+            tree.insert(value);
+        }
+        Ok(tree)
+    }
+
+    pub fn save_to<P: AsRef<Path>>(&self, path: P) -> Result<(), SnapshotError>
+    where
+        T: Codec,
+    {
+        fs::write(path, self.to_snapshot())?;
+        Ok(())
+    }
+
+    pub fn load_from<P: AsRef<Path>>(path: P) -> Result<Self, SnapshotError>
+    where
+        T: Codec,
+    {
+        Self::from_snapshot(&fs::read(path)?)
+    }
+
+    pub fn load_from_with_order<P: AsRef<Path>>(
+        path: P,
+        order: usize,
+    ) -> Result<Self, SnapshotError>
+    where
+        T: Codec,
+    {
+        Self::from_snapshot_with_order(&fs::read(path)?, order)
+    }
+}
+
+impl<T: Ord + Debug> BTree<T> {
+    // ---------------------------------------- Validate ---------------------------------------------
+    // Checks that the values are in order, that every node holds an allowed number of values and
+    // has one child more than values unless it is a leaf, that all leaves are at the same depth and
+    // that the stored length matches. The violation names the values of the offending node.
+    pub fn validate(&self) -> Result<(), InvariantViolation> {
+        let mut leaf_depth = None;
+        let count = match self.root {
+            Some(root) => self.validate_node(root, None, None, 0, &mut leaf_depth)?,
+            None => 0,
+        };
+        if count != self.len {
+            let root = self.root.map(|root| &self.nodes[root].keys);
+            return Err(InvariantViolation::new(Invariant::Size, &root));
+        }
+        Ok(())
+    }
+
+    // Returns the number of values below `node`, which all have to lie strictly between `lower` and
+    // `upper`. The first leaf found sets the depth every other leaf has to be at.
+    fn validate_node(
+        &self,
+        node_id: NodeId,
+        lower: Option<&T>,
+        upper: Option<&T>,
+        depth: usize,
+        leaf_depth: &mut Option<usize>,
+    ) -> Result<usize, InvariantViolation> {
+        let node = &self.nodes[node_id];
+        let violation = |invariant| Err(InvariantViolation::new(invariant, &node.keys));
+
+        let min = if Some(node_id) == self.root {
+            1
+        } else {
+            self.min_keys()
+        };
+        if node.keys.len() < min || node.keys.len() > self.max_keys() {
+            return violation(Invariant::KeyCount);
+        }
+        if !node.is_leaf() && node.children.len() != node.keys.len() + 1 {
+            return violation(Invariant::KeyCount);
+        }
+        if node.keys.windows(2).any(|pair| pair[0] >= pair[1])
+            || lower.is_some_and(|lower| node.keys[0] <= *lower)
+            || upper.is_some_and(|upper| node.keys[node.keys.len() - 1] >= *upper)
+        {
+            return violation(Invariant::Order);
+        }
+        if node.is_leaf() {
+            if *leaf_depth.get_or_insert(depth) != depth {
+                return violation(Invariant::LeafDepth);
+            }
+            return Ok(node.keys.len());
+        }
+
+        let mut count = node.keys.len();
+        for (index, child) in node.children.iter().enumerate() {
+            let lower = index
+                .checked_sub(1)
+                .map(|index| &node.keys[index])
+                .or(lower);
+            let upper = node.keys.get(index).or(upper);
+            count += self.validate_node(*child, lower, upper, depth + 1, leaf_depth)?;
+        }
+        Ok(count)
+    }
+
+    // ---------------------------------------- Print ------------------------------------------------
+    // One node per line with its values, children indented below their parent
+    pub fn print_tree(&self) {
+        if let Some(root) = self.root {
+            self.print_node(root, 0);
+        }
+    }
+
+    fn print_node(&self, node: NodeId, depth: usize) {
+        let node = &self.nodes[node];
+        println!("{}{:?}", "    ".repeat(depth), node.keys);
+        for child in &node.children {
+            self.print_node(*child, depth + 1);
+        }
+    }
+
+    pub fn in_order_traversal(&self) {
+        for value in self.iter() {
+            println!("{:?}", value);
+        }
+    }
+}
+
+// In order iterator over the values of a BTree, from either end. Each end keeps the path from the
+// root down to the value it hands out next: the front stores the index of the next value in every
+// node on the path, the back the number of values in it not handed out yet. The two ends peek one
+// value ahead, and once both peek at the same value it is the last one.
+pub struct Iter<'a, T> {
+    nodes: &'a Arena<Node<T>>,
+    front: Vec<(NodeId, usize)>,
+    back: Vec<(NodeId, usize)>,
+    next_front: Option<&'a T>,
+    next_back: Option<&'a T>,
+}
+
+impl<'a, T: Ord> Iter<'a, T> {
+    fn new(
+        nodes: &'a Arena<Node<T>>,
+        root: Option<NodeId>,
+        start: Bound<&T>,
+        end: Bound<&T>,
+    ) -> Self {
+        let mut iter = Iter {
+            nodes,
+            front: Vec::new(),
+            back: Vec::new(),
+            next_front: None,
+            next_back: None,
+        };
+        // walk down to the first value at or after start and to the last one at or before end
+        let mut current = root;
+        while let Some(node) = current {
+            let keys = &nodes[node].keys;
+            let index = match start {
+                Bound::Unbounded => 0,
+                Bound::Included(start) => keys.partition_point(|key| key < start),
+                Bound::Excluded(start) => keys.partition_point(|key| key <= start),
+            };
+            iter.front.push((node, index));
+            current = nodes[node].children.get(index).copied();
+        }
+        let mut current = root;
+        while let Some(node) = current {
+            let keys = &nodes[node].keys;
+            let index = match end {
+                Bound::Unbounded => keys.len(),
+                Bound::Included(end) => keys.partition_point(|key| key <= end),
+                Bound::Excluded(end) => keys.partition_point(|key| key < end),
+            };
+            iter.back.push((node, index));
+            current = nodes[node].children.get(index).copied();
+        }
+        match (iter.step_front(), iter.step_back()) {
+            (Some(first), Some(last)) if first <= last => {
+                iter.next_front = Some(first);
+                iter.next_back = Some(last);
+            }
+            // an empty range
+            _ => {}
+        }
+        iter
+    }
+}
+
+impl<'a, T> Iter<'a, T> {
+    fn step_front(&mut self) -> Option<&'a T> {
+        let nodes = self.nodes;
+        while let Some((node, index)) = self.front.pop() {
+            let node_ref = &nodes[node];
+            if index < node_ref.keys.len() {
+                self.front.push((node, index + 1));
+                let mut current = node_ref.children.get(index + 1).copied();
+                while let Some(child) = current {
+                    self.front.push((child, 0));
+                    current = nodes[child].children.first().copied();
+                }
+                return Some(&node_ref.keys[index]);
+            }
+        }
+        None
+    }
+
+    fn step_back(&mut self) -> Option<&'a T> {
+        let nodes = self.nodes;
+        while let Some((node, index)) = self.back.pop() {
+            let node_ref = &nodes[node];
+            if index > 0 {
+                self.back.push((node, index - 1));
+                let mut current = node_ref.children.get(index - 1).copied();
+                while let Some(child) = current {
+                    let child_ref = &nodes[child];
+                    self.back.push((child, child_ref.keys.len()));
+                    current = child_ref.children.last().copied();
+                }
+                return Some(&node_ref.keys[index - 1]);
+            }
+        }
+        None
+    }
+
+    fn finish(&mut self) {
+        self.next_front = None;
+        self.next_back = None;
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let value = self.next_front?;
+        if self.next_back.is_some_and(|last| ptr::eq(last, value)) {
+            self.finish();
+        } else {
+            self.next_front = self.step_front();
+        }
+        Some(value)
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        let value = self.next_back?;
+        if self.next_front.is_some_and(|first| ptr::eq(first, value)) {
+            self.finish();
+        } else {
+            self.next_back = self.step_back();
+        }
+        Some(value)
+    }
+}
+
+impl<T> FusedIterator for Iter<'_, T> {}
+
+impl<'a, T: Ord> IntoIterator for &'a BTree<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<T: Ord> Default for BTree<T> {
+    fn default() -> Self {
+        BTree::new()
+    }
+}
+
+impl<T: Ord> FromIterator<T> for BTree<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut tree = BTree::new();
+        tree.extend(iter);
+        tree
+    }
+}
+
+impl<T: Ord> Extend<T> for BTree<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.insert(value);
+        }
+    }
+}
+
+impl<T: Ord> OrderedSet<T> for BTree<T> {
+    type Iter<'a>
+        = Iter<'a, T>
+    where
+        T: 'a;
+
+    const NAME: &'static str = "B-Tree";
+
+    fn new() -> Self {
+        BTree::new()
+    }
+
+    fn insert(&mut self, value: T) -> bool {
+        BTree::insert(self, value)
+    }
+
+    fn remove(&mut self, value: &T) -> bool {
+        BTree::remove(self, value)
+    }
+
+    fn contains(&self, value: &T) -> bool {
+        BTree::contains(self, value)
+    }
+
+    fn len(&self) -> usize {
+        BTree::len(self)
+    }
+
+    fn height(&self) -> i32 {
+        self.get_height()
+    }
+
+    fn count_leaves(&self) -> usize {
+        BTree::count_leaves(self)
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        BTree::iter(self)
+    }
+
+    fn range<'a>(
+        &'a self,
+        start: Bound<&T>,
+        end: Bound<&T>,
+    ) -> Box<dyn DoubleEndedIterator<Item = &'a T> + 'a> {
+        Box::new(Iter::new(&self.nodes, self.root, start, end))
+    }
+
+    fn validate(&self) -> Result<(), InvariantViolation>
+    where
+        T: Debug,
+    {
+        BTree::validate(self)
+    }
+
+    fn print(&self)
+    where
+        T: Debug,
+    {
+        self.print_tree();
+    }
+
+    fn save_to(&self, path: &Path) -> Result<(), SnapshotError>
+    where
+        T: Codec,
+    {
+        BTree::save_to(self, path)
+    }
+
+    fn load_from(path: &Path) -> Result<Self, SnapshotError>
+    where
+        T: Codec,
+    {
+        BTree::load_from(path)
+    }
+
+    // Keeps the order of this tree, which the snapshot does not record
+    fn reload_from(&mut self, path: &Path) -> Result<(), SnapshotError>
+    where
+        T: Codec,
+    {
+        *self = BTree::load_from_with_order(path, self.order)?;
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        BTree::clear(self);
+    }
+}
@@ -1,11 +1,45 @@
-use crate::avl::AVLTree;
-use crate::rb::RedBlackTree;
+use project2::btree::DEFAULT_ORDER;
+use project2::{AVLTree, BTree, OrderedSet, RedBlackTree, SkipList, Treap};
 use std::io;
-fn avlTree() {
-    let mut tree = AVLTree::new();
+use std::path::Path;
+
+// Asks for the file to save to or load from
+fn read_path() -> String {
+    println!("Enter a file path:");
+    let mut path = String::new();
+    io::stdin()
+        .read_line(&mut path)
+        .expect("Failed to read line");
+    path.trim().to_string()
+}
+
+// Asks for the order of a new B-tree, an empty line keeps the default
+fn read_order() -> Option<usize> {
+    println!(
+        "Enter the order of the B-tree (at least 3, {} if left empty):",
+        DEFAULT_ORDER
+    );
+    let mut order = String::new();
+    io::stdin()
+        .read_line(&mut order)
+        .expect("Failed to read line");
+    match order.trim() {
+        "" => Some(DEFAULT_ORDER),
+        order => match order.parse() {
+            Ok(order) if order >= 3 => Some(order),
+            _ => {
+                println!("Invalid input, please enter a number of at least 3");
+                None
+            }
+        },
+    }
+}
+
+// One menu for every tree type, the tree is only used through OrderedSet
+fn tree_menu<S: OrderedSet<i32>>(mut tree: S) {
     loop {
         // Print the menu of available options
-        println!("\nAVL Tree Operations (type the number):");
+        println!("\n{} Operations (type the number):", S::NAME);
         println!("1. Insert a node");
         println!("2. Delete a node");
         println!("3. Count the number of leaves");
@@ -13,7 +47,10 @@ fn avlTree() {
         println!("5. Print in order traversal");
         println!("6. Check if the tree is empty");
         println!("7. Print the tree structure");
-        println!("8. Exit");
+        println!("8. Save the tree to a file");
+        println!("9. Load a tree from a file");
+        println!("10. Show tree statistics");
+        println!("11. Exit");
 
         // Get the user's choice
         let mut choice = String::new();
@@ -45,9 +82,12 @@ fn avlTree() {
                     }
                 };
 
-                // Insert the value into the AVL tree
-                tree.insert(value);
-                println!("Value {} inserted successfully", value);
+                // Insert the value into the tree, a value that is already there is left alone
+                if tree.insert(value) {
+                    println!("Value {} inserted successfully", value);
+                } else {
+                    println!("{} is already in the tree", value);
+                }
             }
             2 => {
                 // Get the value to delete from the user
@@ -64,32 +104,30 @@ fn avlTree() {
                     }
                 };
 
-                // Delete the value from the AVL tree
-                if tree.contains(value){
-                    tree.delete(value);
+                // Delete the value from the tree
+                if tree.remove(&value) {
                     println!("{} deleted from tree", value);
                 } else {
                     println!("{} does not exist in tree", value);
                 }
-                
             }
             3 => {
-                // Count the number of leaves in the AVL tree
+                // Count the number of leaves in the tree
                 let num_leaves = tree.count_leaves();
                 println!("Number of leaves in the tree: {}", num_leaves);
             }
             4 => {
-                // Get the height of the AVL tree
-                let height = tree.get_height();
+                // Get the height of the tree
+                let height = tree.height();
                 println!("Height of the tree: {}", height);
             }
             5 => {
-                // Print the AVL tree in order traversal
+                // Print the tree in order traversal
                 println!("In order traversal of the tree:");
-                tree.in_order_traversal();
+                tree.print_in_order();
             }
             6 => {
-                // Check if the AVL tree is empty
+                // Check if the tree is empty
                 if tree.is_empty() {
                     println!("The tree is empty");
                 } else {
@@ -97,118 +135,57 @@ fn avlTree() {
                 }
             }
             7 => {
-                // Print the AVL tree structure
+                // Print the tree structure
                 println!("Structure of the tree:");
-                tree.print_structure();
+                tree.print();
             }
             8 => {
-                // Exit the program
-                println!("Exiting program");
-                break;
-            }
-            _ => {
-                println!("Invalid choice");
-            }
-        }
-    }
-}
-
-fn RBTree() {
-    let mut rbTree = RedBlackTree::new();
-    loop {
-        // Print the menu of available options
-        println!("\nRedBalck Tree Operations (type the number):");
-        println!("1. Insert a node");
-        println!("2. Delete a node");
-        println!("3. Count the number of leaves");
-        println!("4. Return the height of the tree");
-        println!("5. Print in order traversal");
-        println!("6. Check if the tree is empty");
-        println!("7. Print the tree structure");
-        println!("8. Exit");
-
-        // Get the user's choice
-        let mut choice = String::new();
-        io::stdin()
-            .read_line(&mut choice)
-            .expect("Failed to read line");
-        let choice: u32 = match choice.trim().parse() {
-            Ok(num) => num,
-            Err(_) => {
-                println!("Invalid input, please enter a number");
-                continue;
+                // Save the tree so a later session can load it
+                let path = read_path();
+                match tree.save_to(Path::new(&path)) {
+                    Ok(()) => println!("Saved {} values to {}", tree.len(), path),
+                    Err(err) => println!("Could not save to {}: {}", path, err),
+                }
             }
-        };
-
-        // Call the appropriate function based on the user's choice
-        match choice {
-            1 => {
-                // Get the value to insert from the user
-                println!("Enter a value to insert:");
-                let mut value = String::new();
-                io::stdin()
-                    .read_line(&mut value)
-                    .expect("Failed to read line");
-                let value: u32 = match value.trim().parse() {
-                    Ok(num) => num,
-                    Err(_) => {
-                        println!("Invalid input, please enter a number");
-                        continue;
-                    }
-                };
-
-                // Insert the value into the RedBlack tree
-                rbTree.tree_insert(value);
-                println!("Value {} inserted successfully", value);
+            9 => {
+                // Replace the tree with one saved earlier, a file saved from any tree type loads.
+                // A B-tree keeps the order it was made with.
+                let path = read_path();
+                match tree.reload_from(Path::new(&path)) {
+                    Ok(()) => println!("Loaded {} values from {}", tree.len(), path),
+                    Err(err) => println!("Could not load {}: {}", path, err),
+                }
             }
-            2 => {
-                // Get the value to delete from the user
-                println!("Enter a value to delete:");
-                let mut value = String::new();
-                io::stdin()
-                    .read_line(&mut value)
-                    .expect("Failed to read line");
-                let value: u32 = match value.trim().parse() {
-                    Ok(num) => num,
-                    Err(_) => {
-                        println!("Invalid input, please enter a number");
-                        continue;
+            10 => {
+                // Show how well the tree is shaped
+                match tree.stats() {
+                    Some(stats) => {
+                        println!("Nodes: {}, leaves: {}", stats.nodes, stats.leaves);
+                        println!(
+                            "Leaf depth: {} to {}, average {:.2}",
+                            stats.min_leaf_depth, stats.max_leaf_depth, stats.average_leaf_depth
+                        );
+                        println!("Internal path length: {}", stats.internal_path_length);
+                        println!(
+                            "Height: {}, optimal {}, ratio {:.2}",
+                            stats.height,
+                            stats.optimal_height(),
+                            stats.height_ratio()
+                        );
+                        if let Some(black_height) = stats.black_height {
+                            println!("Black height: {}", black_height);
+                        }
+                        if let Some(balance_factors) = &stats.balance_factors {
+                            println!("Balance factors (left height minus right height):");
+                            for (balance, count) in balance_factors {
+                                println!("  {:>2}: {}", balance, count);
+                            }
+                        }
                     }
-                };
-
-                // Delete the value from the RedBlack tree
-                rbTree.delete(value);
-                println!("{} deleted from tree", value);
-            }
-            3 => {
-                // Count the number of leaves in the RedBlack tree
-                let num_leaves = rbTree.count_leaves();
-                println!("Number of leaves in the tree: {}", num_leaves);
-            }
-            4 => {
-                // Get the height of the RedBlack tree
-                let height = rbTree.get_height();
-                println!("Height of the tree: {}", height);
-            }
-            5 => {
-                // Print the RedBlack tree in order traversal
-                println!("In order traversal of the tree:");
-                rbTree.in_order_traversal();
-            }
-            6 => {
-                // Check if the RedBlack tree is empty
-                if rbTree.is_empty() {
-                    println!("The tree is empty");
-                } else {
-                    println!("The tree is not empty");
+                    None => println!("A {} is not a binary tree", S::NAME),
                 }
             }
-            7 => {
-                // Print the RedBlack tree structure
-                println!("Structure of the tree:");
-                rbTree.print_tree();
-            }
-            8 => {
+            11 => {
                 // Exit the program
                 println!("Exiting program");
                 break;
@@ -225,7 +202,10 @@ pub fn interface() {
     println!("Please indicate what kind of tree you would like to build (type the number): ");
     println!("1. Red-Black Tree");
     println!("2. AVL Tree");
-    println!("3. Exit");
+    println!("3. B-Tree");
+    println!("4. Treap");
+    println!("5. Skip List");
+    println!("6. Exit");
 
     // Get the user's choice
     let mut choice = String::new();
@@ -243,13 +223,22 @@ pub fn interface() {
 
     match choice {
         1 => {
-            RBTree();
+            tree_menu::<RedBlackTree<i32>>(RedBlackTree::new());
         }
         2 => {
-            avlTree();
+            tree_menu::<AVLTree<i32>>(AVLTree::new());
         }
-        _ => {
-            return;
+        3 => {
+            if let Some(order) = read_order() {
+                tree_menu::<BTree<i32>>(BTree::with_order(order));
+            }
+        }
+        4 => {
+            tree_menu::<Treap<i32>>(Treap::new());
+        }
+        5 => {
+            tree_menu::<SkipList<i32>>(SkipList::new());
         }
+        _ => {}
     }
 }
@@ -0,0 +1,81 @@
+use crate::avl::AVLTree;
+use crate::ordered_set::OrderedSet;
+use crate::rb::RedBlackTree;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+// A tree that can be shared between threads, for read-mostly use: any number of threads can look
+// things up at the same time, while inserts and removes take turns and wait for the readers to
+// finish. Share it with Arc, or borrow it from std::thread::scope.
+//
+// The trees keep their nodes in an arena and link them by index (see arena.rs), so they are already
+// Send and Sync whenever their keys and values are. What they lack is a way to change them from
+// several threads, which is what the RwLock adds. A reader never sees a half finished rebalance: a
+// write holds the lock until the tree is valid again.
+//
+// read() and write() hand out the tree itself for anything the helpers below do not cover. A
+// thread that panics while it holds write() may leave the tree half changed, so every later
+// lock panics too instead of handing that tree out.
+#[derive(Debug, Default)]
+pub struct ConcurrentTree<S> {
+    tree: RwLock<S>,
+}
+
+pub type ConcurrentAVLTree<T> = ConcurrentTree<AVLTree<T>>;
+pub type ConcurrentRedBlackTree<K, V = ()> = ConcurrentTree<RedBlackTree<K, V>>;
+
+impl<S> ConcurrentTree<S> {
+    pub fn new(tree: S) -> Self {
+        ConcurrentTree {
+            tree: RwLock::new(tree),
+        }
+    }
+
+    // Shared access, waits while a writer holds the tree
+    pub fn read(&self) -> RwLockReadGuard<'_, S> {
+        self.tree
+            .read()
+            .expect("a writer panicked while changing the tree")
+    }
+
+    // Exclusive access, waits until every reader and the previous writer are done
+    pub fn write(&self) -> RwLockWriteGuard<'_, S> {
+        self.tree
+            .write()
+            .expect("a writer panicked while changing the tree")
+    }
+
+    pub fn into_inner(self) -> S {
+        self.tree
+            .into_inner()
+            .expect("a writer panicked while changing the tree")
+    }
+
+    // True if the value was not in the tree yet
+    pub fn insert<T: Ord>(&self, value: T) -> bool
+    where
+        S: OrderedSet<T>,
+    {
+        self.write().insert(value)
+    }
+
+    // True if the value was in the tree
+    pub fn remove<T: Ord>(&self, value: &T) -> bool
+    where
+        S: OrderedSet<T>,
+    {
+        self.write().remove(value)
+    }
+
+    pub fn contains<T: Ord>(&self, value: &T) -> bool
+    where
+        S: OrderedSet<T>,
+    {
+        self.read().contains(value)
+    }
+}
+
+impl<S> From<S> for ConcurrentTree<S> {
+    fn from(tree: S) -> Self {
+        ConcurrentTree::new(tree)
+    }
+}
@@ -0,0 +1,53 @@
+use std::fmt::Debug;
+use std::io;
+
+// Helpers shared by the Graphviz DOT and JSON exporters of the trees. Keys are written in their
+// Debug format, the same as print_tree and print_structure show them.
+
+// Runs an exporter against a buffer and hands back what it wrote
+pub fn to_string<F: FnOnce(&mut Vec<u8>) -> io::Result<()>>(write: F) -> String {
+    let mut out = Vec::new();
+    write(&mut out).expect("writing to a Vec can not fail");
+    String::from_utf8(out).expect("exporters only write UTF-8")
+}
+
+// The key as a quoted DOT string
+pub fn dot_string<K: Debug>(key: &K) -> String {
+    let mut quoted = String::from("\"");
+    for c in format!("{:?}", key).chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '\n' => quoted.push_str("\\n"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// The key as a JSON string. JSON can not hold an arbitrary Rust value, so the key goes in as the
+// text of its Debug format.
+pub fn json_string<K: Debug>(key: &K) -> String {
+    let mut quoted = String::from("\"");
+    for c in format!("{:?}", key).chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// Indentation for a line at the given nesting depth of the JSON output
+pub fn pad(depth: usize) -> String {
+    "  ".repeat(depth)
+}
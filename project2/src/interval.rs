@@ -0,0 +1,387 @@
+use crate::arena::{Arena, NodeId};
+use crate::invariant::{Invariant, InvariantViolation};
+use crate::map::EntryMap;
+use crate::rb::{RedBlackTree, TreeNode};
+use std::fmt::Debug;
+use std::iter::FusedIterator;
+use std::mem;
+
+// Interval tree: a red-black tree of closed intervals [start, end], ordered by start and then by
+// end, that is also a priority search tree. Besides its own interval every node has room to keep
+// one interval from its subtree, and the kept intervals form a heap on their end points: nothing
+// kept below a node ends later than the interval the node keeps. An interval is kept by a node on
+// the way from the root down to its own node, or waits at its own node when every node on that
+// way keeps one that ends later.
+//
+// A query [a, b] walks down from the root, leaves a subtree as soon as the interval kept at its top
+// ends before a, and only goes right of nodes that start at or before b. Every node it visits
+// keeps or holds an answer, hangs right below one that does, or lies on the path down to where b
+// would go, so a query with k answers takes O(log n + k). The answers come in no particular order.
+//
+// The balancing is the red-black tree's own (rb.rs). After each rotation its hook puts the kept
+// intervals of the two rotated nodes back in place with at most two walks down the tree, and an
+// insert or remove rotates at most three times, so both stay O(log n).
+#[derive(Debug)]
+pub struct IntervalTree<T> {
+    // keyed by (start, end), the value of a node is what it keeps for the heap
+    tree: RedBlackTree<(T, T), Slot>,
+}
+
+// What a node keeps besides its own interval. Intervals are named by the node that holds them as
+// its key.
+#[derive(Clone, Copy, Debug, Default)]
+struct Slot {
+    // the interval from the subtree kept here, None only if nothing below is kept or waiting
+    kept: Option<NodeId>,
+    // the node's own interval is not kept anywhere and waits here
+    waiting: bool,
+}
+
+type Nodes<T> = Arena<TreeNode<(T, T), Slot>>;
+
+fn end_of<T>(nodes: &Nodes<T>, interval: NodeId) -> &T {
+    &nodes[interval].key.1
+}
+
+// The child of `at` on the way down to the node of `interval`, which is below `at`
+fn towards<T: Ord>(nodes: &Nodes<T>, at: NodeId, interval: NodeId) -> NodeId {
+    let child = if nodes[interval].key < nodes[at].key {
+        nodes[at].left
+    } else {
+        nodes[at].right
+    };
+    child.expect("the interval's own node is below")
+}
+
+// Keeps the interval somewhere in the subtree of `at`, which holds its node. An interval that is
+// pushed out of a node by a later ending one moves on down towards its own node.
+fn sift_down<T: Ord>(nodes: &mut Nodes<T>, mut at: NodeId, mut interval: NodeId) {
+    loop {
+        match nodes[at].value.kept {
+            None => {
+                nodes[at].value.kept = Some(interval);
+                return;
+            }
+            Some(kept) => {
+                if end_of(nodes, interval) > end_of(nodes, kept) {
+                    nodes[at].value.kept = Some(interval);
+                    interval = kept;
+                }
+            }
+        }
+        if interval == at {
+            nodes[at].value.waiting = true;
+            return;
+        }
+        at = towards(nodes, at, interval);
+    }
+}
+
+// Refills a node whose kept interval has gone with the latest ending of its waiting interval and
+// what its children keep. A child that gives up its interval is refilled in turn.
+fn pull_up<T: Ord>(nodes: &mut Nodes<T>, mut at: NodeId) {
+    loop {
+        let node = &nodes[at];
+        let own = node.value.waiting.then_some((None, at));
+        let below = [node.left, node.right]
+            .into_iter()
+            .flatten()
+            .filter_map(|child| nodes[child].value.kept.map(|kept| (Some(child), kept)));
+        let best = own
+            .into_iter()
+            .chain(below)
+            .max_by(|a, b| end_of(nodes, a.1).cmp(end_of(nodes, b.1)));
+        nodes[at].value.kept = best.map(|(_, interval)| interval);
+        match best {
+            Some((Some(child), _)) => at = child,
+            Some((None, _)) => {
+                nodes[at].value.waiting = false;
+                return;
+            }
+            None => return,
+        }
+    }
+}
+
+// Takes the interval out of the heap, from the node that keeps it or from its own node
+fn take_out<T: Ord>(nodes: &mut Nodes<T>, root: NodeId, interval: NodeId) {
+    let mut at = root;
+    loop {
+        if nodes[at].value.kept == Some(interval) {
+            return pull_up(nodes, at);
+        }
+        if at == interval {
+            nodes[at].value.waiting = false;
+            return;
+        }
+        at = towards(nodes, at, interval);
+    }
+}
+
+// The rotation hook: `up` took the place of `down`, which now hangs below it. What `down` kept is
+// the latest ending interval of the whole subtree, so it stays at the top. What `up` kept goes back
+// down on its own side, and `down`, whose subtree has changed, is refilled.
+fn rotated<T: Ord>(nodes: &mut Nodes<T>, up: NodeId, down: NodeId) {
+    let top = nodes[down].value.kept.take();
+    let displaced = mem::replace(&mut nodes[up].value.kept, top);
+    let mut moved = None;
+    if let Some(interval) = displaced {
+        if interval == up {
+            nodes[up].value.waiting = true;
+        } else {
+            let child = towards(nodes, up, interval);
+            if child == down {
+                moved = Some(interval);
+            } else {
+                sift_down(nodes, child, interval);
+            }
+        }
+    }
+    pull_up(nodes, down);
+    if let Some(interval) = moved {
+        sift_down(nodes, down, interval);
+    }
+}
+
+fn subtree_min<T>(nodes: &Nodes<T>, mut node: NodeId) -> NodeId {
+    while let Some(left) = nodes[node].left {
+        node = left;
+    }
+    node
+}
+
+impl<T: Ord + Clone> IntervalTree<T> {
+    pub fn new() -> Self {
+        IntervalTree {
+            tree: RedBlackTree::with_rotation_hook(rotated),
+        }
+    }
+
+    // Adds the interval [start, end] and returns true, or false if it was in the tree already.
+    // Panics if the interval ends before it starts.
+    pub fn insert_interval(&mut self, start: T, end: T) -> bool {
+        assert!(start <= end, "an interval cannot end before it starts");
+        let interval = (start, end);
+        if self.tree.find_node(&interval).is_some() {
+            return false;
+        }
+        let node = EntryMap::insert_node(&mut self.tree, interval, Slot::default());
+        // the new node is in place and balanced, its interval goes into the heap from the top
+        let (nodes, root) = self.tree.nodes_mut();
+        sift_down(nodes, root.expect("the tree holds the new interval"), node);
+        true
+    }
+
+    // Removes the interval [start, end], false if it was not in the tree
+    pub fn remove_interval(&mut self, start: &T, end: &T) -> bool {
+        let node = match self.tree.find_node(&(start.clone(), end.clone())) {
+            Some(node) => node,
+            None => return false,
+        };
+        let (nodes, root) = self.tree.nodes_mut();
+        let root = root.expect("the tree holds the interval");
+        // a node with two children trades its interval for its successor's and the successor's
+        // node is taken out instead (rb.rs delete_node), so both intervals leave the heap first
+        let successor = match (nodes[node].left, nodes[node].right) {
+            (Some(_), Some(right)) => Some(subtree_min(nodes, right)),
+            _ => None,
+        };
+        take_out(nodes, root, node);
+        if let Some(successor) = successor {
+            take_out(nodes, root, successor);
+        }
+        let (_, slot) = EntryMap::remove_node(&mut self.tree, node);
+        // the successor's interval now sits in `node`, and whatever the node taken out kept has
+        // lost its place
+        let (nodes, root) = self.tree.nodes_mut();
+        if let Some(root) = root {
+            let homeless = successor.map(|_| node).into_iter().chain(slot.kept);
+            for interval in homeless {
+                sift_down(nodes, root, interval);
+            }
+        }
+        true
+    }
+
+    pub fn contains_interval(&self, start: &T, end: &T) -> bool {
+        self.tree.contains(&(start.clone(), end.clone()))
+    }
+
+    // The intervals containing the point, in no particular order
+    pub fn overlapping<'a>(&'a self, point: &'a T) -> Overlapping<'a, T> {
+        self.overlapping_range(point, point)
+    }
+
+    // The intervals sharing at least one point with [start, end], in no particular order. Nothing
+    // overlaps a range that ends before it starts.
+    pub fn overlapping_range<'a>(&'a self, start: &'a T, end: &'a T) -> Overlapping<'a, T> {
+        let (nodes, root) = self.tree.nodes();
+        Overlapping {
+            nodes,
+            stack: root.filter(|_| start <= end).into_iter().collect(),
+            waiting: None,
+            start,
+            end,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    pub fn get_height(&self) -> i32 {
+        self.tree.get_height()
+    }
+
+    // Every interval in order, as (start, end)
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&T, &T)> + ExactSizeIterator + '_ {
+        self.tree.keys().map(|(start, end)| (start, end))
+    }
+}
+
+impl<T: Ord + Clone + Debug> IntervalTree<T> {
+    // Checks the red-black rules and that the kept intervals form a priority search heap
+    pub fn validate(&self) -> Result<(), InvariantViolation> {
+        self.tree.validate()?;
+        let (nodes, root) = self.tree.nodes();
+        let root = match root {
+            Some(root) => root,
+            None => return Ok(()),
+        };
+        let violation =
+            |node: NodeId| InvariantViolation::new(Invariant::PrioritySearch, &nodes[node].key);
+        let mut all = vec![root];
+        let mut next = 0;
+        while let Some(&id) = all.get(next) {
+            all.extend(nodes[id].left);
+            all.extend(nodes[id].right);
+            next += 1;
+        }
+        // every interval is kept or waiting exactly once, on the way down to its own node
+        let mut places = 0;
+        for &id in &all {
+            let node = &nodes[id];
+            places += usize::from(node.value.kept.is_some()) + usize::from(node.value.waiting);
+            let mut found = 0;
+            let mut at = root;
+            loop {
+                found += usize::from(nodes[at].value.kept == Some(id));
+                if at == id {
+                    found += usize::from(node.value.waiting);
+                    break;
+                }
+                at = towards(nodes, at, id);
+            }
+            if found != 1 {
+                return Err(violation(id));
+            }
+        }
+        if places != self.len() {
+            return Err(violation(root));
+        }
+        // nothing ends later than what the node above keeps, and a node that keeps nothing has
+        // nothing below it either
+        for &id in &all {
+            let node = &nodes[id];
+            let kept = node.value.kept.map(|kept| end_of(nodes, kept));
+            let below = [node.left, node.right]
+                .into_iter()
+                .flatten()
+                .map(|child| nodes[child].value.kept.map(|kept| end_of(nodes, kept)));
+            let own = node.value.waiting.then_some(Some(&node.key.1));
+            if own.into_iter().chain(below).any(|end| end > kept) {
+                return Err(violation(id));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn print_tree(&self) {
+        self.tree.print_tree();
+    }
+}
+
+impl<T: Ord + Clone> Default for IntervalTree<T> {
+    fn default() -> Self {
+        IntervalTree::new()
+    }
+}
+
+impl<T: Ord + Clone> FromIterator<(T, T)> for IntervalTree<T> {
+    fn from_iter<I: IntoIterator<Item = (T, T)>>(iter: I) -> Self {
+        let mut tree = IntervalTree::new();
+        tree.extend(iter);
+        tree
+    }
+}
+
+impl<T: Ord + Clone> Extend<(T, T)> for IntervalTree<T> {
+    fn extend<I: IntoIterator<Item = (T, T)>>(&mut self, iter: I) {
+        for (start, end) in iter {
+            self.insert_interval(start, end);
+        }
+    }
+}
+
+// ---------------------------------------- Iterators ------------------------------------------------
+// Walk over the intervals overlapping [start, end] that only enters a subtree whose top keeps an
+// interval ending at or after the start. The stack holds the nodes still to visit.
+pub struct Overlapping<'a, T> {
+    nodes: &'a Nodes<T>,
+    stack: Vec<NodeId>,
+    // an answer waiting at the node visited last, handed out by the next call
+    waiting: Option<NodeId>,
+    start: &'a T,
+    end: &'a T,
+}
+
+impl<'a, T: Ord> Overlapping<'a, T> {
+    fn overlaps(&self, interval: NodeId) -> bool {
+        let (start, end) = &self.nodes[interval].key;
+        start <= self.end && end >= self.start
+    }
+
+    fn interval(&self, interval: NodeId) -> (&'a T, &'a T) {
+        let (start, end) = &self.nodes[interval].key;
+        (start, end)
+    }
+}
+
+impl<'a, T: Ord> Iterator for Overlapping<'a, T> {
+    type Item = (&'a T, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(interval) = self.waiting.take() {
+            return Some(self.interval(interval));
+        }
+        while let Some(id) = self.stack.pop() {
+            let node = &self.nodes[id];
+            // nothing in this subtree ends later than what its top keeps
+            let kept = match node.value.kept {
+                Some(kept) if end_of(self.nodes, kept) >= self.start => kept,
+                _ => continue,
+            };
+            self.stack.extend(node.left);
+            // everything on the right starts at or after this node's interval
+            if node.key.0 <= *self.end {
+                self.stack.extend(node.right);
+            }
+            if node.value.waiting && self.overlaps(id) {
+                self.waiting = Some(id);
+            }
+            if self.overlaps(kept) {
+                return Some(self.interval(kept));
+            }
+            if let Some(interval) = self.waiting.take() {
+                return Some(self.interval(interval));
+            }
+        }
+        None
+    }
+}
+
+impl<T: Ord> FusedIterator for Overlapping<'_, T> {}
@@ -0,0 +1,83 @@
+use std::error::Error;
+use std::fmt;
+
+// The rules the trees check in validate(), not every rule applies to every tree
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Invariant {
+    // keys on the left of a node are smaller than its key and keys on the right are larger
+    Order,
+    // every child points back at the node it hangs under, and the root has no parent
+    ParentPointer,
+    // the cached height is one more than the taller child's
+    Height,
+    // the cached subtree size is one more than the sizes of both children added up
+    Size,
+    // the root of a red-black tree is black
+    RootColor,
+    // a red node has no red children
+    RedRed,
+    // every path from a node down to an empty child passes the same number of black nodes
+    BlackHeight,
+    // the heights of the two children of an AVL node differ by at most one
+    Balance,
+    // a B-tree node holds between the minimum and maximum number of keys for the order, and an inner
+    // node has one more child than keys
+    KeyCount,
+    // every leaf of a B-tree is at the same depth
+    LeafDepth,
+    // no treap node has a higher priority than its parent
+    Priority,
+    // the links of a skip list agree: each level is a sublist of the one below it holding exactly the
+    // towers that reach it, the backward links and the tail match the bottom level, and the top level
+    // is not empty
+    Links,
+    // every interval of an interval tree is kept exactly once, in a node on the way from the root
+    // down to its own node, and nothing kept below a node ends later than what the node keeps
+    PrioritySearch,
+}
+
+impl fmt::Display for Invariant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rule = match self {
+            Invariant::Order => "keys out of order",
+            Invariant::ParentPointer => "parent pointer does not match",
+            Invariant::Height => "cached height is wrong",
+            Invariant::Size => "cached subtree size is wrong",
+            Invariant::RootColor => "root is not black",
+            Invariant::RedRed => "red node has a red child",
+            Invariant::BlackHeight => "black heights of the children differ",
+            Invariant::Balance => "children heights differ by more than one",
+            Invariant::KeyCount => "node holds the wrong number of keys or children",
+            Invariant::LeafDepth => "leaves are at different depths",
+            Invariant::Priority => "priority is higher than the parent's",
+            Invariant::Links => "skip list links do not match",
+            Invariant::PrioritySearch => "interval is kept out of place",
+        };
+        write!(f, "{}", rule)
+    }
+}
+
+// Returned by validate() with the first rule found broken and the key of the node it was found at
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvariantViolation {
+    pub invariant: Invariant,
+    // the key of the offending node, formatted with Debug
+    pub node: String,
+}
+
+impl InvariantViolation {
+    pub fn new<K: fmt::Debug>(invariant: Invariant, key: &K) -> Self {
+        InvariantViolation {
+            invariant,
+            node: format!("{:?}", key),
+        }
+    }
+}
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at node {}", self.invariant, self.node)
+    }
+}
+
+impl Error for InvariantViolation {}
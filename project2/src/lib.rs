@@ -0,0 +1,41 @@
+// The trees of project2 as a library. Every tree type lives in its own module together with its
+// iterators, and the types other crates are meant to use are re-exported here, so
+// `use project2::{AVLTree, RedBlackTree}` is all a dependent crate needs. The CLI in main.rs is a
+// user of this crate like any other.
+//
+// The arena the node based trees allocate from, the iterators and entries the two binary tree maps
+// share (re-exported from avl and rb under their own names) and the DOT/JSON helpers are
+// implementation details and are not exported.
+mod arena;
+pub mod avl;
+pub mod btree;
+pub mod concurrent;
+mod export;
+pub mod interval;
+pub mod invariant;
+mod map;
+pub mod multiset;
+pub mod ordered_set;
+pub mod persistent;
+pub mod rb;
+pub mod rng;
+pub mod skiplist;
+pub mod snapshot;
+pub mod stats;
+pub mod trace;
+pub mod treap;
+
+pub use avl::{AVLMap, AVLTree};
+pub use btree::BTree;
+pub use concurrent::{ConcurrentAVLTree, ConcurrentRedBlackTree, ConcurrentTree};
+pub use interval::IntervalTree;
+pub use invariant::{Invariant, InvariantViolation};
+pub use multiset::{AVLMultiset, Multiset, RedBlackMultiset};
+pub use ordered_set::OrderedSet;
+pub use persistent::PersistentAVLMap;
+pub use rb::RedBlackTree;
+pub use skiplist::SkipList;
+pub use snapshot::{Codec, SnapshotError};
+pub use stats::TreeStats;
+pub use trace::Step;
+pub use treap::Treap;
@@ -1,7 +1,26 @@
-mod avl;
+// The command line front end, the trees themselves are in the project2 library (lib.rs)
 mod cli;
-mod rb;
+mod script;
+
+use script::Mode;
+use std::process;
 
 fn main() {
-    cli::interface();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", script::USAGE);
+        return;
+    }
+    match script::parse_args(&args) {
+        Ok(Mode::Interactive) => cli::interface(),
+        Ok(Mode::Script {
+            tree,
+            file,
+            explain,
+        }) => process::exit(script::run_from(tree, explain, file.as_deref())),
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, script::USAGE);
+            process::exit(2);
+        }
+    }
 }
@@ -0,0 +1,549 @@
+use crate::arena::{Arena, NodeId};
+use std::iter::FusedIterator;
+use std::mem;
+use std::ops::Bound;
+
+// What the two binary search tree maps, AVLMap (avl.rs) and RedBlackTree (rb.rs), have in common:
+// the in order iterators, the entry API and the serde helpers. Both keep their nodes in an arena
+// with the children as handles, so everything here works on any node that can hand out its
+// children and its entry. Each tree re-exports these types under its own names, e.g. avl::Iter is
+// Iter over avl's nodes, and a fix made here reaches both trees.
+
+// A node as the shared code sees it
+pub trait MapNode {
+    type Key;
+    type Value;
+
+    fn left(&self) -> Option<NodeId>;
+
+    fn right(&self) -> Option<NodeId>;
+
+    fn entry(&self) -> (&Self::Key, &Self::Value);
+
+    // Takes the entry out of a node that has already been freed from the arena
+    fn into_entry(self) -> (Self::Key, Self::Value);
+}
+
+// ---------------------------------------- Iterators ------------------------------------------------
+// In order iterator that can be walked from both ends. Each end keeps the spine of nodes it still
+// has to visit, and `remaining` stops the two ends from running past each other.
+pub struct Iter<'a, N> {
+    nodes: &'a Arena<N>,
+    front: Vec<NodeId>,
+    back: Vec<NodeId>,
+    remaining: usize,
+}
+
+impl<'a, N: MapNode> Iter<'a, N> {
+    pub(crate) fn new(nodes: &'a Arena<N>, root: Option<NodeId>, len: usize) -> Self {
+        let mut iter = Iter {
+            nodes,
+            front: Vec::new(),
+            back: Vec::new(),
+            remaining: len,
+        };
+        iter.push_left_spine(root);
+        iter.push_right_spine(root);
+        iter
+    }
+
+    fn push_left_spine(&mut self, mut node: Option<NodeId>) {
+        while let Some(n) = node {
+            node = self.nodes[n].left();
+            self.front.push(n);
+        }
+    }
+
+    fn push_right_spine(&mut self, mut node: Option<NodeId>) {
+        while let Some(n) = node {
+            node = self.nodes[n].right();
+            self.back.push(n);
+        }
+    }
+}
+
+impl<'a, N: MapNode> Iterator for Iter<'a, N> {
+    type Item = (&'a N::Key, &'a N::Value);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let node = self.front.pop()?;
+        self.push_left_spine(self.nodes[node].right());
+        self.remaining -= 1;
+        Some(self.nodes[node].entry())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, N: MapNode> DoubleEndedIterator for Iter<'a, N> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let node = self.back.pop()?;
+        self.push_right_spine(self.nodes[node].left());
+        self.remaining -= 1;
+        Some(self.nodes[node].entry())
+    }
+}
+
+impl<'a, N: MapNode> ExactSizeIterator for Iter<'a, N> {}
+
+impl<'a, N: MapNode> FusedIterator for Iter<'a, N> {}
+
+// Iterator over the entries whose keys fall in a range. `front` and `back` start out at the two
+// ends of the range, once the node on top of one stack has passed the other the range is used up.
+pub struct Range<'a, N> {
+    nodes: &'a Arena<N>,
+    front: Vec<NodeId>,
+    back: Vec<NodeId>,
+}
+
+impl<'a, N: MapNode> Range<'a, N>
+where
+    N::Key: Ord,
+{
+    pub(crate) fn new(
+        nodes: &'a Arena<N>,
+        root: Option<NodeId>,
+        start: Bound<&N::Key>,
+        end: Bound<&N::Key>,
+    ) -> Self {
+        let mut range = Range {
+            nodes,
+            front: Vec::new(),
+            back: Vec::new(),
+        };
+        // Only nodes at or after the start are kept for the front, going left after every kept one
+        let mut node = root;
+        while let Some(n) = node {
+            let node_ref = &nodes[n];
+            let key = node_ref.entry().0;
+            let after_start = match start {
+                Bound::Included(start) => key >= start,
+                Bound::Excluded(start) => key > start,
+                Bound::Unbounded => true,
+            };
+            if after_start {
+                range.front.push(n);
+                node = node_ref.left();
+            } else {
+                node = node_ref.right();
+            }
+        }
+        // And only nodes at or before the end for the back
+        let mut node = root;
+        while let Some(n) = node {
+            let node_ref = &nodes[n];
+            let key = node_ref.entry().0;
+            let before_end = match end {
+                Bound::Included(end) => key <= end,
+                Bound::Excluded(end) => key < end,
+                Bound::Unbounded => true,
+            };
+            if before_end {
+                range.back.push(n);
+                node = node_ref.right();
+            } else {
+                node = node_ref.left();
+            }
+        }
+        range
+    }
+
+    // Hands out the next node from either end, or None once the ends have crossed
+    fn take(&mut self, from_front: bool) -> Option<NodeId> {
+        let front = *self.front.last()?;
+        let back = *self.back.last()?;
+        if self.nodes[front].entry().0 > self.nodes[back].entry().0 {
+            self.front.clear();
+            self.back.clear();
+            return None;
+        }
+        if front == back {
+            // the last entry in the range
+            self.front.clear();
+            self.back.clear();
+            return Some(front);
+        }
+        if from_front {
+            self.front.pop();
+            let mut node = self.nodes[front].right();
+            while let Some(n) = node {
+                node = self.nodes[n].left();
+                self.front.push(n);
+            }
+            Some(front)
+        } else {
+            self.back.pop();
+            let mut node = self.nodes[back].left();
+            while let Some(n) = node {
+                node = self.nodes[n].right();
+                self.back.push(n);
+            }
+            Some(back)
+        }
+    }
+}
+
+impl<'a, N: MapNode> Iterator for Range<'a, N>
+where
+    N::Key: Ord,
+{
+    type Item = (&'a N::Key, &'a N::Value);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let nodes = self.nodes;
+        self.take(true).map(|node| nodes[node].entry())
+    }
+}
+
+impl<'a, N: MapNode> DoubleEndedIterator for Range<'a, N>
+where
+    N::Key: Ord,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let nodes = self.nodes;
+        self.take(false).map(|node| nodes[node].entry())
+    }
+}
+
+impl<'a, N: MapNode> FusedIterator for Range<'a, N> where N::Key: Ord {}
+
+pub struct Keys<'a, N> {
+    pub(crate) inner: Iter<'a, N>,
+}
+
+impl<'a, N: MapNode> Iterator for Keys<'a, N> {
+    type Item = &'a N::Key;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(key, _)| key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, N: MapNode> DoubleEndedIterator for Keys<'a, N> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(key, _)| key)
+    }
+}
+
+impl<'a, N: MapNode> ExactSizeIterator for Keys<'a, N> {}
+
+impl<'a, N: MapNode> FusedIterator for Keys<'a, N> {}
+
+pub struct Values<'a, N> {
+    pub(crate) inner: Iter<'a, N>,
+}
+
+impl<'a, N: MapNode> Iterator for Values<'a, N> {
+    type Item = &'a N::Value;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(_, value)| value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, N: MapNode> DoubleEndedIterator for Values<'a, N> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(_, value)| value)
+    }
+}
+
+impl<'a, N: MapNode> ExactSizeIterator for Values<'a, N> {}
+
+impl<'a, N: MapNode> FusedIterator for Values<'a, N> {}
+
+// Owning iterator, the map is taken apart in order up front
+pub struct IntoIter<K, V> {
+    entries: std::vec::IntoIter<(K, V)>,
+}
+
+impl<K, V> IntoIter<K, V> {
+    pub(crate) fn new<N>(mut nodes: Arena<N>, root: Option<NodeId>) -> Self
+    where
+        N: MapNode<Key = K, Value = V>,
+    {
+        let mut entries = Vec::with_capacity(nodes.len());
+        // in order walk that frees every node once it has been passed
+        let mut stack = Vec::new();
+        let mut node = root;
+        loop {
+            while let Some(n) = node {
+                stack.push(n);
+                node = nodes[n].left();
+            }
+            match stack.pop() {
+                Some(n) => {
+                    let freed = nodes.free(n);
+                    node = freed.right();
+                    entries.push(freed.into_entry());
+                }
+                None => break,
+            }
+        }
+        IntoIter {
+            entries: entries.into_iter(),
+        }
+    }
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IntoIter<K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.entries.next_back()
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {}
+
+impl<K, V> FusedIterator for IntoIter<K, V> {}
+
+pub struct IntoKeys<K, V> {
+    pub(crate) inner: IntoIter<K, V>,
+}
+
+impl<K, V> Iterator for IntoKeys<K, V> {
+    type Item = K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(key, _)| key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IntoKeys<K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(key, _)| key)
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoKeys<K, V> {}
+
+impl<K, V> FusedIterator for IntoKeys<K, V> {}
+
+pub struct IntoValues<K, V> {
+    pub(crate) inner: IntoIter<K, V>,
+}
+
+impl<K, V> Iterator for IntoValues<K, V> {
+    type Item = V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(_, value)| value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IntoValues<K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(_, value)| value)
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoValues<K, V> {}
+
+impl<K, V> FusedIterator for IntoValues<K, V> {}
+
+// ---------------------------------------- Entry ------------------------------------------------
+// What a map does for its entries, each tree implements it with its own search, insert and remove
+pub trait EntryMap {
+    type Key: Ord;
+    type Value;
+
+    fn find_node(&self, key: &Self::Key) -> Option<NodeId>;
+
+    // Hangs a new node into the map, the key is not in it yet
+    fn insert_node(&mut self, key: Self::Key, value: Self::Value) -> NodeId;
+
+    fn remove_node(&mut self, node: NodeId) -> (Self::Key, Self::Value);
+
+    fn node_key(&self, node: NodeId) -> &Self::Key;
+
+    fn node_value(&self, node: NodeId) -> &Self::Value;
+
+    fn node_value_mut(&mut self, node: NodeId) -> &mut Self::Value;
+}
+
+// The entry for the key, found with a single search
+pub(crate) fn entry<M: EntryMap>(map: &mut M, key: M::Key) -> Entry<'_, M> {
+    match map.find_node(&key) {
+        Some(node) => Entry::Occupied(OccupiedEntry { node, map }),
+        None => Entry::Vacant(VacantEntry { key, map }),
+    }
+}
+
+// Mirrors std::collections::btree_map::Entry
+pub enum Entry<'a, M: EntryMap> {
+    Vacant(VacantEntry<'a, M>),
+    Occupied(OccupiedEntry<'a, M>),
+}
+
+pub struct VacantEntry<'a, M: EntryMap> {
+    key: M::Key,
+    map: &'a mut M,
+}
+
+pub struct OccupiedEntry<'a, M: EntryMap> {
+    node: NodeId,
+    map: &'a mut M,
+}
+
+impl<'a, M: EntryMap> Entry<'a, M> {
+    pub fn key(&self) -> &M::Key {
+        match self {
+            Entry::Vacant(entry) => entry.key(),
+            Entry::Occupied(entry) => entry.key(),
+        }
+    }
+
+    pub fn or_insert(self, default: M::Value) -> &'a mut M::Value {
+        match self {
+            Entry::Vacant(entry) => entry.insert(default),
+            Entry::Occupied(entry) => entry.into_mut(),
+        }
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> M::Value>(self, default: F) -> &'a mut M::Value {
+        match self {
+            Entry::Vacant(entry) => entry.insert(default()),
+            Entry::Occupied(entry) => entry.into_mut(),
+        }
+    }
+
+    pub fn or_insert_with_key<F: FnOnce(&M::Key) -> M::Value>(
+        self,
+        default: F,
+    ) -> &'a mut M::Value {
+        match self {
+            Entry::Vacant(entry) => {
+                let value = default(&entry.key);
+                entry.insert(value)
+            }
+            Entry::Occupied(entry) => entry.into_mut(),
+        }
+    }
+
+    pub fn and_modify<F: FnOnce(&mut M::Value)>(self, f: F) -> Self {
+        match self {
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
+            }
+        }
+    }
+}
+
+impl<'a, M: EntryMap> Entry<'a, M>
+where
+    M::Value: Default,
+{
+    pub fn or_default(self) -> &'a mut M::Value {
+        self.or_insert_with(M::Value::default)
+    }
+}
+
+impl<'a, M: EntryMap> VacantEntry<'a, M> {
+    pub fn key(&self) -> &M::Key {
+        &self.key
+    }
+
+    pub fn into_key(self) -> M::Key {
+        self.key
+    }
+
+    pub fn insert(self, value: M::Value) -> &'a mut M::Value {
+        let map = self.map;
+        let node = map.insert_node(self.key, value);
+        map.node_value_mut(node)
+    }
+}
+
+impl<'a, M: EntryMap> OccupiedEntry<'a, M> {
+    pub fn key(&self) -> &M::Key {
+        self.map.node_key(self.node)
+    }
+
+    pub fn get(&self) -> &M::Value {
+        self.map.node_value(self.node)
+    }
+
+    pub fn get_mut(&mut self) -> &mut M::Value {
+        self.map.node_value_mut(self.node)
+    }
+
+    pub fn into_mut(self) -> &'a mut M::Value {
+        let map = self.map;
+        map.node_value_mut(self.node)
+    }
+
+    pub fn insert(&mut self, value: M::Value) -> M::Value {
+        mem::replace(self.get_mut(), value)
+    }
+
+    pub fn remove_entry(self) -> (M::Key, M::Value) {
+        self.map.remove_node(self.node)
+    }
+
+    pub fn remove(self) -> M::Value {
+        self.remove_entry().1
+    }
+}
+
+// ---------------------------------------- Serde ------------------------------------------------
+// With the serde feature a map is written as the sequence of its (key, value) pairs in key order
+// and read back with a bulk build
+#[cfg(feature = "serde")]
+pub(crate) fn serialize<'a, K, V, I, S>(entries: I, serializer: S) -> Result<S::Ok, S::Error>
+where
+    K: serde::Serialize + 'a,
+    V: serde::Serialize + 'a,
+    I: IntoIterator<Item = (&'a K, &'a V)>,
+    S: serde::Serializer,
+{
+    serializer.collect_seq(entries)
+}
+
+#[cfg(feature = "serde")]
+pub(crate) fn deserialize<'de, K, V, M, D>(deserializer: D) -> Result<M, D::Error>
+where
+    K: serde::Deserialize<'de>,
+    V: serde::Deserialize<'de>,
+    M: FromIterator<(K, V)>,
+    D: serde::Deserializer<'de>,
+{
+    // collect sorts the entries first, which is a single pass when they are already in order
+    let entries: Vec<(K, V)> = serde::Deserialize::deserialize(deserializer)?;
+    Ok(entries.into_iter().collect())
+}
@@ -0,0 +1,249 @@
+use crate::avl::{self, AVLMap};
+use crate::invariant::{Invariant, InvariantViolation};
+use crate::rb::{self, RedBlackTree};
+use std::fmt::Debug;
+use std::iter;
+use std::marker::PhantomData;
+
+// The parts of a tree map a Multiset needs, implemented below for RedBlackTree and AVLMap with
+// usize values
+pub trait CountMap<K> {
+    type Iter<'a>: DoubleEndedIterator<Item = (&'a K, &'a usize)>
+    where
+        Self: 'a,
+        K: 'a;
+
+    fn new() -> Self;
+    fn get(&self, key: &K) -> Option<&usize>;
+    fn get_mut(&mut self, key: &K) -> Option<&mut usize>;
+    fn insert(&mut self, key: K, count: usize) -> Option<usize>;
+    fn remove(&mut self, key: &K) -> Option<usize>;
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    fn iter(&self) -> Self::Iter<'_>;
+    fn validate(&self) -> Result<(), InvariantViolation>
+    where
+        K: Debug;
+}
+
+impl<K: Ord> CountMap<K> for RedBlackTree<K, usize> {
+    type Iter<'a>
+        = rb::Iter<'a, K, usize>
+    where
+        K: 'a;
+
+    fn new() -> Self {
+        RedBlackTree::new()
+    }
+
+    fn get(&self, key: &K) -> Option<&usize> {
+        RedBlackTree::get(self, key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut usize> {
+        RedBlackTree::get_mut(self, key)
+    }
+
+    fn insert(&mut self, key: K, count: usize) -> Option<usize> {
+        RedBlackTree::insert(self, key, count)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<usize> {
+        RedBlackTree::remove(self, key)
+    }
+
+    fn len(&self) -> usize {
+        RedBlackTree::len(self)
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        RedBlackTree::iter(self)
+    }
+
+    fn validate(&self) -> Result<(), InvariantViolation>
+    where
+        K: Debug,
+    {
+        RedBlackTree::validate(self)
+    }
+}
+
+impl<K: Ord> CountMap<K> for AVLMap<K, usize> {
+    type Iter<'a>
+        = avl::Iter<'a, K, usize>
+    where
+        K: 'a;
+
+    fn new() -> Self {
+        AVLMap::new()
+    }
+
+    fn get(&self, key: &K) -> Option<&usize> {
+        AVLMap::get(self, key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut usize> {
+        AVLMap::get_mut(self, key)
+    }
+
+    fn insert(&mut self, key: K, count: usize) -> Option<usize> {
+        AVLMap::insert(self, key, count)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<usize> {
+        AVLMap::remove(self, key)
+    }
+
+    fn len(&self) -> usize {
+        AVLMap::len(self)
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        AVLMap::iter(self)
+    }
+
+    fn validate(&self) -> Result<(), InvariantViolation>
+    where
+        K: Debug,
+    {
+        AVLMap::validate(self)
+    }
+}
+
+// A set that keeps every copy of a value: each distinct key is stored once in the tree map M
+// together with how many times it was inserted, and leaves the tree when its last copy is removed.
+// len() counts every copy, distinct_len() every key. The red-black and AVL versions have aliases
+// below.
+#[derive(Debug)]
+pub struct Multiset<K, M> {
+    counts: M,
+    len: usize,
+    key: PhantomData<K>,
+}
+
+pub type RedBlackMultiset<K> = Multiset<K, RedBlackTree<K, usize>>;
+pub type AVLMultiset<K> = Multiset<K, AVLMap<K, usize>>;
+
+impl<K: Ord, M: CountMap<K>> Multiset<K, M> {
+    pub fn new() -> Self {
+        Multiset {
+            counts: M::new(),
+            len: 0,
+            key: PhantomData,
+        }
+    }
+
+    // Adds one copy of the key and returns true if it was not in the multiset before
+    pub fn insert(&mut self, key: K) -> bool {
+        self.len += 1;
+        match self.counts.get_mut(&key) {
+            Some(count) => {
+                *count += 1;
+                false
+            }
+            None => {
+                self.counts.insert(key, 1);
+                true
+            }
+        }
+    }
+
+    // How many copies of the key there are, 0 if it is not in the multiset
+    pub fn count(&self, key: &K) -> usize {
+        self.counts.get(key).copied().unwrap_or(0)
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.counts.get(key).is_some()
+    }
+
+    // Removes one copy of the key, returns false if there was none
+    pub fn remove_one(&mut self, key: &K) -> bool {
+        match self.counts.get_mut(key) {
+            None => return false,
+            Some(count) if *count > 1 => *count -= 1,
+            Some(_) => {
+                self.counts.remove(key);
+            }
+        }
+        self.len -= 1;
+        true
+    }
+
+    // Removes every copy of the key and returns how many there were
+    pub fn remove_all(&mut self, key: &K) -> usize {
+        let count = self.counts.remove(key).unwrap_or(0);
+        self.len -= count;
+        count
+    }
+
+    // Number of copies of all keys together
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    // Number of different keys
+    pub fn distinct_len(&self) -> usize {
+        self.counts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // The keys in order with their counts, from either end
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&K, usize)> + '_ {
+        self.counts.iter().map(|(key, count)| (key, *count))
+    }
+
+    // Every copy of every key in order, a key with a count of 3 shows up three times
+    pub fn elements(&self) -> impl Iterator<Item = &K> + '_ {
+        self.iter()
+            .flat_map(|(key, count)| iter::repeat_n(key, count))
+    }
+
+    // Checks the rules of the tree map underneath, and that every count is at least 1 and the counts
+    // add up to len()
+    pub fn validate(&self) -> Result<(), InvariantViolation>
+    where
+        K: Debug,
+    {
+        self.counts.validate()?;
+        let mut total = 0;
+        for (key, count) in self.iter() {
+            if count == 0 {
+                return Err(InvariantViolation::new(Invariant::Size, key));
+            }
+            total += count;
+        }
+        if total != self.len {
+            let last = self.iter().next_back().map(|(key, _)| key);
+            return Err(InvariantViolation::new(Invariant::Size, &last));
+        }
+        Ok(())
+    }
+}
+
+impl<K: Ord, M: CountMap<K>> Default for Multiset<K, M> {
+    fn default() -> Self {
+        Multiset::new()
+    }
+}
+
+impl<K: Ord, M: CountMap<K>> FromIterator<K> for Multiset<K, M> {
+    fn from_iter<I: IntoIterator<Item = K>>(iter: I) -> Self {
+        let mut multiset = Multiset::new();
+        multiset.extend(iter);
+        multiset
+    }
+}
+
+impl<K: Ord, M: CountMap<K>> Extend<K> for Multiset<K, M> {
+    fn extend<I: IntoIterator<Item = K>>(&mut self, iter: I) {
+        for key in iter {
+            self.insert(key);
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use crate::invariant::InvariantViolation;
+use crate::snapshot::{self, Codec, SnapshotError};
+use crate::stats::TreeStats;
+use std::collections::{btree_set, BTreeSet};
+use std::fmt::Debug;
+use std::fs;
+use std::ops::Bound;
+use std::path::Path;
+
+// The set operations every tree provides. The CLI menus, the script mode, the benches and the tests
+// are written against this trait, so a new tree type only has to implement it to be usable from all
+// of them. Each tree type implements it next to its own methods.
+//
+// Unlike the inherent methods of the trees, which follow std's collections, these all take the
+// value by reference where they only look at it and tell whether anything changed.
+pub trait OrderedSet<T: Ord> {
+    // In order iterator over the values, from either end
+    type Iter<'a>: DoubleEndedIterator<Item = &'a T>
+    where
+        Self: 'a,
+        T: 'a;
+
+    // Name of the tree type for menus and reports
+    const NAME: &'static str;
+
+    fn new() -> Self
+    where
+        Self: Sized;
+
+    // True if the value was not in the set yet
+    fn insert(&mut self, value: T) -> bool;
+
+    // True if the value was in the set
+    fn remove(&mut self, value: &T) -> bool;
+
+    fn contains(&self, value: &T) -> bool;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Number of levels, 0 for an empty set
+    fn height(&self) -> i32;
+
+    fn count_leaves(&self) -> usize;
+
+    // Shape figures of a binary tree, None for the structures that are not one
+    fn stats(&self) -> Option<TreeStats> {
+        None
+    }
+
+    fn iter(&self) -> Self::Iter<'_>;
+
+    // The values between the two bounds, in order
+    fn range<'a>(
+        &'a self,
+        start: Bound<&T>,
+        end: Bound<&T>,
+    ) -> Box<dyn DoubleEndedIterator<Item = &'a T> + 'a>;
+
+    fn first(&self) -> Option<&T> {
+        self.iter().next()
+    }
+
+    fn last(&self) -> Option<&T> {
+        self.iter().next_back()
+    }
+
+    // Number of values strictly less than the given one. The default walks the values in order, the
+    // trees answer in O(log n) from their subtree sizes.
+    fn rank(&self, value: &T) -> usize {
+        self.iter().take_while(|&other| other < value).count()
+    }
+
+    // The index-th smallest value, counting from 0
+    fn select(&self, index: usize) -> Option<&T> {
+        self.iter().nth(index)
+    }
+
+    // Checks the rules of the tree type, see the validate method of each tree
+    fn validate(&self) -> Result<(), InvariantViolation>
+    where
+        T: Debug;
+
+    // Prints the shape of the tree to stdout
+    fn print(&self)
+    where
+        T: Debug;
+
+    // Prints the values in order to stdout, one per line
+    fn print_in_order(&self)
+    where
+        T: Debug,
+    {
+        for value in self.iter() {
+            println!("{:?}", value);
+        }
+    }
+
+    // Writes the values to a binary snapshot file, see the snapshot module
+    fn save_to(&self, path: &Path) -> Result<(), SnapshotError>
+    where
+        T: Codec;
+
+    fn load_from(path: &Path) -> Result<Self, SnapshotError>
+    where
+        Self: Sized,
+        T: Codec;
+
+    // Replaces the values with the ones in a snapshot file, keeping how this tree was set up (such
+    // as the order of a B-tree) where a tree made by load_from would start from the defaults
+    fn reload_from(&mut self, path: &Path) -> Result<(), SnapshotError>
+    where
+        Self: Sized,
+        T: Codec,
+    {
+        *self = Self::load_from(path)?;
+        Ok(())
+    }
+
+    // Removes every value, keeping how the tree was set up in the same way as reload_from
+    fn clear(&mut self)
+    where
+        Self: Sized,
+    {
+        *self = Self::new();
+    }
+}
+
+// ---------------------------------------- Baseline -----------------------------------------------
+// std's BTreeSet through the same trait, so the benches and tests can run it as a baseline next to
+// the trees. It does not expose its shape, so it reports a height and leaf count of 0.
+impl<T: Ord> OrderedSet<T> for BTreeSet<T> {
+    type Iter<'a>
+        = btree_set::Iter<'a, T>
+    where
+        T: 'a;
+
+    const NAME: &'static str = "BTreeSet";
+
+    fn new() -> Self {
+        BTreeSet::new()
+    }
+
+    fn insert(&mut self, value: T) -> bool {
+        BTreeSet::insert(self, value)
+    }
+
+    fn remove(&mut self, value: &T) -> bool {
+        BTreeSet::remove(self, value)
+    }
+
+    fn contains(&self, value: &T) -> bool {
+        BTreeSet::contains(self, value)
+    }
+
+    fn len(&self) -> usize {
+        BTreeSet::len(self)
+    }
+
+    fn height(&self) -> i32 {
+        0
+    }
+
+    fn count_leaves(&self) -> usize {
+        0
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        BTreeSet::iter(self)
+    }
+
+    fn range<'a>(
+        &'a self,
+        start: Bound<&T>,
+        end: Bound<&T>,
+    ) -> Box<dyn DoubleEndedIterator<Item = &'a T> + 'a> {
+        Box::new(BTreeSet::range::<T, _>(self, (start, end)))
+    }
+
+    fn validate(&self) -> Result<(), InvariantViolation>
+    where
+        T: Debug,
+    {
+        Ok(())
+    }
+
+    fn print(&self)
+    where
+        T: Debug,
+    {
+        println!("{:?}", self);
+    }
+
+    fn save_to(&self, path: &Path) -> Result<(), SnapshotError>
+    where
+        T: Codec,
+    {
+        fs::write(
+            path,
+            snapshot::encode(self.len(), self.iter().map(|value| (value, &()))),
+        )?;
+        Ok(())
+    }
+
+    fn load_from(path: &Path) -> Result<Self, SnapshotError>
+    where
+        T: Codec,
+    {
+        let entries = snapshot::decode::<T, ()>(&fs::read(path)?)?;
+        Ok(entries.into_iter().map(|(value, ())| value).collect())
+    }
+}
@@ -0,0 +1,363 @@
+use crate::invariant::{Invariant, InvariantViolation};
+use std::cmp::Ordering;
+use std::fmt::Debug;
+use std::iter::FusedIterator;
+use std::rc::Rc;
+
+// Persistent AVL map. Nodes are never changed once they are built: insert and remove copy the nodes
+// on the path from the root down to the change and hand back a new map that shares every other
+// subtree with the old one. An update allocates O(log n) nodes and leaves the old version as it was,
+// so keeping a history of versions around (for undo) costs O(log n) per update. Cloning a map only
+// clones the root pointer.
+//
+// Unlike the other trees the nodes live behind Rc instead of in an arena, since a node can belong to
+// any number of versions at once. There are no parent links, so there are no cycles.
+type Link<K, V> = Option<Rc<Node<K, V>>>;
+
+#[derive(Debug)]
+struct Node<K, V> {
+    key: K,
+    value: V,
+    height: i32,
+    // number of nodes in the subtree rooted here
+    size: usize,
+    left: Link<K, V>,
+    right: Link<K, V>,
+}
+
+fn height<K, V>(link: &Link<K, V>) -> i32 {
+    link.as_ref().map_or(0, |node| node.height)
+}
+
+fn size<K, V>(link: &Link<K, V>) -> usize {
+    link.as_ref().map_or(0, |node| node.size)
+}
+
+// Builds a node over the two subtrees, which are shared and not copied
+fn node<K, V>(key: K, value: V, left: Link<K, V>, right: Link<K, V>) -> Rc<Node<K, V>> {
+    Rc::new(Node {
+        height: std::cmp::max(height(&left), height(&right)) + 1,
+        size: size(&left) + size(&right) + 1,
+        key,
+        value,
+        left,
+        right,
+    })
+}
+
+// Same as node, but rotates first when the heights of the subtrees differ by two. The rotations
+// copy the nodes that move and share everything below them.
+fn balance<K: Clone, V: Clone>(key: K, value: V, left: Link<K, V>, right: Link<K, V>) -> Rc<Node<K, V>> {
+    let (left_height, right_height) = (height(&left), height(&right));
+    if left_height > right_height + 1 {
+        let left = left.expect("left is the taller side");
+        if height(&left.left) >= height(&left.right) {
+            // right rotation
+            let lowered = node(key, value, left.right.clone(), right);
+            node(left.key.clone(), left.value.clone(), left.left.clone(), Some(lowered))
+        } else {
+            // left right rotation, the inner grandchild ends up on top
+            let inner = left.right.as_ref().expect("inner grandchild is the taller side");
+            let new_left = node(left.key.clone(), left.value.clone(), left.left.clone(), inner.left.clone());
+            let new_right = node(key, value, inner.right.clone(), right);
+            node(inner.key.clone(), inner.value.clone(), Some(new_left), Some(new_right))
+        }
+    } else if right_height > left_height + 1 {
+        let right = right.expect("right is the taller side");
+        if height(&right.right) >= height(&right.left) {
+            // left rotation
+            let lowered = node(key, value, left, right.left.clone());
+            node(right.key.clone(), right.value.clone(), Some(lowered), right.right.clone())
+        } else {
+            // right left rotation, the inner grandchild ends up on top
+            let inner = right.left.as_ref().expect("inner grandchild is the taller side");
+            let new_left = node(key, value, left, inner.left.clone());
+            let new_right = node(right.key.clone(), right.value.clone(), inner.right.clone(), right.right.clone());
+            node(inner.key.clone(), inner.value.clone(), Some(new_left), Some(new_right))
+        }
+    } else {
+        node(key, value, left, right)
+    }
+}
+
+// Returns the new root of the subtree with the entry in it
+fn insert<K: Ord + Clone, V: Clone>(link: &Link<K, V>, key: K, value: V) -> Rc<Node<K, V>> {
+    let current = match link {
+        Some(current) => current,
+        None => return node(key, value, None, None),
+    };
+    match key.cmp(&current.key) {
+        Ordering::Less => {
+            let left = insert(&current.left, key, value);
+            balance(current.key.clone(), current.value.clone(), Some(left), current.right.clone())
+        }
+        Ordering::Greater => {
+            let right = insert(&current.right, key, value);
+            balance(current.key.clone(), current.value.clone(), current.left.clone(), Some(right))
+        }
+        // the key stays, only the value is replaced
+        Ordering::Equal => node(current.key.clone(), value, current.left.clone(), current.right.clone()),
+    }
+}
+
+// Returns the new root of the subtree without the key, or None if the key is not in it
+fn remove<K: Ord + Clone, V: Clone>(link: &Link<K, V>, key: &K) -> Option<Link<K, V>> {
+    let current = link.as_ref()?;
+    let root = match key.cmp(&current.key) {
+        Ordering::Less => {
+            let left = remove(&current.left, key)?;
+            balance(current.key.clone(), current.value.clone(), left, current.right.clone())
+        }
+        Ordering::Greater => {
+            let right = remove(&current.right, key)?;
+            balance(current.key.clone(), current.value.clone(), current.left.clone(), right)
+        }
+        Ordering::Equal => match (&current.left, &current.right) {
+            (None, _) => return Some(current.right.clone()),
+            (_, None) => return Some(current.left.clone()),
+            // the smallest entry on the right takes the place of the removed one
+            (Some(_), Some(right)) => {
+                let (rest, (min_key, min_value)) = remove_min(right);
+                balance(min_key, min_value, current.left.clone(), rest)
+            }
+        },
+    };
+    Some(Some(root))
+}
+
+// Returns the subtree without its smallest entry, and a copy of that entry
+fn remove_min<K: Clone, V: Clone>(current: &Rc<Node<K, V>>) -> (Link<K, V>, (K, V)) {
+    match &current.left {
+        None => (current.right.clone(), (current.key.clone(), current.value.clone())),
+        Some(left) => {
+            let (rest, min) = remove_min(left);
+            let root = balance(current.key.clone(), current.value.clone(), rest, current.right.clone());
+            (Some(root), min)
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PersistentAVLMap<K, V = ()> {
+    root: Link<K, V>,
+}
+
+// A clone is another handle on the same version, nothing is copied
+impl<K, V> Clone for PersistentAVLMap<K, V> {
+    fn clone(&self) -> Self {
+        PersistentAVLMap {
+            root: self.root.clone(),
+        }
+    }
+}
+
+impl<K, V> Default for PersistentAVLMap<K, V> {
+    fn default() -> Self {
+        PersistentAVLMap::new()
+    }
+}
+
+impl<K, V> PersistentAVLMap<K, V> {
+    pub fn new() -> Self {
+        PersistentAVLMap { root: None }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    pub fn len(&self) -> usize {
+        size(&self.root)
+    }
+
+    pub fn get_height(&self) -> i32 {
+        height(&self.root)
+    }
+
+    // True if both maps are the same version, which is a cheap way to tell that nothing changed
+    // between them
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        match (&self.root, &other.root) {
+            (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    // Iterates over the entries in key order, from either end
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter::new(&self.root)
+    }
+
+    pub fn keys(&self) -> impl DoubleEndedIterator<Item = &K> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl DoubleEndedIterator<Item = &V> {
+        self.iter().map(|(_, value)| value)
+    }
+
+    // Entry with the smallest key
+    pub fn first(&self) -> Option<(&K, &V)> {
+        self.iter().next()
+    }
+
+    // Entry with the largest key
+    pub fn last(&self) -> Option<(&K, &V)> {
+        self.iter().next_back()
+    }
+}
+
+impl<K: Ord, V> PersistentAVLMap<K, V> {
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let mut current = self.root.as_ref();
+        while let Some(node) = current {
+            current = match key.cmp(&node.key) {
+                Ordering::Equal => return Some(&node.value),
+                Ordering::Less => node.left.as_ref(),
+                Ordering::Greater => node.right.as_ref(),
+            };
+        }
+        None
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+}
+
+impl<K: Ord + Clone, V: Clone> PersistentAVLMap<K, V> {
+    // New version with the entry in it. If the key was already there its value is replaced, this
+    // version keeps the old value.
+    pub fn insert(&self, key: K, value: V) -> Self {
+        PersistentAVLMap {
+            root: Some(insert(&self.root, key, value)),
+        }
+    }
+
+    // New version without the key. If the key is not there the new version is this one.
+    pub fn remove(&self, key: &K) -> Self {
+        match remove(&self.root, key) {
+            Some(root) => PersistentAVLMap { root },
+            None => self.clone(),
+        }
+    }
+}
+
+impl<K: Ord + Debug, V> PersistentAVLMap<K, V> {
+    // Checks that the map is a valid AVL tree: keys in order, cached heights and sizes, and children
+    // heights that differ by at most one
+    pub fn validate(&self) -> Result<(), InvariantViolation> {
+        Self::validate_node(&self.root, None, None).map(|_| ())
+    }
+
+    // Checks the subtree below `link`, its keys have to stay strictly between `lower` and `upper`.
+    // Returns the height and the size of the subtree.
+    fn validate_node(
+        link: &Link<K, V>,
+        lower: Option<&K>,
+        upper: Option<&K>,
+    ) -> Result<(i32, usize), InvariantViolation> {
+        let node = match link {
+            Some(node) => node,
+            None => return Ok((0, 0)),
+        };
+        let violation = |invariant| Err(InvariantViolation::new(invariant, &node.key));
+
+        if lower.is_some_and(|lower| node.key <= *lower) || upper.is_some_and(|upper| node.key >= *upper) {
+            return violation(Invariant::Order);
+        }
+        let (left_height, left_size) = Self::validate_node(&node.left, lower, Some(&node.key))?;
+        let (right_height, right_size) = Self::validate_node(&node.right, Some(&node.key), upper)?;
+        if (left_height - right_height).abs() > 1 {
+            return violation(Invariant::Balance);
+        }
+        let height = std::cmp::max(left_height, right_height) + 1;
+        if node.height != height {
+            return violation(Invariant::Height);
+        }
+        let size = left_size + right_size + 1;
+        if node.size != size {
+            return violation(Invariant::Size);
+        }
+        Ok((height, size))
+    }
+}
+
+// ---------------------------------------- Iterators ------------------------------------------------
+// In order iterator that can be walked from both ends, the same way as the iterators of the other trees
+pub struct Iter<'a, K, V> {
+    front: Vec<&'a Node<K, V>>,
+    back: Vec<&'a Node<K, V>>,
+    remaining: usize,
+}
+
+impl<'a, K, V> Iter<'a, K, V> {
+    fn new(root: &'a Link<K, V>) -> Self {
+        let mut iter = Iter {
+            front: Vec::new(),
+            back: Vec::new(),
+            remaining: size(root),
+        };
+        iter.push_left_spine(root);
+        iter.push_right_spine(root);
+        iter
+    }
+
+    fn push_left_spine(&mut self, mut link: &'a Link<K, V>) {
+        while let Some(node) = link {
+            self.front.push(node);
+            link = &node.left;
+        }
+    }
+
+    fn push_right_spine(&mut self, mut link: &'a Link<K, V>) {
+        while let Some(node) = link {
+            self.back.push(node);
+            link = &node.right;
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let node = self.front.pop()?;
+        self.push_left_spine(&node.right);
+        self.remaining -= 1;
+        Some((&node.key, &node.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for Iter<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let node = self.back.pop()?;
+        self.push_right_spine(&node.left);
+        self.remaining -= 1;
+        Some((&node.key, &node.value))
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {}
+
+impl<'a, K, V> FusedIterator for Iter<'a, K, V> {}
+
+impl<'a, K, V> IntoIterator for &'a PersistentAVLMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
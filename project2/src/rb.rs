@@ -1,10 +1,19 @@
-use text_io::read;
+use crate::arena::{Arena, NodeId};
+use crate::export;
+use crate::invariant::{Invariant, InvariantViolation};
+use crate::map::{self, EntryMap, MapNode};
+use crate::ordered_set::OrderedSet;
+use crate::snapshot::{self, Codec, SnapshotError};
+use crate::stats::{self, TreeStats};
+use crate::trace::{Observer, Step};
+use std::fmt::Debug;
+use std::fs;
+use std::io::{self, Write};
+use std::mem;
+use std::ops::{Bound, RangeBounds};
+use std::path::Path;
 
-use std::cell::RefCell;
-use std::process::Child;
-use std::rc::Rc;
 #[derive(Clone, Debug, PartialEq)]
-
 pub enum NodeColor {
     Red,
     Black,
@@ -19,7 +28,44 @@ enum DoubleBlackFix {
     RedSibRight,
     NullSib,
     Root,
-    None,
+}
+
+impl DoubleBlackFix {
+    // The name an observer sees for the case
+    fn name(&self) -> &'static str {
+        match self {
+            DoubleBlackFix::BlackSibllRotation => "BlackSibllRotation",
+            DoubleBlackFix::BlackSiblrRotation => "BlackSiblrRotation",
+            DoubleBlackFix::BlackSibrrRotation => "BlackSibrrRotation",
+            DoubleBlackFix::BlackSibrlRotation => "BlackSibrlRotation",
+            DoubleBlackFix::BlackSibRecolor => "BlackSibRecolor",
+            DoubleBlackFix::RedSibLeft => "RedSibLeft",
+            DoubleBlackFix::RedSibRight => "RedSibRight",
+            DoubleBlackFix::NullSib => "NullSib",
+            DoubleBlackFix::Root => "Root",
+        }
+    }
+}
+
+// A detached subtree together with its black height, the number of black nodes on every path from
+// its root down to an empty child. Split and join keep it up to date instead of walking down for it.
+type Subtree = (Option<NodeId>, usize);
+
+// Called after every rotation with the node that moved up and the one that moved down below it, for
+// trees whose values depend on where a node sits, like the priority search heap of the interval
+// tree (interval.rs). The links are already in their new places. The insert and delete fix-ups
+// only reshape the tree through rotations, so the hook sees every change of shape but the node
+// being hung in or taken out.
+pub(crate) type Rotated<K, V> = fn(&mut Arena<TreeNode<K, V>>, NodeId, NodeId);
+
+// What the set operations get back from splitting one tree at the root of the other
+struct SplitAtRoot {
+    a_left: Subtree,
+    found: Option<NodeId>,
+    a_right: Subtree,
+    pivot: NodeId,
+    b_left: Subtree,
+    b_right: Subtree,
 }
 
 enum FixMode {
@@ -32,1021 +78,1423 @@ enum FixMode {
     RecolorUncleLeft,
     None,
 }
-#[derive(Debug)]
-pub struct RedBlackTree {
-    pub root: Option<Rc<RefCell<TreeNode>>>,
+
+impl FixMode {
+    // The name an observer sees for the case
+    fn name(&self) -> &'static str {
+        match self {
+            FixMode::RotationLeftLeft => "RotationLeftLeft",
+            FixMode::RotationLeftRight => "RotationLeftRight",
+            FixMode::RotationRightRight => "RotationRightRight",
+            FixMode::RotationRightLeft => "RotationRightLeft",
+            FixMode::RecolorRoot => "RecolorRoot",
+            FixMode::RecolorUncleRight => "RecolorUncleRight",
+            FixMode::RecolorUncleLeft => "RecolorUncleLeft",
+            FixMode::None => "None",
+        }
+    }
 }
+
+// The nodes live in an arena owned by the tree and link to each other by NodeId, see arena.rs.
+// References handed out by get, get_mut, the entries and the iterators borrow the arena itself.
 #[derive(Debug)]
-pub struct TreeNode {
-    pub color: NodeColor,
-    pub key: u32,
-    pub parent: Option<Rc<RefCell<TreeNode>>>,
-    left: Option<Rc<RefCell<TreeNode>>>,
-    right: Option<Rc<RefCell<TreeNode>>>,
-    height: i32,
+pub struct RedBlackTree<K, V = ()> {
+    nodes: Arena<TreeNode<K, V>>,
+    root: Option<NodeId>,
+    // set for trees whose values belong to the place of a node, None for ordinary maps
+    rotated: Option<Rotated<K, V>>,
+    // told about every rotation, recolouring and fix-up case, see set_observer
+    observer: Observer<RedBlackTree<K, V>, K>,
+}
+mod node {
+    use super::NodeColor;
+    use crate::arena::NodeId;
+
+    // Public only inside this private module, so that the iterator types below can name it without it
+    // becoming part of the API
+    #[derive(Debug)]
+    pub struct TreeNode<K, V> {
+        pub(crate) color: NodeColor,
+        pub(crate) key: K,
+        pub(crate) value: V,
+        // only a handle, the arena owns every node so parent and child never keep each other alive
+        pub(crate) parent: Option<NodeId>,
+        pub(crate) left: Option<NodeId>,
+        pub(crate) right: Option<NodeId>,
+        pub(super) height: i32,
+        // number of nodes in the subtree rooted here, used for rank and select
+        pub(super) size: usize,
+    }
 }
+pub(crate) use node::TreeNode;
 
-impl TreeNode {
-    // ---------------------------------------- Generic Op -------------------------------------------
-    fn new(z: u32) -> Self {
+impl<K, V> TreeNode<K, V> {
+    fn new(key: K, value: V) -> Self {
         Self {
             color: NodeColor::Red,
-            key: z,
+            key,
+            value,
             parent: None,
             left: None,
             right: None,
             height: 1,
+            size: 1,
         }
     }
+}
+
+impl<K, V> MapNode for TreeNode<K, V> {
+    type Key = K;
+    type Value = V;
 
-    fn height(node: &Option<Rc<RefCell<TreeNode>>>) -> i32 {
-        node.as_ref().map_or(0, |n| n.as_ref().borrow().height)
+    fn left(&self) -> Option<NodeId> {
+        self.left
     }
 
-    fn update_height(node: &Rc<RefCell<TreeNode>>) {
-        // Check heights of left and right node, take the larger one and add 1.
-        let height = std::cmp::max(
-            Self::height(&node.as_ref().borrow().left),
-            Self::height(&node.as_ref().borrow().right),
-        ) + 1;
-        node.as_ref().borrow_mut().height = height;
+    fn right(&self) -> Option<NodeId> {
+        self.right
+    }
+
+    fn entry(&self) -> (&K, &V) {
+        (&self.key, &self.value)
+    }
+
+    fn into_entry(self) -> (K, V) {
+        (self.key, self.value)
+    }
+}
+
+impl<K, V> RedBlackTree<K, V> {
+    // ---------------------------------------- Generic Op -------------------------------------------
+    fn height(&self, node: Option<NodeId>) -> i32 {
+        node.map_or(0, |n| self.nodes[n].height)
+    }
+
+    fn size(&self, node: Option<NodeId>) -> usize {
+        node.map_or(0, |n| self.nodes[n].size)
     }
 
-    fn recursive_update_height(node: &Rc<RefCell<TreeNode>>) {
+    fn update_height(&mut self, node: NodeId) {
         // Check heights of left and right node, take the larger one and add 1.
-        Self::update_height(&Rc::clone(node));
-        if let Some(ref parent) = node.as_ref().borrow().parent {
-            Self::recursive_update_height(&parent);
-        } else {
-            // node is the root node
-            return;
+        let (left, right) = (self.nodes[node].left, self.nodes[node].right);
+        let height = std::cmp::max(self.height(left), self.height(right)) + 1;
+        // The subtree size changes in exactly the same places as the height, so it is refreshed here too
+        let size = self.size(left) + self.size(right) + 1;
+        let node = &mut self.nodes[node];
+        node.height = height;
+        node.size = size;
+    }
+
+    fn recursive_update_height(&mut self, node: NodeId) {
+        let mut current = Some(node);
+        // stops once the root node has been updated
+        while let Some(node) = current {
+            self.update_height(node);
+            current = self.nodes[node].parent;
         }
     }
 
-    fn is_greater(node: &Rc<RefCell<TreeNode>>, z: u32) -> bool {
-        if node.as_ref().borrow().key < z {
-            return true;
+    // swaps both the key and the value, the colours and links stay where they are. The values of a
+    // tree with a rotation hook belong to the place of the node, so they stay too.
+    fn swap_entry(&mut self, node1: NodeId, node2: NodeId) {
+        let (node_1, node_2) = self.nodes.pair_mut(node1, node2);
+        mem::swap(&mut node_1.key, &mut node_2.key);
+        if self.rotated.is_none() {
+            mem::swap(&mut node_1.value, &mut node_2.value);
         }
-        false
     }
-
-    fn is_equal(node: &Rc<RefCell<TreeNode>>, z: u32) -> bool {
-        if node.as_ref().borrow().key == z {
-            return true;
+    fn change_colour(&mut self, node: NodeId, color: NodeColor) {
+        if self.nodes[node].color != color {
+            self.nodes[node].color = color.clone();
+            self.notify(Step::Recolor { node, color });
         }
-        false
     }
-    fn get_key(node: &Rc<RefCell<TreeNode>>) -> u32 {
-        return node.as_ref().borrow().key;
+    fn get_color(&self, node: NodeId) -> NodeColor {
+        self.nodes[node].color.clone()
     }
-    fn swap_key(node1: &Rc<RefCell<TreeNode>>, node2: &Rc<RefCell<TreeNode>>) {
-        let key1 = Self::get_key(node1);
-        let key2 = Self::get_key(node2);
-        node1.borrow_mut().key = key2;
-        node2.borrow_mut().key = key1;
+    fn swap_color(&mut self, node1: NodeId, node2: NodeId) {
+        let (color_1, color_2) = (self.get_color(node1), self.get_color(node2));
+        self.change_colour(node1, color_2);
+        self.change_colour(node2, color_1);
     }
-    fn change_colour(node: &Rc<RefCell<TreeNode>>, color: NodeColor) {
-        node.borrow_mut().color = color;
+
+    fn is_red(&self, node: Option<NodeId>) -> bool {
+        !self.is_black(node)
     }
-    fn get_color(node: &Rc<RefCell<TreeNode>>) -> NodeColor {
-        return node.as_ref().borrow().color.clone();
+    // empty children count as black
+    fn is_black(&self, node: Option<NodeId>) -> bool {
+        node.is_none_or(|n| self.nodes[n].color == NodeColor::Black)
     }
-    fn swap_color(node1: &Option<Rc<RefCell<TreeNode>>>, node2: &Option<Rc<RefCell<TreeNode>>>) {
-        if let Some(node_1) = node1 {
-            let color1 = Self::get_color(node_1);
-            if let Some(node_2) = node2 {
-                let color2 = Self::get_color(node_2);
-                node_1.as_ref().borrow_mut().color = color2;
-                node_2.as_ref().borrow_mut().color = color1;
-            }
-        }
+    // returns false if no parent or is right child
+    fn is_left_child(&self, node: NodeId) -> bool {
+        self.get_parent(node)
+            .is_some_and(|parent| self.nodes[parent].left == Some(node))
     }
 
-    fn is_red(node: Rc<RefCell<TreeNode>>) -> bool {
-        node.as_ref().borrow().color == NodeColor::Red
-    }
-    fn is_black(node: &Option<Rc<RefCell<TreeNode>>>) -> bool {
-        if let Some(node) = node {
-            node.as_ref().borrow().color == NodeColor::Black
+    fn get_sibling(&self, node: NodeId) -> Option<NodeId> {
+        let parent = self.get_parent(node)?;
+        if self.is_left_child(node) {
+            self.get_rightchild(parent)
         } else {
-            true
+            self.get_leftchild(parent)
         }
     }
-    fn is_greater_node(parent: &Rc<RefCell<TreeNode>>, child: &Rc<RefCell<TreeNode>>) -> bool {
-        if parent.as_ref().borrow().key < child.as_ref().borrow().key {
-            return true;
-        }
-        false
+    fn get_parent(&self, child: NodeId) -> Option<NodeId> {
+        self.nodes[child].parent
     }
-    // returns false if no parent or is right child
-    fn is_left_child(node: &Rc<RefCell<TreeNode>>) -> bool {
-        if let Some(parent) = Self::get_parent(node) {
-            if let Some(left) = &parent.as_ref().borrow().left {
-                return Rc::ptr_eq(&left.clone(), node);
-            } else {
-                return false;
-            };
-        } else {
-            false
-        }
+
+    fn get_leftchild(&self, child: NodeId) -> Option<NodeId> {
+        self.nodes[child].left
     }
 
-    fn has_only_child(node: &Rc<RefCell<TreeNode>>) -> bool {
-        return node.as_ref().borrow().left.is_some() ^ node.as_ref().borrow().right.is_some();
+    fn get_rightchild(&self, child: NodeId) -> Option<NodeId> {
+        self.nodes[child].right
     }
 
-    fn get_sibling(node: &Rc<RefCell<TreeNode>>) -> Option<Rc<RefCell<TreeNode>>> {
-        if let Some(parent) = Self::get_parent(node) {
-            if Self::is_left_child(node) {
-                if let Some(sibling) = &parent.as_ref().borrow().right {
-                    return Some(sibling.clone());
-                } else {
-                    return None;
-                }
-            } else {
-                if let Some(sibling) = &parent.as_ref().borrow().left {
-                    return Some(sibling.clone());
-                } else {
-                    return None;
-                }
-            }
-        } else {
-            None
+    // Hands the step to the observer, if there is one, together with the tree as it is now
+    fn notify(&mut self, step: Step<NodeId>) {
+        if let Some(mut observer) = self.observer.take() {
+            observer(step.map(|node| &self.nodes[node].key), self);
+            self.observer.put_back(observer);
         }
     }
-    fn get_sib_right_child(child: &Option<Rc<RefCell<TreeNode>>>) -> Option<Rc<RefCell<TreeNode>>> {
-        if let Some(child) = child {
-            if let Some(sib) = Self::get_sibling(child) {
-                if let Some(right_child_sb) = Self::get_rightchild(&sib) {
-                    return Some(Rc::clone(&right_child_sb));
+
+    // ---------------------------------------- Rotation Op --------------------------------------
+    // Hangs `new` where `old` used to be under `parent`, or makes it the root if there is no parent
+    fn replace_child(&mut self, parent: Option<NodeId>, old: NodeId, new: Option<NodeId>) {
+        match parent {
+            None => self.root = new,
+            Some(parent) => {
+                if self.nodes[parent].left == Some(old) {
+                    self.nodes[parent].left = new;
+                } else {
+                    self.nodes[parent].right = new;
                 }
             }
         }
-        eprintln!("code should not get here: get_sib_right_child");
-        return None;
+        if let Some(new) = new {
+            self.nodes[new].parent = parent;
+        }
     }
 
-    fn get_sib_left_child(child: &Option<Rc<RefCell<TreeNode>>>) -> Option<Rc<RefCell<TreeNode>>> {
-        if let Some(child) = child {
-            if let Some(sib) = Self::get_sibling(child) {
-                if let Some(left_child_sb) = Self::get_leftchild(&sib) {
-                    return Some(Rc::clone(&left_child_sb));
-                }
-            }
+    // Rotates the subtree at `node` to the left, its right child takes its place
+    fn rotate_left(&mut self, node: NodeId) {
+        let right = self
+            .get_rightchild(node)
+            .expect("left rotation needs a right child");
+        let inner = self.get_leftchild(right);
+        self.nodes[node].right = inner;
+        if let Some(inner) = inner {
+            self.nodes[inner].parent = Some(node);
         }
-        eprintln!("code should not get here: get_sib_right_child");
-        return None;
-    }
-    fn get_parent(child: &Rc<RefCell<TreeNode>>) -> Option<Rc<RefCell<TreeNode>>> {
-        if let Some(parent) = &child.as_ref().borrow().parent {
-            return Some(Rc::clone(parent));
-        } else {
-            return None;
+        let parent = self.get_parent(node);
+        self.replace_child(parent, node, Some(right));
+        self.nodes[right].left = Some(node);
+        self.nodes[node].parent = Some(right);
+        // the old root is now below the new one so it is fixed first, the heights above may change too
+        self.update_height(node);
+        self.recursive_update_height(right);
+        if let Some(rotated) = self.rotated {
+            rotated(&mut self.nodes, right, node);
+        }
+        self.notify(Step::RotateLeft(node));
+    }
+
+    // Rotates the subtree at `node` to the right, its left child takes its place
+    fn rotate_right(&mut self, node: NodeId) {
+        let left = self
+            .get_leftchild(node)
+            .expect("right rotation needs a left child");
+        let inner = self.get_rightchild(left);
+        self.nodes[node].left = inner;
+        if let Some(inner) = inner {
+            self.nodes[inner].parent = Some(node);
         }
+        let parent = self.get_parent(node);
+        self.replace_child(parent, node, Some(left));
+        self.nodes[left].right = Some(node);
+        self.nodes[node].parent = Some(left);
+        // the old root is now below the new one so it is fixed first, the heights above may change too
+        self.update_height(node);
+        self.recursive_update_height(left);
+        if let Some(rotated) = self.rotated {
+            rotated(&mut self.nodes, left, node);
+        }
+        self.notify(Step::RotateRight(node));
     }
 
-    fn get_grandparent(child: &Rc<RefCell<TreeNode>>) -> Option<Rc<RefCell<TreeNode>>> {
-        if let Some(gparent) = &child
-            .as_ref()
-            .borrow()
-            .parent
-            .as_ref()
-            .unwrap()
-            .borrow()
-            .parent
-        {
-            return Some(Rc::clone(gparent));
-        } else {
-            return None;
+    // ---------------------------------------- Insert & Insert fix ------------------------------------------
+    fn fix_mode(&self, child: NodeId) -> FixMode {
+        let parent = match self.get_parent(child) {
+            Some(parent) => parent,
+            None => return FixMode::RecolorRoot,
+        };
+        if self.get_color(parent) == NodeColor::Black {
+            // no fixing needed;
+            return FixMode::None;
         }
-    }
-    fn get_greatgrandparent(child: &Rc<RefCell<TreeNode>>) -> Option<Rc<RefCell<TreeNode>>> {
-        if let Some(ref ggparent) = Self::get_grandparent(child)
-            .as_ref()
-            .unwrap()
-            .borrow()
-            .parent
-        {
-            return Some(Rc::clone(&ggparent));
+        let grandp = match self.get_parent(parent) {
+            Some(grandp) => grandp,
+            None => unreachable!("a red parent is never the root"),
+        };
+        // check uncle.
+        let is_right_child = !self.is_left_child(child);
+        if !self.is_left_child(parent) {
+            // parent was the right child
+            match self.get_leftchild(grandp) {
+                Some(uncle) if self.get_color(uncle) == NodeColor::Red => FixMode::RecolorUncleLeft,
+                // rotate
+                _ if is_right_child => FixMode::RotationRightRight,
+                _ => FixMode::RotationRightLeft,
+            }
         } else {
-            return None;
+            match self.get_rightchild(grandp) {
+                // recolor;
+                Some(uncle) if self.get_color(uncle) == NodeColor::Red => FixMode::RecolorUncleRight,
+                // rotate
+                _ if is_right_child => FixMode::RotationLeftRight,
+                _ => FixMode::RotationLeftLeft,
+            }
         }
     }
 
-    fn get_leftchild(child: &Rc<RefCell<TreeNode>>) -> Option<Rc<RefCell<TreeNode>>> {
-        if let Some(l) = &child.as_ref().borrow().left {
-            return Some(Rc::clone(l));
-        } else {
-            return None;
+    // Restores the red-black rules after `child` was hung into the tree as a red node
+    fn fix(&mut self, child: NodeId) {
+        let mode = self.fix_mode(child);
+        if !matches!(mode, FixMode::None) {
+            self.notify(Step::Case {
+                name: mode.name(),
+                at: child,
+            });
         }
-    }
 
-    fn get_rightchild(child: &Rc<RefCell<TreeNode>>) -> Option<Rc<RefCell<TreeNode>>> {
-        if let Some(r) = &child.as_ref().borrow().right {
-            return Some(Rc::clone(r));
-        } else {
-            return None;
+        match mode {
+            FixMode::RotationLeftLeft => {
+                let parent = self.get_parent(child).expect("child has a parent");
+                let grandp = self.get_parent(parent).expect("child has a grandparent");
+                self.rotate_right(grandp);
+                self.swap_color(parent, grandp);
+            }
+            FixMode::RotationLeftRight => {
+                let parent = self.get_parent(child).expect("child has a parent");
+                let grandp = self.get_parent(parent).expect("child has a grandparent");
+                self.rotate_left(parent);
+                self.rotate_right(grandp);
+                // the child ends up on top of the subtree
+                self.swap_color(child, grandp);
+            }
+            FixMode::RotationRightRight => {
+                let parent = self.get_parent(child).expect("child has a parent");
+                let grandp = self.get_parent(parent).expect("child has a grandparent");
+                self.rotate_left(grandp);
+                self.swap_color(parent, grandp);
+            }
+            FixMode::RotationRightLeft => {
+                let parent = self.get_parent(child).expect("child has a parent");
+                let grandp = self.get_parent(parent).expect("child has a grandparent");
+                self.rotate_right(parent);
+                self.rotate_left(grandp);
+                // the child ends up on top of the subtree
+                self.swap_color(child, grandp);
+            }
+            FixMode::RecolorRoot => {
+                self.change_colour(child, NodeColor::Black);
+            }
+            FixMode::RecolorUncleRight | FixMode::RecolorUncleLeft => {
+                let parent = self.get_parent(child).expect("child has a parent");
+                let grandp = self.get_parent(parent).expect("child has a grandparent");
+                let uncle = self.get_sibling(parent).expect("uncle is red");
+                self.change_colour(parent, NodeColor::Black);
+                self.change_colour(uncle, NodeColor::Black);
+                self.change_colour(grandp, NodeColor::Red);
+                // recursive call on child's grand_parent
+                self.fix(grandp);
+            }
+            FixMode::None => {}
         }
     }
 
-    // ---------------------------------------- Rotation Op --------------------------------------
-    fn ll_mutate_parent(child: &Rc<RefCell<TreeNode>>, ggp: Option<Rc<RefCell<TreeNode>>>) {
-        if let Some(parent) = &child.as_ref().borrow().parent {
-            parent.borrow_mut().right = Self::get_grandparent(child);
+    // ---------------------------------------- Join ------------------------------------------------
+    // These work on detached subtrees, roots without a parent, while `self.root` is taken out. The
+    // rotations may point `self.root` at one of them along the way, the caller sets the real root
+    // once it is done.
 
-            parent.borrow_mut().parent = ggp;
-        } else {
-            println!("RIGHT ROTATION DEBUG:P IS NULL");
+    // Unhooks both children of the node and returns them
+    fn detach(&mut self, node: NodeId) -> (Option<NodeId>, Option<NodeId>) {
+        let left = self.nodes[node].left.take();
+        let right = self.nodes[node].right.take();
+        for child in [left, right].into_iter().flatten() {
+            self.nodes[child].parent = None;
         }
+        (left, right)
     }
 
-    fn ll_mutate_grandp(child: &Rc<RefCell<TreeNode>>) {
-        if let Some(gparent) = &child
-            .as_ref()
-            .borrow()
-            .parent
-            .as_ref()
-            .unwrap()
-            .borrow()
-            .parent
-        {
-            gparent.borrow_mut().parent = Self::get_parent(child);
-            gparent.borrow_mut().left =
-                Self::get_rightchild(Self::get_parent(child).as_ref().unwrap());
-            if let Some(right_child) =
-                Self::get_rightchild(Self::get_parent(child).as_ref().unwrap())
-            {
-                right_child.as_ref().borrow_mut().parent = Some(Rc::clone(gparent));
-            }
-        } else {
-            println!("RIGHT ROTATION DEBUG:GP IS NULL");
+    // Hangs both subtrees under the node, the node itself ends up detached
+    fn link(&mut self, left: Option<NodeId>, node: NodeId, right: Option<NodeId>) {
+        self.nodes[node].left = left;
+        self.nodes[node].right = right;
+        self.nodes[node].parent = None;
+        for child in [left, right].into_iter().flatten() {
+            self.nodes[child].parent = Some(node);
         }
+        self.update_height(node);
     }
-    fn ll_rotation(child: &Rc<RefCell<TreeNode>>, tree: &mut RedBlackTree) {
-        let ggp_cp = Self::get_greatgrandparent(child);
-        if let Some(ggp) = Self::get_greatgrandparent(child) {
-            if let Some(gp) = Self::get_grandparent(child) {
-                if Self::is_greater(&gp, ggp.as_ref().borrow().key) {
-                    ggp.as_ref().borrow_mut().left = Self::get_parent(child);
-                } else {
-                    ggp.as_ref().borrow_mut().right = Self::get_parent(child);
-                }
+
+    fn black_height(&self, node: Option<NodeId>) -> usize {
+        let mut black_height = 0;
+        let mut current = node;
+        while let Some(node) = current {
+            if self.is_black(Some(node)) {
+                black_height += 1;
             }
-        } else {
-            tree.root = Self::get_parent(child);
+            current = self.get_leftchild(node);
         }
-
-        Self::ll_mutate_grandp(child);
-        Self::ll_mutate_parent(child, ggp_cp);
-        // also need to mutate greategrandparent
+        black_height
     }
-    fn rr_mutate_parent(child: &Rc<RefCell<TreeNode>>, ggp: Option<Rc<RefCell<TreeNode>>>) {
-        if let Some(parent) = &child.as_ref().borrow().parent {
-            parent.borrow_mut().left = Self::get_grandparent(child);
 
-            parent.borrow_mut().parent = ggp;
-        } else {
-            println!("RIGHT ROTATION DEBUG:P IS NULL");
-        }
+    // Black height of the children of the subtree's root
+    fn child_black_height(&self, subtree: Subtree) -> usize {
+        subtree.1 - usize::from(self.is_black(subtree.0))
     }
 
-    fn rr_mutate_grandp(child: &Rc<RefCell<TreeNode>>) {
-        if let Some(gparent) = &child
-            .as_ref()
-            .borrow()
-            .parent
-            .as_ref()
-            .unwrap()
-            .borrow()
-            .parent
-        {
-            gparent.borrow_mut().parent = Self::get_parent(child);
-            gparent.borrow_mut().right =
-                Self::get_leftchild(Self::get_parent(child).as_ref().unwrap());
-            if let Some(left_child) = Self::get_leftchild(Self::get_parent(child).as_ref().unwrap())
-            {
-                left_child.as_ref().borrow_mut().parent = Self::get_grandparent(child);
+    // Joins two subtrees with `node` in between, every key in `left` is smaller than the node's and
+    // every key in `right` larger. Runs in the difference of the black heights.
+    fn join_subtrees(&mut self, left: Subtree, node: NodeId, right: Subtree) -> Subtree {
+        if left.1 > right.1 {
+            let top = self.join_right(left, node, right);
+            // a red root with a red right child is fixed by making the root black
+            if self.is_red(Some(top)) && self.is_red(self.get_rightchild(top)) {
+                self.change_colour(top, NodeColor::Black);
+                (Some(top), left.1 + 1)
+            } else {
+                (Some(top), left.1)
+            }
+        } else if right.1 > left.1 {
+            let top = self.join_left(left, node, right);
+            if self.is_red(Some(top)) && self.is_red(self.get_leftchild(top)) {
+                self.change_colour(top, NodeColor::Black);
+                (Some(top), right.1 + 1)
+            } else {
+                (Some(top), right.1)
             }
+        } else if self.is_black(left.0) && self.is_black(right.0) {
+            self.change_colour(node, NodeColor::Red);
+            self.link(left.0, node, right.0);
+            (Some(node), left.1)
         } else {
-            println!("RIGHT ROTATION DEBUG:GP IS NULL");
+            self.change_colour(node, NodeColor::Black);
+            self.link(left.0, node, right.0);
+            (Some(node), left.1 + 1)
         }
     }
 
-    fn rr_rotation(child: &Rc<RefCell<TreeNode>>, tree: &mut RedBlackTree) {
-        let ggp_cp = Self::get_greatgrandparent(child);
-        if let Some(ggp) = Self::get_greatgrandparent(child) {
-            if let Some(gp) = Self::get_grandparent(child) {
-                if Self::is_greater(&gp, ggp.as_ref().borrow().key) {
-                    ggp.as_ref().borrow_mut().left = Self::get_parent(child);
-                } else {
-                    ggp.as_ref().borrow_mut().right = Self::get_parent(child);
-                }
-            }
-        } else {
-            tree.root = Self::get_parent(child);
+    // Walks down the right spine of the taller `left` to a black node with the same black height as
+    // `right` and hangs the node there as a red node
+    fn join_right(&mut self, left: Subtree, node: NodeId, right: Subtree) -> NodeId {
+        if self.is_black(left.0) && left.1 == right.1 {
+            self.change_colour(node, NodeColor::Red);
+            self.link(left.0, node, right.0);
+            return node;
         }
-        Self::rr_mutate_grandp(child);
-        Self::rr_mutate_parent(child, ggp_cp);
-        // also need to mutate greategrandparent
+        let top = left.0.expect("left is taller than right");
+        let child_black_height = self.child_black_height(left);
+        let (inner, spine) = self.detach(top);
+        let joined = self.join_right((spine, child_black_height), node, right);
+        self.link(inner, top, Some(joined));
+        // two reds in a row can only show up on the right spine, one rotation fixes them
+        let outer = self.get_rightchild(joined);
+        if self.is_black(Some(top)) && self.is_red(Some(joined)) && self.is_red(outer) {
+            self.change_colour(outer.expect("outer child is red"), NodeColor::Black);
+            self.rotate_left(top);
+            return joined;
+        }
+        top
     }
 
-    fn rl_p_mutate_p(child: &Rc<RefCell<TreeNode>>) {
-        if let Some(parent) = &child.as_ref().borrow().parent {
-            parent.borrow_mut().parent = Some(Rc::clone(child));
-            parent.borrow_mut().left = Self::get_rightchild(child);
-            if let Some(right_child) = Self::get_rightchild(child) {
-                right_child.as_ref().borrow_mut().parent = Some(Rc::clone(parent));
+    // Mirror of join_right for a taller `right`
+    fn join_left(&mut self, left: Subtree, node: NodeId, right: Subtree) -> NodeId {
+        if self.is_black(right.0) && left.1 == right.1 {
+            self.change_colour(node, NodeColor::Red);
+            self.link(left.0, node, right.0);
+            return node;
+        }
+        let top = right.0.expect("right is taller than left");
+        let child_black_height = self.child_black_height(right);
+        let (spine, inner) = self.detach(top);
+        let joined = self.join_left(left, node, (spine, child_black_height));
+        self.link(Some(joined), top, inner);
+        let outer = self.get_leftchild(joined);
+        if self.is_black(Some(top)) && self.is_red(Some(joined)) && self.is_red(outer) {
+            self.change_colour(outer.expect("outer child is red"), NodeColor::Black);
+            self.rotate_right(top);
+            return joined;
+        }
+        top
+    }
+
+    // Takes the largest node out of a non-empty subtree, returns what is left and the node
+    fn split_last(&mut self, subtree: Subtree) -> (Subtree, NodeId) {
+        let node = subtree.0.expect("split_last needs a node");
+        let child_black_height = self.child_black_height(subtree);
+        let (left, right) = self.detach(node);
+        match right {
+            None => ((left, child_black_height), node),
+            Some(_) => {
+                let (rest, last) = self.split_last((right, child_black_height));
+                let rest = self.join_subtrees((left, child_black_height), node, rest);
+                (rest, last)
             }
-        } else {
-            println!("RIGHT ROTATION DEBUG:P IS NULL");
         }
     }
 
-    fn rl_p_mutate_gp(child: &Rc<RefCell<TreeNode>>) {
-        if let Some(gparent) = &child
-            .as_ref()
-            .borrow()
-            .parent
-            .as_ref()
-            .unwrap()
-            .borrow()
-            .parent
-        {
-            gparent.borrow_mut().right = Some(Rc::clone(child));
-        } else {
-            println!("RIGHT ROTATION DEBUG:GP IS NULL");
+    // Joins two subtrees without a node in between
+    fn join_pair(&mut self, left: Subtree, right: Subtree) -> Subtree {
+        if left.0.is_none() {
+            return right;
         }
+        let (rest, last) = self.split_last(left);
+        self.join_subtrees(rest, last, right)
     }
 
-    fn rl_p_mutate_child(child: &Rc<RefCell<TreeNode>>, gp: &Rc<RefCell<TreeNode>>) {
-        child.as_ref().borrow_mut().right = Self::get_parent(child);
-
-        child.as_ref().borrow_mut().parent = Some(Rc::clone(gp));
+    // Frees every node of a detached subtree
+    fn free_subtree(&mut self, node: Option<NodeId>) {
+        let mut stack: Vec<NodeId> = node.into_iter().collect();
+        while let Some(node) = stack.pop() {
+            let freed = self.nodes.free(node);
+            stack.extend(freed.left);
+            stack.extend(freed.right);
+        }
     }
 
-    fn rl_p_rotation(child: &Rc<RefCell<TreeNode>>) {
-        let child_rc_1 = Rc::clone(child);
-        let parent_rc = Rc::clone(Self::get_grandparent(child).as_ref().unwrap());
-
-        Self::rl_p_mutate_gp(&child_rc_1);
-        let child_rc_2 = Rc::clone(child);
-
-        Self::rl_p_mutate_p(&child_rc_2);
-        let child_rc_3 = Rc::clone(child);
-        Self::rl_p_mutate_child(&child_rc_3, &parent_rc);
+    // Moves a subtree out of another arena into this one and returns its new, detached root
+    fn adopt(&mut self, other: &mut Arena<TreeNode<K, V>>, node: Option<NodeId>) -> Option<NodeId> {
+        let mut moved = other.free(node?);
+        let (left, right) = (moved.left.take(), moved.right.take());
+        moved.parent = None;
+        let node = self.nodes.alloc(moved);
+        let left = self.adopt(other, left);
+        let right = self.adopt(other, right);
+        // heights and sizes come along unchanged
+        self.nodes[node].left = left;
+        self.nodes[node].right = right;
+        for child in [left, right].into_iter().flatten() {
+            self.nodes[child].parent = Some(node);
+        }
+        Some(node)
     }
-    fn rl_rotation(child: &Rc<RefCell<TreeNode>>, tree: &mut RedBlackTree) {
-        Self::rl_p_rotation(child);
-        Self::rr_rotation(Self::get_rightchild(child).as_ref().unwrap(), tree);
+
+    // Makes the subtree the whole tree, a red root can always be turned black
+    fn set_root(&mut self, root: Option<NodeId>) {
+        self.root = root;
+        if let Some(root) = root {
+            self.nodes[root].parent = None;
+            self.change_colour(root, NodeColor::Black);
+        }
     }
-    // lr
-    fn lr_p_mutate_gp(child: &Rc<RefCell<TreeNode>>) {
-        if let Some(gparent) = &child
-            .as_ref()
-            .borrow()
-            .parent
-            .as_ref()
-            .unwrap()
-            .borrow()
-            .parent
-        {
-            gparent.borrow_mut().left = Some(Rc::clone(child));
+
+    // Puts the nodes of both trees into one arena, the smaller tree moves into the arena of the larger
+    // one. Returns the tree owning that arena, with its root taken out, and both trees as subtrees.
+    fn combine(a: Self, b: Self) -> (Self, Subtree, Subtree) {
+        let a_is_larger = a.size(a.root) >= b.size(b.root);
+        let (mut larger, mut smaller) = if a_is_larger { (a, b) } else { (b, a) };
+        let larger_root = larger.root.take();
+        let smaller_root = larger.adopt(&mut smaller.nodes, smaller.root);
+        let larger_tree = (larger_root, larger.black_height(larger_root));
+        let smaller_tree = (smaller_root, larger.black_height(smaller_root));
+        if a_is_larger {
+            (larger, larger_tree, smaller_tree)
         } else {
-            println!("RIGHT ROTATION DEBUG:GP IS NULL");
+            (larger, smaller_tree, larger_tree)
         }
     }
-    fn lr_p_mutate_p(child: &Rc<RefCell<TreeNode>>) {
-        if let Some(parent) = &child.as_ref().borrow().parent {
-            parent.borrow_mut().parent = Some(Rc::clone(child));
-            parent.borrow_mut().right = Self::get_leftchild(child);
-            if let Some(left_child) = Self::get_leftchild(child) {
-                left_child.as_ref().borrow_mut().parent = Self::get_parent(child);
-            }
+
+    // Splits the tree into two trees made of the two subtrees, the smaller one moves to a new arena
+    fn separate(mut self, left: Subtree, right: Subtree) -> (Self, Self) {
+        let mut moved = RedBlackTree {
+            nodes: Arena::new(),
+            root: None,
+            rotated: self.rotated,
+            observer: Observer::new(),
+        };
+        if self.size(left.0) <= self.size(right.0) {
+            let root = moved.adopt(&mut self.nodes, left.0);
+            moved.set_root(root);
+            self.set_root(right.0);
+            (moved, self)
         } else {
-            println!("RIGHT ROTATION DEBUG:P IS NULL");
+            let root = moved.adopt(&mut self.nodes, right.0);
+            moved.set_root(root);
+            self.set_root(left.0);
+            (self, moved)
         }
     }
+}
 
-    fn lr_p_mutate_child(child: &Rc<RefCell<TreeNode>>, gp: &Rc<RefCell<TreeNode>>) {
-        child.as_ref().borrow_mut().left = Self::get_parent(child);
-
-        child.as_ref().borrow_mut().parent = Some(Rc::clone(gp));
+impl<K: Ord, V> RedBlackTree<K, V> {
+    pub fn new() -> RedBlackTree<K, V> {
+        RedBlackTree {
+            nodes: Arena::new(),
+            root: None,
+            rotated: None,
+            observer: Observer::new(),
+        }
     }
 
-    fn lr_rotation(child: &Rc<RefCell<TreeNode>>, tree: &mut RedBlackTree) {
-        Self::lr_rotation_p(child);
-        Self::ll_rotation(Self::get_leftchild(child).as_ref().unwrap(), tree);
+    // An empty tree whose values are kept in step with its shape by the hook instead of only being
+    // set by the caller, see Rotated. Only for trees that never hand out their values mutably.
+    pub(crate) fn with_rotation_hook(rotated: Rotated<K, V>) -> Self {
+        RedBlackTree {
+            rotated: Some(rotated),
+            ..RedBlackTree::new()
+        }
     }
-    fn lr_rotation_p(child: &Rc<RefCell<TreeNode>>) {
-        let child_rc_1 = Rc::clone(child);
-        let gparent_rc = Rc::clone(Self::get_grandparent(child).as_ref().unwrap());
-        Self::lr_p_mutate_gp(&child_rc_1);
-        let child_rc_2 = Rc::clone(child);
 
-        Self::lr_p_mutate_p(&child_rc_2);
-        let child_rc_3 = Rc::clone(child);
-        Self::lr_p_mutate_child(&child_rc_3, &gparent_rc);
+    // Calls the observer after every step of every insert and remove from now on, with the step and
+    // the tree right after it: the new node being hung in, each fix-up case picked by fix and
+    // fix_double_black, every rotation and recolouring, and the node being taken out. A node with
+    // two children trades places with its successor first, so from the replace step until the
+    // remove step the removed key sits out of order where the successor was. Replaces the observer
+    // set before.
+    pub fn set_observer<F>(&mut self, observer: F)
+    where
+        F: FnMut(Step<&K>, &Self) + Send + Sync + 'static,
+    {
+        self.observer.set(observer);
     }
 
-    // ---------------------------------------- Insert & Insert fix ------------------------------------------
-    fn fix_mode(child: &Rc<RefCell<TreeNode>>) -> FixMode {
-        match child.as_ref().borrow().parent {
-            Some(ref parent) => {
-                if parent.clone().as_ref().borrow().color == NodeColor::Black {
-                    // no fixing needed;
-                    return FixMode::None;
-                } else {
-                    match parent.as_ref().borrow().parent {
-                        Some(ref grandp) => {
-                            // check uncle.
-                            if TreeNode::is_greater(grandp, parent.clone().as_ref().borrow().key) {
-                                // parent was the right child
-                                match grandp.as_ref().borrow().left {
-                                    Some(ref uncle) => {
-                                        if uncle.clone().as_ref().borrow().color == NodeColor::Red {
-                                            return FixMode::RecolorUncleLeft;
-                                        } else {
-                                            // rotate
-                                            if TreeNode::is_greater(
-                                                parent,
-                                                child.clone().as_ref().borrow().key,
-                                            ) {
-                                                return FixMode::RotationRightRight;
-                                            } else {
-                                                return FixMode::RotationRightLeft;
-                                            }
-                                        }
-                                    }
-                                    None => {
-                                        // rotate
-                                        if TreeNode::is_greater(
-                                            parent,
-                                            child.clone().as_ref().borrow().key,
-                                        ) {
-                                            return FixMode::RotationRightRight;
-                                        } else {
-                                            return FixMode::RotationRightLeft;
-                                        }
-                                    }
-                                }
-                            } else {
-                                match grandp.as_ref().borrow().right {
-                                    Some(ref uncle) => {
-                                        if uncle.clone().as_ref().borrow().color == NodeColor::Red {
-                                            // recolor;
-                                            return FixMode::RecolorUncleRight;
-                                        } else {
-                                            // rotate
-                                            if TreeNode::is_greater(
-                                                parent,
-                                                child.clone().as_ref().borrow().key,
-                                            ) {
-                                                return FixMode::RotationLeftRight;
-                                            } else {
-                                                return FixMode::RotationLeftLeft;
-                                            }
-                                        }
-                                    }
-                                    None => {
-                                        // rotate
-                                        if TreeNode::is_greater(
-                                            parent,
-                                            child.clone().as_ref().borrow().key,
-                                        ) {
-                                            return FixMode::RotationLeftRight;
-                                        } else {
-                                            return FixMode::RotationLeftLeft;
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        None => {
-                            println!("FIX DEBUG: Child has no grand parent");
-                        }
-                    };
-                }
-            }
-            None => {
-                return FixMode::RecolorRoot;
-            }
-        }
-        return FixMode::None;
+    pub fn clear_observer(&mut self) {
+        self.observer.clear();
     }
 
-    fn fix(child: &Rc<RefCell<TreeNode>>, tree: &mut RedBlackTree) {
-        let mode = Self::fix_mode(child);
-
-        match mode {
-            FixMode::RotationLeftLeft => {
-                // println!("          ROTATION LL");
-                let child_rc = Rc::clone(&child);
-                let p_cpy = Self::get_parent(child);
-                let gp_cpy = Self::get_grandparent(child);
-                Self::ll_rotation(child, tree);
-                Self::swap_color(&p_cpy, &gp_cpy);
-                if let Some(gp) = gp_cpy {
-                    Self::update_height(&gp);
-                }
-                Self::recursive_update_height(&child_rc);
-
-                // fix height here
-                Self::fix(child, tree);
-            }
-            FixMode::RotationLeftRight => {
-                let c_cpy = Some(Rc::clone(child));
-                let p_cpy = Self::get_parent(child);
-
-                let gp_cpy = Self::get_grandparent(child);
-                Self::lr_rotation(child, tree);
-
-                Self::swap_color(&c_cpy, &gp_cpy);
-                if let Some(gp) = gp_cpy {
-                    Self::update_height(&gp);
-                }
-                if let Some(p) = p_cpy {
-                    Self::recursive_update_height(&p);
-                }
+    // The arena and the root, for trees built on top of this one that walk the nodes themselves
+    pub(crate) fn nodes(&self) -> (&Arena<TreeNode<K, V>>, Option<NodeId>) {
+        (&self.nodes, self.root)
+    }
 
-                Self::fix(child, tree);
-            }
-            FixMode::RotationRightRight => {
-                let child_rc = Rc::clone(&child);
+    pub(crate) fn nodes_mut(&mut self) -> (&mut Arena<TreeNode<K, V>>, Option<NodeId>) {
+        (&mut self.nodes, self.root)
+    }
 
-                let p_cpy = Self::get_parent(child);
-                let gp_cpy = Self::get_grandparent(child);
-                // println!("          ROTATION   RR");
+    pub fn get_height(&self) -> i32 {
+        self.height(self.root)
+    }
 
-                Self::rr_rotation(child, tree);
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
 
-                Self::swap_color(&p_cpy, &gp_cpy);
-                if let Some(gp) = gp_cpy {
-                    Self::update_height(&gp);
+    // Number of entries, read off the size of the root
+    pub fn len(&self) -> usize {
+        self.size(self.root)
+    }
+    pub fn count_leaves(&self) -> usize {
+        fn count_leaves_helper<K, V>(nodes: &Arena<TreeNode<K, V>>, node: Option<NodeId>) -> usize {
+            match node {
+                None => 0,
+                Some(n) => {
+                    let node = &nodes[n];
+                    if node.left.is_none() && node.right.is_none() {
+                        1
+                    } else {
+                        count_leaves_helper(nodes, node.left) + count_leaves_helper(nodes, node.right)
+                    }
                 }
-                Self::recursive_update_height(&child_rc);
-                Self::fix(child, tree);
             }
-            FixMode::RotationRightLeft => {
-                let c_cpy = Some(Rc::clone(child));
-                let p_cpy = Self::get_parent(child);
-
-                let gp_cpy = Self::get_grandparent(child);
-                Self::rl_rotation(child, tree);
+        }
 
-                Self::swap_color(&c_cpy, &gp_cpy);
-                if let Some(gp) = gp_cpy {
-                    Self::update_height(&gp);
-                }
-                if let Some(p) = p_cpy {
-                    Self::recursive_update_height(&p);
-                }
-                Self::fix(child, tree);
-            }
-            FixMode::RecolorRoot => {
-                Self::change_colour(child, NodeColor::Black);
-                return;
-            }
-            FixMode::RecolorUncleRight => {
-                if let Some(parent) = &child.as_ref().borrow().parent {
-                    Self::change_colour(parent, NodeColor::Black);
-                    if let Some(grand_parent) = &parent.as_ref().borrow().parent {
-                        Self::change_colour(grand_parent, NodeColor::Red);
-                        if let Some(uncle) = &grand_parent.as_ref().borrow().right {
-                            Self::change_colour(uncle, NodeColor::Black);
-                        };
-                    };
-                };
-                if let Some(gp) = Self::get_grandparent(child) {
-                    Self::fix(&gp, tree);
-                } else {
-                    eprintln!("Child should have grandp");
-                }
-            }
-            FixMode::RecolorUncleLeft => {
-                if let Some(parent) = &child.as_ref().borrow().parent {
-                    Self::change_colour(parent, NodeColor::Black);
-                    if let Some(grand_parent) = &parent.as_ref().borrow().parent {
-                        Self::change_colour(grand_parent, NodeColor::Red);
-                        if let Some(uncle) = &grand_parent.as_ref().borrow().left {
-                            Self::change_colour(uncle, NodeColor::Black);
-                        };
-                    };
-                };
+        count_leaves_helper(&self.nodes, self.root)
+    }
+
+    // Node count, leaf depths, path length and black height, see TreeStats
+    pub fn stats(&self) -> TreeStats {
+        let mut stats = stats::collect(self.root, |node| {
+            let node = &self.nodes[node];
+            [node.left, node.right].into_iter().flatten()
+        });
+        stats.black_height = Some(self.black_height(self.root));
+        stats
+    }
+
+    // Hangs a new node holding key and value into the tree and returns it, then fixes the colours
+    // on the way back up. Callers make sure the key is not in the tree yet.
+    fn insert_node(&mut self, key: K, value: V) -> NodeId {
+        // Walks down from the root to the empty spot the key belongs in
+        let mut parent = None;
+        let mut go_left = false;
+        let mut current = self.root;
+        while let Some(node) = current {
+            parent = Some(node);
+            go_left = key < self.nodes[node].key;
+            current = if go_left {
+                self.get_leftchild(node)
+            } else {
+                self.get_rightchild(node)
+            };
+        }
 
-                // recursive call on child's grand_parent
-                if let Some(gp) = Self::get_grandparent(child) {
-                    Self::fix(&gp, tree);
+        let mut new_node = TreeNode::new(key, value);
+        new_node.parent = parent;
+        let leaf = self.nodes.alloc(new_node);
+        match parent {
+            // case x is the root
+            None => self.root = Some(leaf),
+            Some(parent) => {
+                if go_left {
+                    self.nodes[parent].left = Some(leaf);
                 } else {
-                    eprintln!("Child should have grandp");
+                    self.nodes[parent].right = Some(leaf);
                 }
+                self.recursive_update_height(parent);
             }
-            FixMode::None => {
-                return;
+        }
+        self.notify(Step::Insert(leaf));
+        self.fix(leaf);
+        leaf
+    }
+
+    // Inserts the key with its value. If the key was already in the tree the value is replaced
+    // and the old one is returned, the key itself is left untouched.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.entry(key) {
+            Entry::Occupied(mut entry) => Some(entry.insert(value)),
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+                None
             }
         }
     }
 
-    fn insert(node: &mut Option<Rc<RefCell<TreeNode>>>, key: u32) -> Option<Rc<RefCell<TreeNode>>> {
-        let new_leaf: Option<Rc<RefCell<TreeNode>>> = {
-            let mut return_leaf: Option<Rc<RefCell<TreeNode>>> = None;
-            if let Some(current_node) = node {
-                // compare with the tree root node with key
-                if TreeNode::is_greater(current_node, key) {
-                    let mut t_node = current_node.borrow_mut();
-                    if !t_node.right.is_none() {
-                        return_leaf = Self::insert(&mut t_node.right, key);
-                    } else {
-                        let mut new_node = TreeNode::new(key);
-                        new_node.parent = Some(current_node.clone());
-                        let new_leaf = Rc::new(RefCell::new(new_node));
-                        t_node.right = Some(new_leaf.clone());
-                        return_leaf = Some(new_leaf.clone());
-                    }
-                } else if TreeNode::is_equal(current_node, key) {
-                    // duplicated value, do nothing
-                    return None;
-                } else {
-                    let mut t_node = current_node.borrow_mut();
-                    if !t_node.left.is_none() {
-                        return_leaf = Self::insert(&mut t_node.left, key);
-                    } else {
-                        let mut new_node = TreeNode::new(key);
-                        new_node.parent = Some(current_node.clone());
-                        let new_leaf = Rc::new(RefCell::new(new_node));
-                        t_node.left = Some(new_leaf.clone());
-                        return_leaf = Some(new_leaf.clone());
-                    }
-                }
+    pub fn contains(&self, key: &K) -> bool {
+        self.get_node(key).is_some()
+    }
 
-                let height = std::cmp::max(
-                    Self::height(&current_node.as_ref().borrow().left),
-                    Self::height(&current_node.as_ref().borrow().right),
-                ) + 1;
-                current_node.borrow_mut().height = height;
-            } else {
-                return_leaf = None;
-            }
-            return_leaf
-        };
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.get_node(key).map(|node| self.node_value(node))
+    }
 
-        return new_leaf;
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let node = self.get_node(key)?;
+        Some(self.node_value_mut(node))
     }
 
-    pub fn node_insert(tree: &mut RedBlackTree, key: u32) {
-        let ref mut node = tree.root;
-        let leaf_node = Self::insert(node, key);
-        match leaf_node {
-            Some(child) => {
-                Self::fix(&child, tree);
-            }
-            None => {
-                // This is a duplicated key
-            }
-        }
+    // Removes the key from the tree and hands back its value
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let delete_node = self.get_node(key)?;
+        Some(self.delete_node(delete_node).1)
+    }
+
+    pub fn delete(&mut self, key: &K) {
+        self.remove(key);
+    }
+
+    // Gets the entry for the key so it can be looked at or filled in with a single search
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+        map::entry(self, key)
     }
 
     // ---------------------------------------- Get -------------------------------------------
-    fn get(node: &Option<Rc<RefCell<TreeNode>>>, key: u32) -> Option<Rc<RefCell<TreeNode>>> {
-        if let Some(current_node) = node {
-            if current_node.as_ref().borrow().key == key {
-                return Some(current_node.clone());
-            } else if current_node.as_ref().borrow().key > key {
-                return Self::get(&current_node.as_ref().borrow().left, key);
-            } else {
-                return Self::get(&current_node.as_ref().borrow().right, key);
-            }
-        } else {
-            return None;
+    fn get_node(&self, key: &K) -> Option<NodeId> {
+        let mut current = self.root;
+        while let Some(node) = current {
+            let node_ref = &self.nodes[node];
+            current = match key.cmp(&node_ref.key) {
+                std::cmp::Ordering::Equal => return Some(node),
+                std::cmp::Ordering::Less => node_ref.left,
+                std::cmp::Ordering::Greater => node_ref.right,
+            };
         }
+        None
     }
 
     // ---------------------------------------- Delete ------------------------------------------
-    fn successor(node: &Option<Rc<RefCell<TreeNode>>>) -> Option<Rc<RefCell<TreeNode>>> {
-        let temp = node.as_ref().unwrap().clone();
-        if (!temp.as_ref().borrow().left.is_none()) {
-            return Self::successor(&temp.as_ref().borrow().left);
+    // the smallest node below the given one
+    fn subtree_min(&self, node: NodeId) -> NodeId {
+        let mut node = node;
+        while let Some(left) = self.get_leftchild(node) {
+            node = left;
         }
-        Some(temp)
+        node
     }
-    fn replace_node(node: &Rc<RefCell<TreeNode>>) -> Option<Rc<RefCell<TreeNode>>> {
-        if node.as_ref().borrow().left.is_some() && node.as_ref().borrow().right.is_some() {
-            return Self::successor(&node.as_ref().borrow().right);
-        }
-        if node.as_ref().borrow().left.is_none() && node.as_ref().borrow().right.is_none() {
-            return None;
+    // the mirror of subtree_min, finds the largest node below the given one
+    fn subtree_max(&self, node: NodeId) -> NodeId {
+        let mut node = node;
+        while let Some(right) = self.get_rightchild(node) {
+            node = right;
         }
-
-        if node.as_ref().borrow().left.is_some() {
-            return Some(node.as_ref().borrow().left.as_ref().unwrap().clone());
-        } else {
-            return Some(node.as_ref().borrow().right.as_ref().unwrap().clone());
+        node
+    }
+
+    // Unlinks a node of this tree, frees it and takes its entry out. When the node has two
+    // children its entry is swapped into the successor first, so the node that is physically
+    // removed always has at most one child.
+    fn delete_node(&mut self, delete_node: NodeId) -> (K, V) {
+        let mut delete_node = delete_node;
+        if let (Some(_), Some(right)) = (self.get_leftchild(delete_node), self.get_rightchild(delete_node)) {
+            let successor = self.subtree_min(right);
+            self.swap_entry(delete_node, successor);
+            self.notify(Step::Replace {
+                removed: successor,
+                by: delete_node,
+            });
+            delete_node = successor;
         }
-    }
-
-    fn delete(delete_node: Rc<RefCell<TreeNode>>, tree: &mut RedBlackTree, root_key: u32) {
-        let u = Self::replace_node(&delete_node);
-        // let uvBlack = (u.is_none() || Self::is_black(u));
-        let parent = Self::get_parent(&delete_node);
-
-        // // Debugging print
-        // if let Some(p) = &parent {
-        //     Self::print_val(&delete_node.as_ref().borrow().parent.as_ref().unwrap());
-        //     println!("******************************************parent db************************");
-        //     Self::print_val(p);
-        // }
 
-        if u.is_none() {
-            if Self::is_equal(&delete_node, root_key) {
-                tree.root = None;
-            } else {
-                if !Self::is_red(Rc::clone(&delete_node)) {
-                    Self::fix_double_black(&Some(Rc::clone(&delete_node)), tree);
-                } else {
-                    // sibling is not null, make it red"  *** Does not make sense to change sibling here
+        let child = self
+            .get_leftchild(delete_node)
+            .or(self.get_rightchild(delete_node));
+        match child {
+            // a node with a single child is black and the child is red, so the child moves up and
+            // takes over the black colour
+            Some(child) => {
+                let parent = self.get_parent(delete_node);
+                self.replace_child(parent, delete_node, Some(child));
+                self.change_colour(child, NodeColor::Black);
+                if let Some(parent) = parent {
+                    self.recursive_update_height(parent);
                 }
-
-                if Self::is_left_child(&delete_node) {
-                    if let Some(parent) = Self::get_parent(&delete_node) {
-                        parent.borrow_mut().left = None;
-                        Self::recursive_update_height(&parent);
-                    };
-                } else {
-                    if let Some(parent) = Self::get_parent(&delete_node) {
-                        parent.borrow_mut().right = None;
-                        Self::recursive_update_height(&parent);
-                    };
+            }
+            None => {
+                // taking out a black leaf leaves its path one black node short, which is fixed
+                // while the leaf is still in place
+                if self.is_black(Some(delete_node)) {
+                    self.fix_double_black(delete_node);
+                }
+                let parent = self.get_parent(delete_node);
+                self.replace_child(parent, delete_node, None);
+                if let Some(parent) = parent {
+                    self.recursive_update_height(parent);
                 }
             }
-            return;
         }
 
-        if Self::has_only_child(&delete_node) {
-            if Self::is_equal(&delete_node, root_key) {
-                tree.root = u.clone();
-                if let Some(replace_node) = &u {
-                    let mut temp = replace_node.borrow_mut();
-                    temp.parent = None;
-                    temp.color = NodeColor::Black;
-                    // no update height needed
-                }
-            } else {
-                if Self::is_left_child(&delete_node) {
-                    if let Some(parent) = Self::get_parent(&delete_node) {
-                        if let Some(current_node) = &u {
-                            parent.borrow_mut().left = Some(current_node.clone());
-                            current_node.borrow_mut().parent = Some(parent.clone());
-                            Self::recursive_update_height(&parent);
-                        }
-                    }
-                } else {
-                    if let Some(parent) = Self::get_parent(&delete_node) {
-                        if let Some(current_node) = &u {
-                            parent.borrow_mut().right = Some(current_node.clone());
-                            current_node.borrow_mut().parent = Some(parent.clone());
-                            Self::recursive_update_height(&parent);
-                        }
-                    }
-                }
+        self.notify(Step::Remove(delete_node));
+        let TreeNode { key, value, .. } = self.nodes.free(delete_node);
+        (key, value)
+    }
 
-                if Self::is_black(&u) && Self::is_black(&Some(delete_node.clone())) {
-                    if let Some(current_node) = &u {
-                        Self::fix_double_black(&Some(current_node.clone()), tree);
-                    }
+    fn fix_double_black_helper(&self, node: NodeId) -> DoubleBlackFix {
+        if self.get_parent(node).is_none() {
+            return DoubleBlackFix::Root;
+        }
+        if let Some(sib) = self.get_sibling(node) {
+            if self.is_red(Some(sib)) {
+                //rotation case
+                if self.is_left_child(sib) {
+                    DoubleBlackFix::RedSibLeft
                 } else {
-                    if let Some(current_node) = &u {
-                        current_node.borrow_mut().color = NodeColor::Black;
-                    }
+                    DoubleBlackFix::RedSibRight
                 }
-            }
-            return;
-        }
-        if let Some(current_node) = &u {
-            let mut key = root_key;
-            if Self::get_key(&delete_node) == root_key {
-                key = Self::get_key(current_node)
-            }
-            Self::swap_key(&delete_node, current_node);
-            Self::delete(current_node.clone(), tree, key)
-        }
-    }
-    fn fix_double_black_helper(node: &Option<Rc<RefCell<TreeNode>>>) -> DoubleBlackFix {
-        if let Some(child) = node {
-            if let Some(sib) = Self::get_sibling(child) {
-                if sib.as_ref().borrow().color == NodeColor::Red {
-                    //rotation case
-                    if Self::is_left_child(&sib) {
-                        return DoubleBlackFix::RedSibLeft;
-                    } else {
-                        return DoubleBlackFix::RedSibRight;
-                    }
+            } else if self.is_black(self.get_leftchild(sib))
+                && self.is_black(self.get_rightchild(sib))
+            {
+                // sib is black
+                // recolor
+                // 2 black children
+                DoubleBlackFix::BlackSibRecolor
+            } else if self.is_left_child(sib) {
+                if self.is_red(self.get_leftchild(sib)) {
+                    DoubleBlackFix::BlackSibllRotation
                 } else {
-                    // sib is black
-                    if Self::is_black(&Self::get_leftchild(&sib))
-                        && Self::is_black(&Self::get_rightchild(&sib))
-                    {
-                        // recolor
-                        // 2 black children
-
-                        return DoubleBlackFix::BlackSibRecolor;
-                    } else {
-                        if Self::is_left_child(&sib) {
-                            if !Self::is_black(&Self::get_leftchild(&sib)) {
-                                return DoubleBlackFix::BlackSibllRotation;
-                            } else {
-                                return DoubleBlackFix::BlackSiblrRotation;
-                            }
-                        } else {
-                            if !Self::is_black(&Self::get_leftchild(&sib)) {
-                                return DoubleBlackFix::BlackSibrlRotation;
-                            } else {
-                                return DoubleBlackFix::BlackSibrrRotation;
-                            }
-                        }
-                    }
+                    DoubleBlackFix::BlackSiblrRotation
                 }
+            } else if self.is_red(self.get_leftchild(sib)) {
+                DoubleBlackFix::BlackSibrlRotation
             } else {
-                // sib is black
-                return DoubleBlackFix::NullSib;
+                DoubleBlackFix::BlackSibrrRotation
             }
         } else {
-            return DoubleBlackFix::Root;
+            // sib is black
+            DoubleBlackFix::NullSib
         }
     }
 
-    fn fix_double_black(u: &Option<Rc<RefCell<TreeNode>>>, tree: &mut RedBlackTree) {
-        match Self::fix_double_black_helper(u) {
-            DoubleBlackFix::BlackSibllRotation => {
-                if let Some(child) = u {
-                    if let Some(sb) = Self::get_sibling(child) {
-                        if let Some(sb_left_child) = Self::get_sib_left_child(u) {
-                            // sibling.left.color = sibling.color
-                            sb_left_child.as_ref().borrow_mut().color =
-                                sb.as_ref().borrow().color.clone();
-                        }
-                        if let Some(p) = Self::get_parent(child) {
-                            sb.as_ref().borrow_mut().color = p.as_ref().borrow().color.clone();
-                        }
-                    }
-                    if let Some(p) = Self::get_parent(child) {
-                        p.as_ref().borrow_mut().color = NodeColor::Black;
-                    }
-                }
-                if let Some(sb_left_child) = Self::get_sib_left_child(u) {
-                    Self::ll_rotation(&sb_left_child, tree);
-                    Self::recursive_update_height(&sb_left_child);
-                }
+    // `u` is one black node short on its path, fixes that by recolouring and rotating around it
+    fn fix_double_black(&mut self, u: NodeId) {
+        let mode = self.fix_double_black_helper(u);
+        if !matches!(mode, DoubleBlackFix::Root) {
+            self.notify(Step::Case {
+                name: mode.name(),
+                at: u,
+            });
+        }
+        let (parent, sib) = match mode {
+            DoubleBlackFix::Root => return,
+            DoubleBlackFix::NullSib => {
+                let parent = self.get_parent(u).expect("u is not the root");
+                return self.fix_double_black(parent);
+            }
+            _ => (
+                self.get_parent(u).expect("u is not the root"),
+                self.get_sibling(u).expect("u has a sibling"),
+            ),
+        };
+        match mode {
+            DoubleBlackFix::BlackSibllRotation => {
+                let sb_left_child = self.get_leftchild(sib).expect("sibling has a red left child");
+                // sibling.left.color = sibling.color
+                self.change_colour(sb_left_child, self.get_color(sib));
+                // sibling.color = parent.color
+                self.change_colour(sib, self.get_color(parent));
+                self.change_colour(parent, NodeColor::Black);
+                self.rotate_right(parent);
             }
             DoubleBlackFix::BlackSiblrRotation => {
-                if let Some(child) = u {
-                    if let Some(sb) = Self::get_sibling(child) {
-                        if let Some(sb_right_child) = Self::get_sib_right_child(u) {
-                            if let Some(p) = Self::get_parent(child) {
-                                // siblin.left.color = parent.color
-                                sb_right_child.as_ref().borrow_mut().color =
-                                    p.as_ref().borrow().color.clone();
-                            }
-                        }
-                    }
-                    if let Some(p) = Self::get_parent(child) {
-                        // sibling.color = parent.color
-                        p.as_ref().borrow_mut().color = NodeColor::Black;
-                    }
-                }
-                if let Some(sb_right_child) = Self::get_sib_right_child(u) {
-                    let p_cpy = Self::get_parent(&sb_right_child);
-                    let gp_cpy = Self::get_grandparent(&sb_right_child);
-                    Self::lr_rotation(&sb_right_child, tree);
-                    if let Some(gp) = gp_cpy {
-                        Self::update_height(&gp);
-                    }
-                    if let Some(p) = p_cpy {
-                        Self::recursive_update_height(&p);
-                    }
-                }
+                let sb_right_child = self.get_rightchild(sib).expect("sibling has a red right child");
+                // sibling.right.color = parent.color
+                self.change_colour(sb_right_child, self.get_color(parent));
+                self.change_colour(parent, NodeColor::Black);
+                self.rotate_left(sib);
+                self.rotate_right(parent);
             }
             DoubleBlackFix::BlackSibrrRotation => {
-                if let Some(child) = u {
-                    if let Some(sb) = Self::get_sibling(child) {
-                        if let Some(sb_right_child) = Self::get_sib_right_child(u) {
-                            // sibling.right,color = sibling.color
-                            sb_right_child.as_ref().borrow_mut().color =
-                                sb.as_ref().borrow().color.clone();
-                        }
-                        if let Some(p) = Self::get_parent(child) {
-                            // sibling.color = parent.color
-                            sb.as_ref().borrow_mut().color = p.as_ref().borrow().color.clone();
-                        }
-                    }
-                    if let Some(p) = Self::get_parent(child) {
-                        // sibling.color = parent.color
-                        p.as_ref().borrow_mut().color = NodeColor::Black;
-                    }
-                }
-                if let Some(sb_right_child) = Self::get_sib_right_child(u) {
-                    Self::rr_rotation(&sb_right_child, tree);
-                    Self::recursive_update_height(&sb_right_child);
-                }
+                let sb_right_child = self.get_rightchild(sib).expect("sibling has a red right child");
+                // sibling.right,color = sibling.color
+                self.change_colour(sb_right_child, self.get_color(sib));
+                // sibling.color = parent.color
+                self.change_colour(sib, self.get_color(parent));
+                self.change_colour(parent, NodeColor::Black);
+                self.rotate_left(parent);
             }
             DoubleBlackFix::BlackSibrlRotation => {
-                if let Some(child) = u {
-                    if let Some(sb) = Self::get_sibling(child) {
-                        if let Some(sb_left_child) = Self::get_sib_left_child(u) {
-                            if let Some(p) = Self::get_parent(child) {
-                                // siblin.left.color = parent.color
-                                sb_left_child.as_ref().borrow_mut().color =
-                                    p.as_ref().borrow().color.clone();
-                            }
-                        }
-                    }
-                    if let Some(p) = Self::get_parent(child) {
-                        // sibling.color = parent.color
-                        p.as_ref().borrow_mut().color = NodeColor::Black;
-                    }
-                }
-                if let Some(sb_left_child) = Self::get_sib_left_child(u) {
-                    let p_cpy = Self::get_parent(&sb_left_child);
-                    let gp_cpy = Self::get_grandparent(&sb_left_child);
-                    Self::rl_rotation(&sb_left_child, tree);
-                    if let Some(gp) = gp_cpy {
-                        Self::update_height(&gp);
-                    }
-                    if let Some(p) = p_cpy {
-                        Self::recursive_update_height(&p);
-                    }
-                }
+                let sb_left_child = self.get_leftchild(sib).expect("sibling has a red left child");
+                // sibling.left.color = parent.color
+                self.change_colour(sb_left_child, self.get_color(parent));
+                self.change_colour(parent, NodeColor::Black);
+                self.rotate_right(sib);
+                self.rotate_left(parent);
             }
             DoubleBlackFix::BlackSibRecolor => {
-                if let Some(child) = u {
-                    if let Some(sib) = Self::get_sibling(child) {
-                        sib.as_ref().borrow_mut().color = NodeColor::Red;
-                        if Self::is_black(&Self::get_parent(child)) {
-                            Self::fix_double_black(&Self::get_parent(child), tree);
-                        } else {
-                            Self::get_parent(child).as_ref().unwrap().borrow_mut().color =
-                                NodeColor::Black;
-                        }
-                    }
+                self.change_colour(sib, NodeColor::Red);
+                if self.is_black(Some(parent)) {
+                    self.fix_double_black(parent);
+                } else {
+                    self.change_colour(parent, NodeColor::Black);
                 }
             }
             DoubleBlackFix::RedSibLeft => {
-                if let Some(child) = u {
-                    if let Some(sb) = Self::get_sibling(child) {
-                        sb.as_ref().borrow_mut().color = NodeColor::Black;
-                    }
-                    if let Some(p) = Self::get_parent(child) {
-                        p.as_ref().borrow_mut().color = NodeColor::Red;
-                    }
-                }
-                if let Some(sb_right_child) = Self::get_sib_left_child(u) {
-                    Self::ll_rotation(&sb_right_child, tree);
-                }
-                Self::fix_double_black(u, tree)
+                self.change_colour(sib, NodeColor::Black);
+                self.change_colour(parent, NodeColor::Red);
+                self.rotate_right(parent);
+                self.fix_double_black(u)
             }
             DoubleBlackFix::RedSibRight => {
-                if let Some(child) = u {
-                    if let Some(sb) = Self::get_sibling(child) {
-                        sb.as_ref().borrow_mut().color = NodeColor::Black;
-                    }
-                    if let Some(p) = Self::get_parent(child) {
-                        p.as_ref().borrow_mut().color = NodeColor::Red;
-                    }
-                }
+                self.change_colour(sib, NodeColor::Black);
+                self.change_colour(parent, NodeColor::Red);
+                self.rotate_left(parent);
+                self.fix_double_black(u)
+            }
+            DoubleBlackFix::Root | DoubleBlackFix::NullSib => {}
+        }
+    }
+
+    // ---------------------------------------- Navigation ------------------------------------------
+    // Iterates over the entries whose keys fall in the range, from either end
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Range<'_, K, V> {
+        Range::new(&self.nodes, self.root, range.start_bound(), range.end_bound())
+    }
+
+    // Entry with the smallest key
+    pub fn first(&self) -> Option<(&K, &V)> {
+        let node = self.subtree_min(self.root?);
+        Some((self.node_key(node), self.node_value(node)))
+    }
+
+    // Entry with the largest key
+    pub fn last(&self) -> Option<(&K, &V)> {
+        let node = self.subtree_max(self.root?);
+        Some((self.node_key(node), self.node_value(node)))
+    }
+
+    pub fn pop_first(&mut self) -> Option<(K, V)> {
+        let node = self.subtree_min(self.root?);
+        Some(self.delete_node(node))
+    }
+
+    pub fn pop_last(&mut self) -> Option<(K, V)> {
+        let node = self.subtree_max(self.root?);
+        Some(self.delete_node(node))
+    }
+
+    // Entry with the largest key that is less than or equal to the given one
+    pub fn floor(&self, key: &K) -> Option<(&K, &V)> {
+        self.range(..=key).next_back()
+    }
+
+    // Entry with the smallest key that is greater than or equal to the given one
+    pub fn ceiling(&self, key: &K) -> Option<(&K, &V)> {
+        self.range(key..).next()
+    }
+
+    // Entry with the largest key that is strictly less than the given one
+    pub fn predecessor(&self, key: &K) -> Option<(&K, &V)> {
+        self.range(..key).next_back()
+    }
 
-                if let Some(sb_right_child) = Self::get_sib_right_child(u) {
-                    Self::rr_rotation(&sb_right_child, tree);
+    // Entry with the smallest key that is strictly greater than the given one
+    pub fn successor(&self, key: &K) -> Option<(&K, &V)> {
+        self.range((Bound::Excluded(key), Bound::Unbounded)).next()
+    }
+
+    // ---------------------------------------- Order statistics ------------------------------------
+    // Number of keys in the tree that are strictly less than the given one
+    pub fn rank(&self, key: &K) -> usize {
+        let mut rank = 0;
+        let mut current = self.root;
+        while let Some(node) = current {
+            current = if self.nodes[node].key < *key {
+                // everything on the left and the node itself come before the key
+                rank += self.size(self.get_leftchild(node)) + 1;
+                self.get_rightchild(node)
+            } else {
+                self.get_leftchild(node)
+            };
+        }
+        rank
+    }
+
+    // Entry with the index-th smallest key, counting from 0
+    pub fn select(&self, index: usize) -> Option<(&K, &V)> {
+        let mut index = index;
+        let mut current = self.root;
+        while let Some(node) = current {
+            let left_size = self.size(self.get_leftchild(node));
+            current = match index.cmp(&left_size) {
+                std::cmp::Ordering::Equal => {
+                    return Some((self.node_key(node), self.node_value(node)));
                 }
-                Self::fix_double_black(u, tree)
-            }
-            DoubleBlackFix::Root => {}
-            DoubleBlackFix::None => {}
-            DoubleBlackFix::NullSib => {
-                if let Some(child) = u {
-                    Self::fix_double_black(&Self::get_parent(child), tree)
+                std::cmp::Ordering::Less => self.get_leftchild(node),
+                std::cmp::Ordering::Greater => {
+                    index -= left_size + 1;
+                    self.get_rightchild(node)
                 }
+            };
+        }
+        None
+    }
+
+    // ---------------------------------------- Bulk construction -----------------------------------
+    // Builds a tree out of entries that are already sorted by key in O(n), without a single rotation.
+    // Panics if the keys are not strictly ascending.
+    pub fn from_sorted_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let entries: Vec<(K, V)> = iter.into_iter().collect();
+        assert!(
+            entries.windows(2).all(|pair| pair[0].0 < pair[1].0),
+            "from_sorted_iter needs keys in strictly ascending order"
+        );
+        Self::from_sorted_vec(entries)
+    }
+
+    fn from_sorted_vec(entries: Vec<(K, V)>) -> Self {
+        let mut tree = RedBlackTree::new();
+        let len = entries.len();
+        // Splitting in the middle fills every level except the last one. Colouring the nodes on that
+        // partly filled level red and everything above black gives every path the same black height.
+        let red_depth = (len + 1).ilog2() as usize;
+        tree.root = tree.build_sorted(&mut entries.into_iter(), len, 0, red_depth);
+        tree
+    }
+
+    // Builds a subtree out of the next `len` entries and returns its root
+    fn build_sorted<I: Iterator<Item = (K, V)>>(
+        &mut self,
+        entries: &mut I,
+        len: usize,
+        depth: usize,
+        red_depth: usize,
+    ) -> Option<NodeId> {
+        if len == 0 {
+            return None;
+        }
+        let left_len = (len - 1) / 2;
+        let left = self.build_sorted(entries, left_len, depth + 1, red_depth);
+        let (key, value) = entries.next().expect("there are len entries left");
+        let mut node = TreeNode::new(key, value);
+        if depth != red_depth {
+            node.color = NodeColor::Black;
+        }
+        let node = self.nodes.alloc(node);
+        let right = self.build_sorted(entries, len - 1 - left_len, depth + 1, red_depth);
+        self.link(left, node, right);
+        Some(node)
+    }
+
+    // ---------------------------------------- Split & join ----------------------------------------
+    // Splits the tree at the key, the left tree gets the keys below it and the right tree the rest.
+    // Cutting the tree apart takes O(log n), but the smaller of the two halves then moves to an arena
+    // of its own, so a split costs O(log n + min(|left|, |right|)) in all.
+    pub fn split(mut self, key: &K) -> (Self, Self) {
+        let root = self.root.take();
+        let tree = (root, self.black_height(root));
+        let (left, found, right) = self
+            .split_subtree(tree, &|nodes: &Arena<TreeNode<K, V>>, node: NodeId| {
+                key.cmp(&nodes[node].key)
+            });
+        // the key itself goes to the right tree
+        let right = match found {
+            Some(found) => self.join_subtrees((None, 0), found, right),
+            None => right,
+        };
+        self.separate(left, right)
+    }
+
+    // Puts two trees back together around a new entry. Every key in `left` has to be smaller than the
+    // key and every key in `right` larger. The smaller tree moves into the arena of the larger one.
+    pub fn join(left: Self, key: K, value: V, right: Self) -> Self {
+        assert!(
+            left.last().is_none_or(|(last, _)| *last < key)
+                && right.first().is_none_or(|(first, _)| key < *first),
+            "join needs the keys of left below the key and the keys of right above it"
+        );
+        let (mut tree, left, right) = Self::combine(left, right);
+        let node = tree.nodes.alloc(TreeNode::new(key, value));
+        let (root, _) = tree.join_subtrees(left, node, right);
+        tree.set_root(root);
+        tree
+    }
+
+    // Splits a subtree around the node `locate` steers towards. Returns the part before it, the node
+    // itself if it is there, and the part after it.
+    fn split_subtree<F>(&mut self, tree: Subtree, locate: &F) -> (Subtree, Option<NodeId>, Subtree)
+    where
+        F: Fn(&Arena<TreeNode<K, V>>, NodeId) -> std::cmp::Ordering,
+    {
+        let node = match tree.0 {
+            Some(node) => node,
+            None => return ((None, 0), None, (None, 0)),
+        };
+        let child_black_height = self.child_black_height(tree);
+        let (left, right) = self.detach(node);
+        match locate(&self.nodes, node) {
+            std::cmp::Ordering::Equal => (
+                (left, child_black_height),
+                Some(node),
+                (right, child_black_height),
+            ),
+            std::cmp::Ordering::Less => {
+                let (before, found, after) = self.split_subtree((left, child_black_height), locate);
+                let after = self.join_subtrees(after, node, (right, child_black_height));
+                (before, found, after)
+            }
+            std::cmp::Ordering::Greater => {
+                let (before, found, after) =
+                    self.split_subtree((right, child_black_height), locate);
+                let before = self.join_subtrees((left, child_black_height), node, before);
+                (before, found, after)
+            }
+        }
+    }
+
+    // ---------------------------------------- Set operations --------------------------------------
+    // All four take the other tree apart around the root of one and split the other at that key,
+    // which comes to O(m log(n/m + 1)) for trees of sizes m <= n. Entries that do not make it into
+    // the result are dropped. Where both trees have a key the entry of `self` is kept.
+
+    // Entries whose key is in either tree
+    pub fn union(self, other: Self) -> Self {
+        let (mut tree, a, b) = Self::combine(self, other);
+        let (root, _) = tree.union_subtrees(a, b);
+        tree.set_root(root);
+        tree
+    }
+
+    // Entries whose key is in both trees
+    pub fn intersection(self, other: Self) -> Self {
+        let (mut tree, a, b) = Self::combine(self, other);
+        let (root, _) = tree.intersection_subtrees(a, b);
+        tree.set_root(root);
+        tree
+    }
+
+    // Entries of `self` whose key is not in `other`
+    pub fn difference(self, other: Self) -> Self {
+        let (mut tree, a, b) = Self::combine(self, other);
+        let (root, _) = tree.difference_subtrees(a, b);
+        tree.set_root(root);
+        tree
+    }
+
+    // Entries whose key is in exactly one of the trees
+    pub fn symmetric_difference(self, other: Self) -> Self {
+        let (mut tree, a, b) = Self::combine(self, other);
+        let (root, _) = tree.symmetric_difference_subtrees(a, b);
+        tree.set_root(root);
+        tree
+    }
+
+    // Takes `b` apart at its root and splits `a` at the root's key. Returns the two halves of `a`, the
+    // node of `a` with that key if there is one, the root of `b` and the two halves of `b`.
+    fn split_at_root(&mut self, a: Subtree, b: Subtree) -> SplitAtRoot {
+        let pivot = b.0.expect("b is not empty");
+        let child_black_height = self.child_black_height(b);
+        let (b_left, b_right) = self.detach(pivot);
+        let (a_left, found, a_right) = self
+            .split_subtree(a, &|nodes: &Arena<TreeNode<K, V>>, node: NodeId| {
+                nodes[pivot].key.cmp(&nodes[node].key)
+            });
+        SplitAtRoot {
+            a_left,
+            found,
+            a_right,
+            pivot,
+            b_left: (b_left, child_black_height),
+            b_right: (b_right, child_black_height),
+        }
+    }
+
+    fn union_subtrees(&mut self, a: Subtree, b: Subtree) -> Subtree {
+        if a.0.is_none() {
+            return b;
+        }
+        if b.0.is_none() {
+            return a;
+        }
+        let split = self.split_at_root(a, b);
+        let left = self.union_subtrees(split.a_left, split.b_left);
+        let right = self.union_subtrees(split.a_right, split.b_right);
+        let node = match split.found {
+            Some(found) => {
+                self.nodes.free(split.pivot);
+                found
+            }
+            None => split.pivot,
+        };
+        self.join_subtrees(left, node, right)
+    }
+
+    fn intersection_subtrees(&mut self, a: Subtree, b: Subtree) -> Subtree {
+        if a.0.is_none() || b.0.is_none() {
+            self.free_subtree(a.0);
+            self.free_subtree(b.0);
+            return (None, 0);
+        }
+        let split = self.split_at_root(a, b);
+        self.nodes.free(split.pivot);
+        let left = self.intersection_subtrees(split.a_left, split.b_left);
+        let right = self.intersection_subtrees(split.a_right, split.b_right);
+        match split.found {
+            Some(found) => self.join_subtrees(left, found, right),
+            None => self.join_pair(left, right),
+        }
+    }
+
+    fn difference_subtrees(&mut self, a: Subtree, b: Subtree) -> Subtree {
+        if a.0.is_none() || b.0.is_none() {
+            self.free_subtree(b.0);
+            return a;
+        }
+        let split = self.split_at_root(a, b);
+        self.nodes.free(split.pivot);
+        self.free_subtree(split.found);
+        let left = self.difference_subtrees(split.a_left, split.b_left);
+        let right = self.difference_subtrees(split.a_right, split.b_right);
+        self.join_pair(left, right)
+    }
+
+    fn symmetric_difference_subtrees(&mut self, a: Subtree, b: Subtree) -> Subtree {
+        if a.0.is_none() {
+            return b;
+        }
+        if b.0.is_none() {
+            return a;
+        }
+        let split = self.split_at_root(a, b);
+        let left = self.symmetric_difference_subtrees(split.a_left, split.b_left);
+        let right = self.symmetric_difference_subtrees(split.a_right, split.b_right);
+        match split.found {
+            Some(found) => {
+                self.nodes.free(split.pivot);
+                self.nodes.free(found);
+                self.join_pair(left, right)
             }
+            None => self.join_subtrees(left, split.pivot, right),
+        }
+    }
+
+    fn node_key(&self, node: NodeId) -> &K {
+        &self.nodes[node].key
+    }
+
+    fn node_value(&self, node: NodeId) -> &V {
+        &self.nodes[node].value
+    }
+
+    fn node_value_mut(&mut self, node: NodeId) -> &mut V {
+        &mut self.nodes[node].value
+    }
+}
+
+impl<K: Ord, V: Default> RedBlackTree<K, V> {
+    // Inserts the key with a default value and returns true if it was new. A key that is already in
+    // the tree is left alone, value included, and false is returned.
+    pub fn tree_insert(&mut self, key: K) -> bool {
+        match self.entry(key) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(V::default());
+                true
+            }
+        }
+    }
+}
+
+impl<K: Ord + Codec, V: Codec> RedBlackTree<K, V> {
+    // ---------------------------------------- Snapshots -------------------------------------------
+    // The entries in the binary snapshot format, see the snapshot module
+    pub fn to_snapshot(&self) -> Vec<u8> {
+        snapshot::encode(self.len(), self.iter())
+    }
+
+    // Rebuilds a tree from a snapshot in O(n), the entries are already in order
+    pub fn from_snapshot(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        snapshot::decode(bytes).map(Self::from_sorted_vec)
+    }
+
+    pub fn save_to<P: AsRef<Path>>(&self, path: P) -> Result<(), SnapshotError> {
+        fs::write(path, self.to_snapshot())?;
+        Ok(())
+    }
+
+    pub fn load_from<P: AsRef<Path>>(path: P) -> Result<Self, SnapshotError> {
+        Self::from_snapshot(&fs::read(path)?)
+    }
+}
+
+impl<K, V> RedBlackTree<K, V> {
+    // Iterates over the entries in key order, from either end
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter::new(&self.nodes, self.root, self.size(self.root))
+    }
+
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys { inner: self.iter() }
+    }
+
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values { inner: self.iter() }
+    }
+
+    pub fn into_keys(self) -> IntoKeys<K, V> {
+        IntoKeys { inner: self.into_iter() }
+    }
+
+    pub fn into_values(self) -> IntoValues<K, V> {
+        IntoValues { inner: self.into_iter() }
+    }
+}
+
+impl<K: Ord + Debug, V> RedBlackTree<K, V> {
+    // Checks that the tree is a valid red-black tree: keys in order, parent pointers, cached heights
+    // and sizes, a black root, no red node with a red child and the same black height on every path.
+    // The first broken rule is reported together with the node it was found at.
+    pub fn validate(&self) -> Result<(), InvariantViolation> {
+        if let Some(root) = self.root {
+            if self.get_color(root) != NodeColor::Black {
+                return Err(InvariantViolation::new(
+                    Invariant::RootColor,
+                    self.node_key(root),
+                ));
+            }
+        }
+        self.validate_node(self.root, None, None, None).map(|_| ())
+    }
+
+    // ---------------------------------------- Validate ---------------------------------------------
+    // Checks the subtree below `node` against every rule, `parent` is the node it should point back
+    // at and the keys have to stay strictly between `lower` and `upper`.
+    // Returns the black height, the height and the size of the subtree.
+    fn validate_node(
+        &self,
+        node: Option<NodeId>,
+        parent: Option<NodeId>,
+        lower: Option<&K>,
+        upper: Option<&K>,
+    ) -> Result<(usize, i32, usize), InvariantViolation> {
+        let node_id = match node {
+            Some(node) => node,
+            // empty children count as black
+            None => return Ok((1, 0, 0)),
+        };
+        let node = &self.nodes[node_id];
+        let violation = |invariant| Err(InvariantViolation::new(invariant, &node.key));
+
+        if node.parent != parent {
+            return violation(Invariant::ParentPointer);
+        }
+        if lower.is_some_and(|lower| node.key <= *lower)
+            || upper.is_some_and(|upper| node.key >= *upper)
+        {
+            return violation(Invariant::Order);
+        }
+        if node.color == NodeColor::Red && (self.is_red(node.left) || self.is_red(node.right)) {
+            return violation(Invariant::RedRed);
+        }
+
+        let (left_black, left_height, left_size) =
+            self.validate_node(node.left, Some(node_id), lower, Some(&node.key))?;
+        let (right_black, right_height, right_size) =
+            self.validate_node(node.right, Some(node_id), Some(&node.key), upper)?;
+        if left_black != right_black {
+            return violation(Invariant::BlackHeight);
+        }
+        let height = std::cmp::max(left_height, right_height) + 1;
+        if node.height != height {
+            return violation(Invariant::Height);
+        }
+        let size = left_size + right_size + 1;
+        if node.size != size {
+            return violation(Invariant::Size);
+        }
+
+        let black_height = left_black + usize::from(node.color == NodeColor::Black);
+        Ok((black_height, height, size))
+    }
+}
+
+impl<K: Debug, V> RedBlackTree<K, V> {
+    pub fn print_tree(&self) {
+        println!("++++++++++++++Pretty tree+++++++++");
+        println!(" ┌ denotes left, └ denotes right\n");
+        self.write_tree(io::stdout().lock()).expect("failed to write to stdout");
+    }
+
+    // The lines print_tree shows below its header, one per node with its colour
+    pub fn write_tree<W: Write>(&self, mut out: W) -> io::Result<()> {
+        self.pretty_print(&mut out, self.root, "", false, true)
+    }
+
+    pub fn in_order_traversal(&self) {
+        self.in_order_traversal_recursive(self.root);
+    }
+
+    // Uses recursion to print out he in order traversal of the AVL tree by traversing through the tree to the left first, then the right
+    fn in_order_traversal_recursive(&self, node: Option<NodeId>) {
+        if let Some(n) = node {
+            let node = &self.nodes[n];
+            self.in_order_traversal_recursive(node.left);
+            println!("{:?}", node.key);
+            self.in_order_traversal_recursive(node.right);
         }
     }
 
     // ---------------------------------------- Print ------------------------------------------------
-    pub fn pretty_print(
-        node: &Option<Rc<RefCell<TreeNode>>>,
+    fn pretty_print<W: Write>(
+        &self,
+        out: &mut W,
+        node: Option<NodeId>,
         prefix: &str,
         is_left: bool,
         is_root: bool,
-    ) {
-        if is_root {
-            println!("++++++++++++++Pretty tree+++++++++");
-            println!(" ┌ denotes left, └ denotes right\n");
-        }
+    ) -> io::Result<()> {
         match node {
-            None => return,
+            None => Ok(()),
             Some(n) => {
-                let node_ref = n.borrow();
+                let node_ref = &self.nodes[n];
                 let color_str = match node_ref.color {
                     NodeColor::Red => "R",
                     NodeColor::Black => "B",
                 };
-                print!(
+                write!(
+                    out,
                     "{}{}{}─",
                     prefix,
                     if is_left {
@@ -1057,125 +1505,426 @@ impl TreeNode {
                         "└-"
                     },
                     color_str
-                );
-                println!("{}", node_ref.key);
+                )?;
+                writeln!(out, "{:?}", node_ref.key)?;
                 let new_prefix = format!("{}{}", prefix, if is_left { "│ " } else { "  " });
-                Self::pretty_print(&node_ref.left, &new_prefix, true, false);
-                Self::pretty_print(&node_ref.right, &new_prefix, false, false);
+                self.pretty_print(out, node_ref.left, &new_prefix, true, false)?;
+                self.pretty_print(out, node_ref.right, &new_prefix, false, false)
             }
         }
     }
-}
 
-impl RedBlackTree {
-    pub fn new() -> RedBlackTree {
-        RedBlackTree { root: None }
+    // ---------------------------------------- Export -----------------------------------------------
+    // The tree in Graphviz DOT with every node filled in its colour, `dot -Tsvg` renders it. With
+    // `null_leaves` the empty children are drawn as points, the way textbooks draw red-black trees.
+    pub fn to_dot(&self, null_leaves: bool) -> String {
+        export::to_string(|out| self.write_dot(out, null_leaves))
     }
-    fn clone(&self) -> Self {
-        if let Some(node) = &self.root {
-            return RedBlackTree {
-                root: Some(Rc::clone(node)),
-            };
-        } else {
-            return RedBlackTree { root: None };
+
+    pub fn write_dot<W: Write>(&self, mut out: W, null_leaves: bool) -> io::Result<()> {
+        writeln!(out, "digraph RedBlackTree {{")?;
+        writeln!(
+            out,
+            "    node [shape=circle, style=filled, fontcolor=white];"
+        )?;
+        if let Some(root) = self.root {
+            self.write_dot_node(&mut out, root, null_leaves, &mut 0)?;
+        }
+        writeln!(out, "}}")
+    }
+
+    // Writes the subtree and returns the number its root was written under. Nodes are numbered in
+    // preorder, so two trees of the same shape give the same output whatever their arenas look like.
+    fn write_dot_node<W: Write>(
+        &self,
+        out: &mut W,
+        node: NodeId,
+        null_leaves: bool,
+        next: &mut usize,
+    ) -> io::Result<usize> {
+        let id = *next;
+        *next += 1;
+        let node = &self.nodes[node];
+        let fill = match node.color {
+            NodeColor::Red => "red",
+            NodeColor::Black => "black",
+        };
+        writeln!(
+            out,
+            "    n{} [label={}, fillcolor={}];",
+            id,
+            export::dot_string(&node.key),
+            fill
+        )?;
+        for child in [node.left, node.right] {
+            match child {
+                Some(child) => {
+                    let child = self.write_dot_node(out, child, null_leaves, next)?;
+                    writeln!(out, "    n{} -> n{};", id, child)?;
+                }
+                None if null_leaves => {
+                    writeln!(out, "    n{} [shape=point];", *next)?;
+                    writeln!(out, "    n{} -> n{};", id, *next)?;
+                    *next += 1;
+                }
+                // an invisible placeholder keeps an only child on its own side
+                None if node.left.is_some() || node.right.is_some() => {
+                    writeln!(out, "    n{} [style=invis];", *next)?;
+                    writeln!(out, "    n{} -> n{} [style=invis];", id, *next)?;
+                    *next += 1;
+                }
+                None => {}
+            }
         }
+        Ok(id)
+    }
+
+    // The structure of the tree as JSON, one node per object with its key, colour, subtree size and
+    // children. The output is indented so two dumps can be compared line by line.
+    pub fn to_json(&self) -> String {
+        export::to_string(|out| self.write_json(out))
+    }
+
+    pub fn write_json<W: Write>(&self, mut out: W) -> io::Result<()> {
+        writeln!(out, "{{")?;
+        writeln!(out, "  \"kind\": \"red-black\",")?;
+        writeln!(out, "  \"len\": {},", self.size(self.root))?;
+        write!(out, "  \"root\": ")?;
+        self.write_json_node(&mut out, self.root, 1)?;
+        writeln!(out)?;
+        writeln!(out, "}}")
+    }
+
+    fn write_json_node<W: Write>(
+        &self,
+        out: &mut W,
+        node: Option<NodeId>,
+        depth: usize,
+    ) -> io::Result<()> {
+        let node = match node {
+            Some(node) => &self.nodes[node],
+            None => return write!(out, "null"),
+        };
+        let color = match node.color {
+            NodeColor::Red => "red",
+            NodeColor::Black => "black",
+        };
+        let pad = export::pad(depth + 1);
+        writeln!(out, "{{")?;
+        writeln!(out, "{}\"key\": {},", pad, export::json_string(&node.key))?;
+        writeln!(out, "{}\"color\": \"{}\",", pad, color)?;
+        writeln!(out, "{}\"size\": {},", pad, node.size)?;
+        write!(out, "{}\"left\": ", pad)?;
+        self.write_json_node(out, node.left, depth + 1)?;
+        writeln!(out, ",")?;
+        write!(out, "{}\"right\": ", pad)?;
+        self.write_json_node(out, node.right, depth + 1)?;
+        writeln!(out)?;
+        write!(out, "{}}}", export::pad(depth))
     }
-    pub fn get_height(&self) -> i32 {
-        return TreeNode::height(&self.root);
+}
+
+// ---------------------------------------- Iterators ------------------------------------------------
+// The in order iterators are shared with the AVL map, see map.rs
+pub type Iter<'a, K, V> = map::Iter<'a, TreeNode<K, V>>;
+pub type Range<'a, K, V> = map::Range<'a, TreeNode<K, V>>;
+pub type Keys<'a, K, V> = map::Keys<'a, TreeNode<K, V>>;
+pub type Values<'a, K, V> = map::Values<'a, TreeNode<K, V>>;
+pub use crate::map::{IntoIter, IntoKeys, IntoValues};
+
+impl<K, V> IntoIterator for RedBlackTree<K, V> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter::new(self.nodes, self.root)
     }
+}
 
-    pub fn print_tree(&self) {
-        TreeNode::pretty_print(&self.root, "", false, true);
+impl<'a, K, V> IntoIterator for &'a RedBlackTree<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
     }
+}
 
-    pub fn is_empty(&self) -> bool {
-        self.root.is_none()
+impl<K: Ord, V> Default for RedBlackTree<K, V> {
+    fn default() -> Self {
+        RedBlackTree::new()
     }
-    pub fn count_leaves(&self) -> usize {
-        fn count_leaves_helper(node: &Option<Rc<RefCell<TreeNode>>>) -> usize {
-            match node {
-                None => 0,
-                Some(n) => {
-                    let node_borrow = n.borrow();
-                    if node_borrow.left.is_none() && node_borrow.right.is_none() {
-                        1
-                    } else {
-                        count_leaves_helper(&node_borrow.left)
-                            + count_leaves_helper(&node_borrow.right)
-                    }
-                }
+}
+
+// Entries can come in any order, they are sorted first and then built in one go. For a key that
+// shows up more than once the first key is kept with the last value, the same as inserting them
+// one by one.
+impl<K: Ord, V> FromIterator<(K, V)> for RedBlackTree<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut entries: Vec<(K, V)> = iter.into_iter().collect();
+        // stable, so entries with the same key stay in the order they came in
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        entries.dedup_by(|next, kept| {
+            let duplicate = next.0 == kept.0;
+            if duplicate {
+                mem::swap(&mut next.1, &mut kept.1);
             }
-        }
+            duplicate
+        });
+        Self::from_sorted_vec(entries)
+    }
+}
 
-        count_leaves_helper(&self.root)
+impl<K: Ord> FromIterator<K> for RedBlackTree<K> {
+    fn from_iter<I: IntoIterator<Item = K>>(iter: I) -> Self {
+        iter.into_iter().map(|key| (key, ())).collect()
     }
+}
 
-    pub fn tree_insert(&mut self, key: u32) {
-        if let Some(ref mut current_node) = self.root {
-            // have a node already
-            TreeNode::node_insert(self, key);
-        } else {
-            // case x is the root
-            println!("insert root node!");
-            let mut new_node = TreeNode::new(key);
-            new_node.color = NodeColor::Black;
-            let rc = Rc::new(RefCell::new(new_node));
-            *self = RedBlackTree {
-                root: Some(rc.clone()),
-            };
+impl<K: Ord, V> Extend<(K, V)> for RedBlackTree<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
         }
     }
+}
 
-    pub fn in_order_traversal(&self) {
-        if let Some(node) = &self.root {
-            let node_borrow = node.borrow();
-            Self::in_order_traversal_recursive(node_borrow.left.as_ref());
-            println!("{:?}", node_borrow.key);
-            Self::in_order_traversal_recursive(node_borrow.right.as_ref());
+impl<K: Ord> Extend<K> for RedBlackTree<K> {
+    fn extend<I: IntoIterator<Item = K>>(&mut self, iter: I) {
+        for key in iter {
+            self.tree_insert(key);
         }
     }
+}
 
-    pub fn contains(&self, key: u32) -> bool {
-        match TreeNode::get(&self.root, key) {
-            Some(_) => true,
-            None => false,
-        }
+impl<K: Ord> OrderedSet<K> for RedBlackTree<K> {
+    type Iter<'a>
+        = Keys<'a, K, ()>
+    where
+        K: 'a;
+
+    const NAME: &'static str = "RedBlack Tree";
+
+    fn new() -> Self {
+        RedBlackTree::new()
     }
 
-    // Uses recursion to print out he in order traversal of the AVL tree by traversing through the tree to the left first, then the right
-    pub fn in_order_traversal_recursive(node: Option<&Rc<RefCell<TreeNode>>>) {
-        if let Some(n) = node {
-            let node_borrow = n.borrow();
-            Self::in_order_traversal_recursive(node_borrow.left.as_ref());
-            println!("{:?}", node_borrow.key);
-            Self::in_order_traversal_recursive(node_borrow.right.as_ref());
-        }
+    fn insert(&mut self, value: K) -> bool {
+        self.tree_insert(value)
     }
 
-    pub fn get(&self, key: u32) -> Option<Rc<RefCell<TreeNode>>> {
-        TreeNode::get(&self.root, key)
+    fn remove(&mut self, value: &K) -> bool {
+        RedBlackTree::remove(self, value).is_some()
     }
 
-    pub fn root_key(&self) -> Option<u32> {
-        if let Some(root) = &self.root {
-            return Some(root.as_ref().borrow().key);
-        } else {
-            None
-        }
+    fn contains(&self, value: &K) -> bool {
+        RedBlackTree::contains(self, value)
     }
-    pub fn delete(&mut self, key: u32) {
-        if let Some(root) = &self.root {
-            if let Some(delete_node) = TreeNode::get(&self.root, key) {
-                let root_key = self.root_key().unwrap();
-                TreeNode::delete(delete_node, self, root_key);
-            } else {
-                println!("Node not found D:");
-            }
-        } else {
-            println!("Empty Tree D:");
+
+    fn len(&self) -> usize {
+        RedBlackTree::len(self)
+    }
+
+    fn height(&self) -> i32 {
+        self.get_height()
+    }
+
+    fn count_leaves(&self) -> usize {
+        RedBlackTree::count_leaves(self)
+    }
+
+    fn stats(&self) -> Option<TreeStats> {
+        Some(RedBlackTree::stats(self))
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        self.keys()
+    }
+
+    fn range<'a>(
+        &'a self,
+        start: Bound<&K>,
+        end: Bound<&K>,
+    ) -> Box<dyn DoubleEndedIterator<Item = &'a K> + 'a> {
+        Box::new(RedBlackTree::range(self, (start, end)).map(|(key, _)| key))
+    }
+
+    fn rank(&self, value: &K) -> usize {
+        RedBlackTree::rank(self, value)
+    }
+
+    fn select(&self, index: usize) -> Option<&K> {
+        RedBlackTree::select(self, index).map(|(key, _)| key)
+    }
+
+    fn validate(&self) -> Result<(), InvariantViolation>
+    where
+        K: Debug,
+    {
+        RedBlackTree::validate(self)
+    }
+
+    fn print(&self)
+    where
+        K: Debug,
+    {
+        self.print_tree();
+    }
+
+    fn save_to(&self, path: &Path) -> Result<(), SnapshotError>
+    where
+        K: Codec,
+    {
+        RedBlackTree::save_to(self, path)
+    }
+
+    fn load_from(path: &Path) -> Result<Self, SnapshotError>
+    where
+        K: Codec,
+    {
+        RedBlackTree::load_from(path)
+    }
+}
+
+// ---------------------------------------- Serde ------------------------------------------------
+// With the serde feature a tree is written as the sequence of its (key, value) pairs in key order
+// and read back with a bulk build. That holds for a set too: RedBlackTree<K> is written as (key, ())
+// pairs, which serde_json shows as [key, null], and not as the plain values AVLTree<T> is written
+// as, so the two set forms can't read each other's output.
+#[cfg(feature = "serde")]
+impl<K: serde::Serialize, V: serde::Serialize> serde::Serialize for RedBlackTree<K, V> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        map::serialize(self.iter(), serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K, V> serde::Deserialize<'de> for RedBlackTree<K, V>
+where
+    K: Ord + serde::Deserialize<'de>,
+    V: serde::Deserialize<'de>,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        map::deserialize(deserializer)
+    }
+}
+
+// ---------------------------------------- Entry ------------------------------------------------
+// Mirrors std::collections::btree_map::Entry, shared with the AVL map (see map.rs)
+pub type Entry<'a, K, V> = map::Entry<'a, RedBlackTree<K, V>>;
+pub type VacantEntry<'a, K, V> = map::VacantEntry<'a, RedBlackTree<K, V>>;
+pub type OccupiedEntry<'a, K, V> = map::OccupiedEntry<'a, RedBlackTree<K, V>>;
+
+impl<K: Ord, V> EntryMap for RedBlackTree<K, V> {
+    type Key = K;
+    type Value = V;
+
+    fn find_node(&self, key: &K) -> Option<NodeId> {
+        self.get_node(key)
+    }
+
+    fn insert_node(&mut self, key: K, value: V) -> NodeId {
+        RedBlackTree::insert_node(self, key, value)
+    }
+
+    fn remove_node(&mut self, node: NodeId) -> (K, V) {
+        self.delete_node(node)
+    }
+
+    fn node_key(&self, node: NodeId) -> &K {
+        RedBlackTree::node_key(self, node)
+    }
+
+    fn node_value(&self, node: NodeId) -> &V {
+        RedBlackTree::node_value(self, node)
+    }
+
+    fn node_value_mut(&mut self, node: NodeId) -> &mut V {
+        RedBlackTree::node_value_mut(self, node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // Each test breaks one rule by hand in a valid tree and checks that validate names that rule
+    // and the node it is broken at
+    use super::*;
+
+    // 1 to 7 inserted in order: black 2 at the root with black 1 on the left and red 4 on the
+    // right, 4 has black 3 and 6, and 6 has red 5 and 7
+    fn tree() -> RedBlackTree<i32> {
+        let mut tree = RedBlackTree::new();
+        for key in 1..=7 {
+            tree.insert(key, ());
         }
+        let red: Vec<bool> = (1..=7).map(|key| tree.is_red(Some(node(&tree, key)))).collect();
+        assert_eq!(red, [false, false, false, true, true, false, true]);
+        assert_eq!(tree.validate(), Ok(()));
+        tree
     }
 
-    // fix should be called after we inserted a leaf node
-    // deal with the error
+    fn node(tree: &RedBlackTree<i32>, key: i32) -> NodeId {
+        tree.get_node(&key).expect("the key is in the tree")
+    }
+
+    fn broken_at(tree: &RedBlackTree<i32>, invariant: Invariant, key: i32) {
+        assert_eq!(tree.validate(), Err(InvariantViolation::new(invariant, &key)));
+    }
+
+    #[test]
+    fn red_root() {
+        let mut tree = tree();
+        let root = node(&tree, 2);
+        tree.nodes[root].color = NodeColor::Red;
+        broken_at(&tree, Invariant::RootColor, 2);
+    }
+
+    #[test]
+    fn red_node_with_a_red_child() {
+        let mut tree = tree();
+        let six = node(&tree, 6);
+        tree.nodes[six].color = NodeColor::Red;
+        broken_at(&tree, Invariant::RedRed, 4);
+    }
+
+    #[test]
+    fn black_heights_that_differ() {
+        let mut tree = tree();
+        let five = node(&tree, 5);
+        tree.nodes[five].color = NodeColor::Black;
+        broken_at(&tree, Invariant::BlackHeight, 6);
+    }
+
+    #[test]
+    fn stored_height() {
+        let mut tree = tree();
+        let three = node(&tree, 3);
+        tree.nodes[three].height = 2;
+        broken_at(&tree, Invariant::Height, 3);
+    }
+
+    #[test]
+    fn subtree_size() {
+        let mut tree = tree();
+        let six = node(&tree, 6);
+        tree.nodes[six].size = 4;
+        broken_at(&tree, Invariant::Size, 6);
+    }
+
+    #[test]
+    fn parent_link() {
+        let mut tree = tree();
+        let (seven, four) = (node(&tree, 7), node(&tree, 4));
+        tree.nodes[seven].parent = Some(four);
+        broken_at(&tree, Invariant::ParentPointer, 7);
+    }
+
+    #[test]
+    fn key_order() {
+        let mut tree = tree();
+        let (three, five) = (node(&tree, 3), node(&tree, 5));
+        tree.swap_entry(three, five);
+        // 5 now sits left of 4, where only keys between 2 and 4 belong
+        broken_at(&tree, Invariant::Order, 5);
+    }
 }
@@ -0,0 +1,30 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+// Random numbers for the randomized structures, the treap priorities and the skip list tower
+// heights. This is splitmix64: tiny, fast, and good enough to keep the expected O(log n) bounds,
+// but not meant for anything where the numbers must not be guessed. The same seed gives the same
+// sequence, so a structure built with a fixed seed has the same shape every run.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn with_seed(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    // Seeded from the per process random keys std uses for HashMap
+    pub fn from_entropy() -> Self {
+        Rng::with_seed(RandomState::new().build_hasher().finish())
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
@@ -0,0 +1,412 @@
+use project2::btree::DEFAULT_ORDER;
+use project2::{AVLTree, BTree, OrderedSet, RedBlackTree, SkipList, Step, Treap};
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::ops::Bound;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+// Non-interactive mode of the CLI. Commands are read one per line from a file or stdin and every
+// command writes exactly one line of output, so the output of a script can be lined up with its
+// commands and compared by other programs:
+//
+//     insert 5        true if the value was added, false if it was already there
+//     delete 5        true if the value was removed, false if it was not there
+//     contains 5      true or false
+//     len, height, leaves, empty
+//     print           the values in order, separated by spaces
+//     range 1 10      the values from 1 to 10, both included
+//     min, max        the smallest or largest value, or none
+//     rank 5          how many values are smaller than 5
+//     select 2        the value at index 2 in order, or none
+//     validate        ok, or the first broken tree rule as an error
+//     stats           node and leaf counts, leaf depths, height against the optimum and more, see
+//                     TreeStats; an error for structures that are not binary trees
+//     save FILE, load FILE, clear
+//
+// A command that fails writes a line starting with "error:" instead. Blank lines and lines starting
+// with # are skipped without output.
+//
+// With --explain, every insert and delete on a red-black or AVL tree first writes the steps it took,
+// each indented by two spaces and followed by the tree right after it indented by four, and then
+// its usual line. Other commands write only their usual line.
+pub const USAGE: &str =
+    "usage: project2 [--script] [--explain] [--tree rb|avl|btree|treap|skiplist] [--order N] [FILE]
+
+Without arguments the interactive menu starts. With --script, or a FILE to read commands from,
+commands are read one per line (from stdin when there is no FILE) and run against an empty tree.
+--tree picks the tree type, red-black by default. --order sets the order of a B-tree, at least 3
+and 16 by default; clear and load keep it. --explain also prints every rotation, recolouring and
+fix-up case of inserts and deletes with the tree after it, for the rb and avl trees.";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TreeKind {
+    RedBlack,
+    Avl,
+    BTree { order: usize },
+    Treap,
+    SkipList,
+}
+
+// What the command line asked for
+#[derive(Debug, PartialEq, Eq)]
+pub enum Mode {
+    Interactive,
+    Script {
+        tree: TreeKind,
+        file: Option<String>,
+        explain: bool,
+    },
+}
+
+pub fn parse_args(args: &[String]) -> Result<Mode, String> {
+    let mut script = false;
+    let mut explain = false;
+    let mut tree = None;
+    let mut order = None;
+    let mut file = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--script" => script = true,
+            "--explain" => explain = true,
+            "--tree" => {
+                tree = match args.next().map(String::as_str) {
+                    Some("rb") => Some(TreeKind::RedBlack),
+                    Some("avl") => Some(TreeKind::Avl),
+                    Some("btree") => Some(TreeKind::BTree {
+                        order: DEFAULT_ORDER,
+                    }),
+                    Some("treap") => Some(TreeKind::Treap),
+                    Some("skiplist") => Some(TreeKind::SkipList),
+                    Some(other) => return Err(format!("unknown tree type {}", other)),
+                    None => {
+                        return Err("--tree needs rb, avl, btree, treap or skiplist".to_string())
+                    }
+                }
+            }
+            "--order" => {
+                order = match args.next().map(|order| order.parse()) {
+                    Some(Ok(order)) if order >= 3 => Some(order),
+                    _ => return Err("--order needs a number of at least 3".to_string()),
+                }
+            }
+            flag if flag.starts_with('-') && flag != "-" => {
+                return Err(format!("unknown option {}", flag))
+            }
+            path if file.is_none() => file = Some(path.to_string()),
+            extra => return Err(format!("unexpected argument {}", extra)),
+        }
+    }
+    if !script && !explain && tree.is_none() && order.is_none() && file.is_none() {
+        return Ok(Mode::Interactive);
+    }
+    let tree = match (tree.unwrap_or(TreeKind::RedBlack), order) {
+        (TreeKind::BTree { .. }, Some(order)) => TreeKind::BTree { order },
+        (_, Some(_)) => return Err("--order works with the btree tree only".to_string()),
+        (tree, None) => tree,
+    };
+    if explain && !matches!(tree, TreeKind::RedBlack | TreeKind::Avl) {
+        return Err("--explain works with the rb and avl trees only".to_string());
+    }
+    Ok(Mode::Script {
+        tree,
+        // "-" is stdin, the same as no file
+        file: file.filter(|path| path != "-"),
+        explain,
+    })
+}
+
+// Runs a script against a new tree of the given kind. Returns how many commands failed.
+pub fn run<R: BufRead, W: Write>(
+    tree: TreeKind,
+    explain: bool,
+    input: R,
+    output: W,
+) -> io::Result<usize> {
+    match (tree, explain) {
+        (TreeKind::RedBlack, false) => run_with(&mut RedBlackTree::new(), None, input, output),
+        (TreeKind::RedBlack, true) => {
+            let explain = Explain::new(watch_red_black);
+            run_with(&mut RedBlackTree::new(), Some(&explain), input, output)
+        }
+        (TreeKind::Avl, false) => run_with(&mut AVLTree::new(), None, input, output),
+        (TreeKind::Avl, true) => {
+            let explain = Explain::new(watch_avl);
+            run_with(&mut AVLTree::new(), Some(&explain), input, output)
+        }
+        // parse_args turns --explain away for the others
+        (TreeKind::BTree { order }, _) => {
+            run_with(&mut BTree::with_order(order), None, input, output)
+        }
+        (TreeKind::Treap, _) => run_with(&mut Treap::new(), None, input, output),
+        (TreeKind::SkipList, _) => run_with(&mut SkipList::new(), None, input, output),
+    }
+}
+
+// Entry point from main for script mode, returns the exit code of the process
+pub fn run_from(tree: TreeKind, explain: bool, file: Option<&str>) -> i32 {
+    let stdout = io::stdout();
+    let result = match file {
+        Some(path) => match File::open(path) {
+            Ok(file) => run(tree, explain, BufReader::new(file), stdout.lock()),
+            Err(err) => {
+                eprintln!("could not open {}: {}", path, err);
+                return 2;
+            }
+        },
+        None => run(tree, explain, io::stdin().lock(), stdout.lock()),
+    };
+    match result {
+        Ok(0) => 0,
+        Ok(_) => 1,
+        Err(err) => {
+            eprintln!("{}", err);
+            2
+        }
+    }
+}
+
+fn run_with<S: OrderedSet<i32>, R: BufRead, W: Write>(
+    tree: &mut S,
+    explain: Option<&Explain<S>>,
+    input: R,
+    mut output: W,
+) -> io::Result<usize> {
+    if let Some(explain) = explain {
+        explain.watch(tree);
+    }
+    let mut failed = 0;
+    for line in input.lines() {
+        let line = line?;
+        let result = match parse(&line) {
+            Ok(None) => continue,
+            Ok(Some(command)) => {
+                // load and clear put a new tree in place, which has to be watched again
+                let replaces = matches!(command, Command::Load(_) | Command::Clear);
+                let result = execute(tree, command);
+                if let Some(explain) = explain {
+                    if replaces {
+                        explain.watch(tree);
+                    }
+                    output.write_all(explain.take_steps().as_bytes())?;
+                }
+                result
+            }
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(out) => writeln!(output, "{}", out)?,
+            Err(err) => {
+                failed += 1;
+                writeln!(output, "error: {}", err)?;
+            }
+        }
+    }
+    output.flush()?;
+    Ok(failed)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Command {
+    Insert(i32),
+    Delete(i32),
+    Contains(i32),
+    Len,
+    Height,
+    Leaves,
+    Empty,
+    Print,
+    Range(i32, i32),
+    Min,
+    Max,
+    Rank(i32),
+    Select(usize),
+    Validate,
+    Stats,
+    Save(String),
+    Load(String),
+    Clear,
+}
+
+// Reads one line of a script, None for a line without a command
+fn parse(line: &str) -> Result<Option<Command>, String> {
+    let mut words = line.split_whitespace();
+    let name = match words.next() {
+        None => return Ok(None),
+        Some(name) if name.starts_with('#') => return Ok(None),
+        Some(name) => name,
+    };
+    let args: Vec<&str> = words.collect();
+    let expect = |count: usize| {
+        if args.len() == count {
+            Ok(())
+        } else {
+            Err(format!(
+                "{} takes {} argument(s), got {}",
+                name,
+                count,
+                args.len()
+            ))
+        }
+    };
+    let number = |arg: &str| {
+        arg.parse::<i32>()
+            .map_err(|_| format!("{} is not a valid value", arg))
+    };
+    let command = match name {
+        "insert" | "delete" | "contains" | "rank" => {
+            expect(1)?;
+            let value = number(args[0])?;
+            match name {
+                "insert" => Command::Insert(value),
+                "delete" => Command::Delete(value),
+                "contains" => Command::Contains(value),
+                _ => Command::Rank(value),
+            }
+        }
+        "select" => {
+            expect(1)?;
+            let index = args[0]
+                .parse()
+                .map_err(|_| format!("{} is not a valid index", args[0]))?;
+            Command::Select(index)
+        }
+        "range" => {
+            expect(2)?;
+            Command::Range(number(args[0])?, number(args[1])?)
+        }
+        "save" | "load" => {
+            expect(1)?;
+            match name {
+                "save" => Command::Save(args[0].to_string()),
+                _ => Command::Load(args[0].to_string()),
+            }
+        }
+        _ => {
+            let command = match name {
+                "len" => Command::Len,
+                "height" => Command::Height,
+                "leaves" => Command::Leaves,
+                "empty" => Command::Empty,
+                "print" => Command::Print,
+                "min" => Command::Min,
+                "max" => Command::Max,
+                "validate" => Command::Validate,
+                "stats" => Command::Stats,
+                "clear" => Command::Clear,
+                _ => return Err(format!("unknown command {}", name)),
+            };
+            expect(0)?;
+            command
+        }
+    };
+    Ok(Some(command))
+}
+
+fn execute<S: OrderedSet<i32>>(tree: &mut S, command: Command) -> Result<String, String> {
+    let optional = |value: Option<&i32>| value.map_or("none".to_string(), i32::to_string);
+    let out = match command {
+        Command::Insert(value) => tree.insert(value).to_string(),
+        Command::Delete(value) => tree.remove(&value).to_string(),
+        Command::Contains(value) => tree.contains(&value).to_string(),
+        Command::Len => tree.len().to_string(),
+        Command::Height => tree.height().to_string(),
+        Command::Leaves => tree.count_leaves().to_string(),
+        Command::Empty => tree.is_empty().to_string(),
+        Command::Print => join(tree.iter()),
+        // an empty range rather than the panic std's ranges give for bounds the wrong way round
+        Command::Range(low, high) if low > high => String::new(),
+        Command::Range(low, high) => {
+            join(tree.range(Bound::Included(&low), Bound::Included(&high)))
+        }
+        Command::Min => optional(tree.first()),
+        Command::Max => optional(tree.last()),
+        Command::Rank(value) => tree.rank(&value).to_string(),
+        Command::Select(index) => optional(tree.select(index)),
+        Command::Validate => {
+            tree.validate().map_err(|violation| violation.to_string())?;
+            "ok".to_string()
+        }
+        Command::Stats => match tree.stats() {
+            Some(stats) => stats.to_string(),
+            None => return Err(format!("a {} is not a binary tree", S::NAME)),
+        },
+        Command::Save(path) => {
+            tree.save_to(Path::new(&path))
+                .map_err(|err| err.to_string())?;
+            "ok".to_string()
+        }
+        Command::Load(path) => {
+            tree.reload_from(Path::new(&path))
+                .map_err(|err| err.to_string())?;
+            tree.len().to_string()
+        }
+        Command::Clear => {
+            tree.clear();
+            "ok".to_string()
+        }
+    };
+    Ok(out)
+}
+
+// ---------------------------------------- Explain ----------------------------------------------
+// The text of the steps seen since the last command, shared with the observer inside the tree
+type Steps = Arc<Mutex<String>>;
+
+// What explain mode needs for a tree of type S: the steps, and how to set an observer on a tree
+// that writes into them
+struct Explain<S> {
+    steps: Steps,
+    watch: fn(&mut S, Steps),
+}
+
+impl<S> Explain<S> {
+    fn new(watch: fn(&mut S, Steps)) -> Self {
+        Explain {
+            steps: Steps::default(),
+            watch,
+        }
+    }
+
+    fn watch(&self, tree: &mut S) {
+        (self.watch)(tree, Arc::clone(&self.steps));
+    }
+
+    fn take_steps(&self) -> String {
+        std::mem::take(&mut *self.steps.lock().unwrap())
+    }
+}
+
+fn watch_red_black(tree: &mut RedBlackTree<i32>, steps: Steps) {
+    tree.set_observer(move |step, tree| {
+        write_step(&steps, step, |out| tree.write_tree(out));
+    });
+}
+
+fn watch_avl(tree: &mut AVLTree<i32>, steps: Steps) {
+    tree.set_observer(move |step, tree| {
+        write_step(&steps, step, |out| tree.write_structure(out));
+    });
+}
+
+// Adds the step and then the tree drawn by `draw`, indented below it
+fn write_step<K: Debug>(
+    steps: &Steps,
+    step: Step<&K>,
+    draw: impl FnOnce(&mut Vec<u8>) -> io::Result<()>,
+) {
+    let mut tree = Vec::new();
+    draw(&mut tree).expect("writing to a Vec cannot fail");
+    let mut steps = steps.lock().unwrap();
+    steps.push_str(&format!("  {}\n", step));
+    for line in String::from_utf8_lossy(&tree).lines() {
+        steps.push_str(&format!("    {}\n", line));
+    }
+}
+
+fn join<'a, I: Iterator<Item = &'a i32>>(values: I) -> String {
+    let values: Vec<String> = values.map(i32::to_string).collect();
+    values.join(" ")
+}
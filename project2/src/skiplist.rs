@@ -0,0 +1,626 @@
+use crate::arena::{Arena, NodeId};
+use crate::invariant::{Invariant, InvariantViolation};
+use crate::ordered_set::OrderedSet;
+use crate::rng::Rng;
+use crate::snapshot::{self, Codec, SnapshotError};
+use std::fmt::Debug;
+use std::fs;
+use std::iter::FusedIterator;
+use std::ops::{Bound, RangeBounds};
+use std::path::Path;
+
+// Towers never grow past this many levels, enough for far more values than fit in memory
+const MAX_LEVEL: usize = 32;
+
+#[derive(Debug)]
+struct Node<T> {
+    key: T,
+    // the next node on each level the tower of this node reaches, next[0] is the next value in order
+    next: Vec<Option<NodeId>>,
+    // the previous node on the bottom level, for walking backwards
+    prev: Option<NodeId>,
+}
+
+// A randomized ordered set: a sorted linked list with express lanes. Every value is on the bottom
+// level, and each level above holds about half of the values of the one below it, picked at random
+// when the value is inserted. A search starts on the top level and drops a level whenever the next
+// value would be too far, which takes O(log n) steps in expectation. It offers the same operations
+// as AVLTree except for split, join and the set operations; there are no subtree sizes either, so
+// rank and select walk the bottom level.
+//
+// The nodes live in an arena owned by the list, see arena.rs.
+#[derive(Debug)]
+pub struct SkipList<T> {
+    nodes: Arena<Node<T>>,
+    // the first node on each level, the list has as many levels as its tallest tower
+    head: Vec<Option<NodeId>>,
+    tail: Option<NodeId>,
+    len: usize,
+    rng: Rng,
+}
+
+impl<T> SkipList<T> {
+    // The node after `node` on a level, where None stands for the head of the list
+    fn next(&self, node: Option<NodeId>, level: usize) -> Option<NodeId> {
+        match node {
+            Some(node) => self.nodes[node].next[level],
+            None => self.head[level],
+        }
+    }
+
+    fn set_next(&mut self, node: Option<NodeId>, level: usize, next: Option<NodeId>) {
+        match node {
+            Some(node) => self.nodes[node].next[level] = next,
+            None => self.head[level] = next,
+        }
+    }
+
+    // The last node on each level for which `before` holds, None where that is the head. `before`
+    // has to hold for a prefix of the values in order.
+    fn last_before_each<F: Fn(&T) -> bool>(&self, before: F) -> Vec<Option<NodeId>> {
+        let mut found = vec![None; self.head.len()];
+        let mut current = None;
+        for level in (0..self.head.len()).rev() {
+            while let Some(next) = self.next(current, level) {
+                if !before(&self.nodes[next].key) {
+                    break;
+                }
+                current = Some(next);
+            }
+            found[level] = current;
+        }
+        found
+    }
+
+    // The last node on the bottom level for which `before` holds, see last_before_each
+    fn last_before<F: Fn(&T) -> bool>(&self, before: F) -> Option<NodeId> {
+        let mut current = None;
+        for level in (0..self.head.len()).rev() {
+            while let Some(next) = self.next(current, level) {
+                if !before(&self.nodes[next].key) {
+                    break;
+                }
+                current = Some(next);
+            }
+        }
+        current
+    }
+
+    // The first node for which `before` does not hold
+    fn first_after<F: Fn(&T) -> bool>(&self, before: F) -> Option<NodeId> {
+        let last = self.last_before(before);
+        if self.head.is_empty() {
+            None
+        } else {
+            self.next(last, 0)
+        }
+    }
+
+    // Levels of a new tower, each level above the first is reached with a chance of one half
+    fn random_level(&mut self) -> usize {
+        (self.rng.next_u64().trailing_ones() as usize + 1).min(MAX_LEVEL)
+    }
+
+    // Links a new node in after `before`, which holds the node in front of it on every level
+    fn link(&mut self, mut before: Vec<Option<NodeId>>, key: T) -> NodeId {
+        let level = self.random_level();
+        while self.head.len() < level {
+            self.head.push(None);
+            before.push(None);
+        }
+        let next = (0..level).map(|l| self.next(before[l], l)).collect();
+        let node = self.nodes.alloc(Node {
+            key,
+            next,
+            prev: before[0],
+        });
+        for (l, before) in before.iter().enumerate().take(level) {
+            self.set_next(*before, l, Some(node));
+        }
+        match self.nodes[node].next[0] {
+            Some(after) => self.nodes[after].prev = Some(node),
+            None => self.tail = Some(node),
+        }
+        self.len += 1;
+        node
+    }
+
+    // Unlinks `node` from every level, `before` holds the node in front of it on every level it is on
+    fn unlink(&mut self, before: &[Option<NodeId>], node: NodeId) -> T {
+        let Node { key, next, prev } = self.nodes.free(node);
+        for (l, after) in next.iter().enumerate() {
+            self.set_next(before[l], l, *after);
+        }
+        match next[0] {
+            Some(after) => self.nodes[after].prev = prev,
+            None => self.tail = prev,
+        }
+        // levels whose only node was this one go away
+        while self.head.last() == Some(&None) {
+            self.head.pop();
+        }
+        self.len -= 1;
+        key
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    // Number of levels, the height of the tallest tower
+    pub fn get_height(&self) -> i32 {
+        self.head.len() as i32
+    }
+
+    // A skip list has no leaves, the closest thing are the values whose tower is one level high and
+    // that no search ever drops down to from above
+    pub fn count_leaves(&self) -> usize {
+        self.node_ids()
+            .filter(|node| self.nodes[*node].next.len() == 1)
+            .count()
+    }
+
+    fn node_ids(&self) -> impl Iterator<Item = NodeId> + '_ {
+        let first = self.head.first().copied().flatten();
+        std::iter::successors(first, |node| self.nodes[*node].next[0])
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            nodes: &self.nodes,
+            front: self.head.first().copied().flatten(),
+            back: self.tail,
+            remaining: self.len,
+        }
+    }
+
+    pub fn first(&self) -> Option<&T> {
+        self.iter().next()
+    }
+
+    pub fn last(&self) -> Option<&T> {
+        self.iter().next_back()
+    }
+
+    pub fn pop_first(&mut self) -> Option<T> {
+        let first = self.head.first().copied().flatten()?;
+        // the first node follows the head on every level it is on
+        let before = vec![None; self.head.len()];
+        Some(self.unlink(&before, first))
+    }
+}
+
+impl<T: Ord> SkipList<T> {
+    pub fn new() -> Self {
+        Self::with_rng(Rng::from_entropy())
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
+        Self::with_rng(Rng::with_seed(seed))
+    }
+
+    fn with_rng(rng: Rng) -> Self {
+        SkipList {
+            nodes: Arena::new(),
+            head: Vec::new(),
+            tail: None,
+            len: 0,
+            rng,
+        }
+    }
+
+    pub fn contains(&self, data: T) -> bool {
+        self.find(&data).is_some()
+    }
+
+    fn find(&self, data: &T) -> Option<NodeId> {
+        self.first_after(|key| key < data)
+            .filter(|node| self.nodes[*node].key == *data)
+    }
+
+    // Returns false, and leaves the list as it was, if the value is already in it
+    pub fn insert(&mut self, data: T) -> bool {
+        let before = self.last_before_each(|key| *key < data);
+        if !self.head.is_empty()
+            && self
+                .next(before[0], 0)
+                .is_some_and(|next| self.nodes[next].key == data)
+        {
+            return false;
+        }
+        self.link(before, data);
+        true
+    }
+
+    // Returns false if the value was not in the list
+    pub fn delete(&mut self, data: T) -> bool {
+        self.remove(&data).is_some()
+    }
+
+    fn remove(&mut self, data: &T) -> Option<T> {
+        let node = self.find(data)?;
+        let before = self.last_before_each(|key| key < data);
+        Some(self.unlink(&before, node))
+    }
+
+    pub fn pop_last(&mut self) -> Option<T> {
+        let last = self.tail?;
+        let before = self.last_before_each(|key| *key < self.nodes[last].key);
+        Some(self.unlink(&before, last))
+    }
+
+    // Iterates over the values that fall in the range, from either end
+    pub fn range<R: RangeBounds<T>>(&self, range: R) -> Range<'_, T> {
+        let front = self.first_after(|key| match range.start_bound() {
+            Bound::Included(start) => key < start,
+            Bound::Excluded(start) => key <= start,
+            Bound::Unbounded => false,
+        });
+        let back = self.last_before(|key| match range.end_bound() {
+            Bound::Included(end) => key <= end,
+            Bound::Excluded(end) => key < end,
+            Bound::Unbounded => true,
+        });
+        let mut range = Range {
+            nodes: &self.nodes,
+            front,
+            back,
+        };
+        // the two ends have passed each other, or one of them found nothing
+        match (front, back) {
+            (Some(front), Some(back)) if self.nodes[front].key <= self.nodes[back].key => {}
+            _ => range.front = None,
+        }
+        range
+    }
+
+    // Largest value that is less than or equal to the given one
+    pub fn floor(&self, data: &T) -> Option<&T> {
+        self.last_before(|key| key <= data)
+            .map(|node| &self.nodes[node].key)
+    }
+
+    // Smallest value that is greater than or equal to the given one
+    pub fn ceiling(&self, data: &T) -> Option<&T> {
+        self.first_after(|key| key < data)
+            .map(|node| &self.nodes[node].key)
+    }
+
+    // Largest value that is strictly less than the given one
+    pub fn predecessor(&self, data: &T) -> Option<&T> {
+        self.last_before(|key| key < data)
+            .map(|node| &self.nodes[node].key)
+    }
+
+    // Smallest value that is greater than the given one
+    pub fn successor(&self, data: &T) -> Option<&T> {
+        self.first_after(|key| key <= data)
+            .map(|node| &self.nodes[node].key)
+    }
+
+    // Builds a list out of values that are already sorted in O(n), each value is linked in behind the
+    // last tower on every level
+    pub fn from_sorted_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = SkipList::new();
+        let mut last = Vec::new();
+        for data in iter {
+            assert!(
+                list.tail.is_none_or(|tail| list.nodes[tail].key < data),
+                "from_sorted_iter needs values in strictly ascending order"
+            );
+            let node = list.link(last.clone(), data);
+            last.resize(list.head.len(), None);
+            for level in last.iter_mut().take(list.nodes[node].next.len()) {
+                *level = Some(node);
+            }
+        }
+        list
+    }
+
+    // Number of values in the list that are strictly less than the given one, walks the values
+    pub fn rank(&self, data: &T) -> usize {
+        self.iter().take_while(|key| *key < data).count()
+    }
+
+    // The index-th smallest value, counting from 0, walks the values
+    pub fn select(&self, index: usize) -> Option<&T> {
+        self.iter().nth(index)
+    }
+}
+
+impl<T: Ord + Codec> SkipList<T> {
+    // The values in the binary snapshot format, see the snapshot module
+    pub fn to_snapshot(&self) -> Vec<u8> {
+        snapshot::encode(self.len(), self.iter().map(|data| (data, &())))
+    }
+
+    // Rebuilds a list from a snapshot in O(n), the values are already in order
+    pub fn from_snapshot(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let entries = snapshot::decode::<T, ()>(bytes)?;
+        Ok(Self::from_sorted_iter(
+            entries.into_iter().map(|(data, ())| data),
+        ))
+    }
+
+    pub fn save_to<P: AsRef<Path>>(&self, path: P) -> Result<(), SnapshotError> {
+        fs::write(path, self.to_snapshot())?;
+        Ok(())
+    }
+
+    pub fn load_from<P: AsRef<Path>>(path: P) -> Result<Self, SnapshotError> {
+        Self::from_snapshot(&fs::read(path)?)
+    }
+}
+
+impl<T: Ord + Debug> SkipList<T> {
+    // ---------------------------------------- Validate ---------------------------------------------
+    // Checks that every level is in order, that each level holds exactly the towers that reach it,
+    // that the backward links and the tail match the bottom level, that the top level is not empty
+    // and that the stored length matches
+    pub fn validate(&self) -> Result<(), InvariantViolation> {
+        let violation = |invariant, node: Option<NodeId>| {
+            let key = node.map(|node| &self.nodes[node].key);
+            Err(InvariantViolation::new(invariant, &key))
+        };
+        if self.head.last() == Some(&None) || self.head.len() > MAX_LEVEL {
+            return violation(Invariant::Links, None);
+        }
+        let mut prev = None;
+        let mut towers = vec![0; self.head.len()];
+        for node in self.node_ids() {
+            if self.nodes[node].prev != prev {
+                return violation(Invariant::Links, Some(node));
+            }
+            let height = self.nodes[node].next.len();
+            if height == 0 || height > self.head.len() {
+                return violation(Invariant::Links, Some(node));
+            }
+            for count in &mut towers[..height] {
+                *count += 1;
+            }
+            prev = Some(node);
+        }
+        if prev != self.tail {
+            return violation(Invariant::Links, self.tail);
+        }
+        if towers.first().copied().unwrap_or(0) != self.len {
+            return violation(Invariant::Size, self.tail);
+        }
+        for (level, towers) in towers.into_iter().enumerate() {
+            let mut count = 0;
+            let mut current = self.head[level];
+            while let Some(node) = current {
+                if self.nodes[node].next.len() <= level {
+                    return violation(Invariant::Links, Some(node));
+                }
+                let next = self.nodes[node].next[level];
+                if next.is_some_and(|next| self.nodes[next].key <= self.nodes[node].key) {
+                    return violation(Invariant::Order, Some(node));
+                }
+                count += 1;
+                current = next;
+            }
+            if count != towers {
+                return violation(Invariant::Links, self.head[level]);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: Debug> SkipList<T> {
+    pub fn in_order_traversal(&self) {
+        for data in self.iter() {
+            println!("{:?}", data);
+        }
+    }
+
+    // One line per level, the top level first
+    pub fn print_structure(&self) {
+        for level in (0..self.head.len()).rev() {
+            let mut line = format!("level {}:", level + 1);
+            let mut current = self.head[level];
+            while let Some(node) = current {
+                line += &format!(" {:?}", self.nodes[node].key);
+                current = self.nodes[node].next[level];
+            }
+            println!("{}", line);
+        }
+    }
+}
+
+// ---------------------------------------- Iterators ------------------------------------------------
+// In order iterator that can be walked from both ends along the bottom level, `remaining` stops the
+// two ends from running past each other
+pub struct Iter<'a, T> {
+    nodes: &'a Arena<Node<T>>,
+    front: Option<NodeId>,
+    back: Option<NodeId>,
+    remaining: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let node = &self.nodes[self.front?];
+        self.front = node.next[0];
+        self.remaining -= 1;
+        Some(&node.key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let node = &self.nodes[self.back?];
+        self.back = node.prev;
+        self.remaining -= 1;
+        Some(&node.key)
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+
+impl<T> FusedIterator for Iter<'_, T> {}
+
+// Iterator over the values in a range. Without subtree sizes the number of values in the range is
+// not known up front, so the two ends stop once they meet at the same node.
+pub struct Range<'a, T> {
+    nodes: &'a Arena<Node<T>>,
+    front: Option<NodeId>,
+    back: Option<NodeId>,
+}
+
+impl<'a, T> Range<'a, T> {
+    // Hands out the value of `node` and ends the range if it was the last one
+    fn hand_out(&mut self, node: NodeId, next: Option<NodeId>, prev: Option<NodeId>) -> &'a T {
+        if self.front == self.back {
+            self.front = None;
+        } else {
+            self.front = next;
+            self.back = prev;
+        }
+        &self.nodes[node].key
+    }
+}
+
+impl<'a, T> Iterator for Range<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let node = self.front?;
+        Some(self.hand_out(node, self.nodes[node].next[0], self.back))
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for Range<'a, T> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        self.front?;
+        let node = self.back?;
+        Some(self.hand_out(node, self.front, self.nodes[node].prev))
+    }
+}
+
+impl<T> FusedIterator for Range<'_, T> {}
+
+impl<'a, T> IntoIterator for &'a SkipList<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<T: Ord> Default for SkipList<T> {
+    fn default() -> Self {
+        SkipList::new()
+    }
+}
+
+impl<T: Ord> FromIterator<T> for SkipList<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = SkipList::new();
+        list.extend(iter);
+        list
+    }
+}
+
+impl<T: Ord> Extend<T> for SkipList<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for data in iter {
+            self.insert(data);
+        }
+    }
+}
+
+impl<T: Ord> OrderedSet<T> for SkipList<T> {
+    type Iter<'a>
+        = Iter<'a, T>
+    where
+        T: 'a;
+
+    const NAME: &'static str = "Skip List";
+
+    fn new() -> Self {
+        SkipList::new()
+    }
+
+    fn insert(&mut self, value: T) -> bool {
+        SkipList::insert(self, value)
+    }
+
+    fn remove(&mut self, value: &T) -> bool {
+        SkipList::remove(self, value).is_some()
+    }
+
+    fn contains(&self, value: &T) -> bool {
+        self.find(value).is_some()
+    }
+
+    fn len(&self) -> usize {
+        SkipList::len(self)
+    }
+
+    fn height(&self) -> i32 {
+        self.get_height()
+    }
+
+    fn count_leaves(&self) -> usize {
+        SkipList::count_leaves(self)
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        SkipList::iter(self)
+    }
+
+    fn range<'a>(
+        &'a self,
+        start: Bound<&T>,
+        end: Bound<&T>,
+    ) -> Box<dyn DoubleEndedIterator<Item = &'a T> + 'a> {
+        Box::new(SkipList::range(self, (start, end)))
+    }
+
+    fn validate(&self) -> Result<(), InvariantViolation>
+    where
+        T: Debug,
+    {
+        SkipList::validate(self)
+    }
+
+    fn print(&self)
+    where
+        T: Debug,
+    {
+        self.print_structure();
+    }
+
+    fn save_to(&self, path: &Path) -> Result<(), SnapshotError>
+    where
+        T: Codec,
+    {
+        SkipList::save_to(self, path)
+    }
+
+    fn load_from(path: &Path) -> Result<Self, SnapshotError>
+    where
+        T: Codec,
+    {
+        SkipList::load_from(path)
+    }
+}
@@ -0,0 +1,277 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+// Binary snapshot of a tree: its entries in key order, written compactly and closed with a checksum
+// so that a damaged file is caught when it is loaded instead of turning into a wrong tree.
+//
+//     magic     b"TREE"
+//     version   one byte, FORMAT_VERSION
+//     count     varint, the number of entries
+//     entries   count times the key followed by the value, each written with Codec
+//     checksum  CRC-32 of everything before it, 4 bytes little endian
+//
+// The format does not say which kind of tree wrote it, a snapshot of a red-black tree loads just as
+// well into an AVL tree. Sets are maps with () values, which take no bytes at all.
+const MAGIC: &[u8; 4] = b"TREE";
+pub const FORMAT_VERSION: u8 = 1;
+
+// Reasons a snapshot could not be read or written
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    // the data does not start with the magic bytes, so it is not a snapshot at all
+    BadMagic,
+    UnsupportedVersion(u8),
+    Checksum { expected: u32, found: u32 },
+    // the data ends in the middle of an entry
+    Truncated,
+    // bytes that do not decode to a value of the expected type, e.g. a string that is not UTF-8
+    InvalidValue(&'static str),
+    // the keys are not strictly ascending
+    Unsorted,
+    TrailingBytes,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(error) => write!(f, "{}", error),
+            SnapshotError::BadMagic => write!(f, "not a tree snapshot"),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "snapshot format version {} is not supported", version)
+            }
+            SnapshotError::Checksum { expected, found } => write!(
+                f,
+                "checksum mismatch, expected {:08x} but found {:08x}",
+                expected, found
+            ),
+            SnapshotError::Truncated => write!(f, "snapshot ends in the middle of an entry"),
+            SnapshotError::InvalidValue(what) => write!(f, "invalid {} in snapshot", what),
+            SnapshotError::Unsorted => write!(f, "snapshot keys are not in ascending order"),
+            SnapshotError::TrailingBytes => write!(f, "unexpected bytes after the last entry"),
+        }
+    }
+}
+
+impl Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SnapshotError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(error: io::Error) -> Self {
+        SnapshotError::Io(error)
+    }
+}
+
+// Types that can be stored in a snapshot. `decode` reads one value from the front of `input` and
+// moves `input` past it.
+pub trait Codec: Sized {
+    fn encode(&self, out: &mut Vec<u8>);
+    fn decode(input: &mut &[u8]) -> Result<Self, SnapshotError>;
+}
+
+// LEB128: seven bits per byte, low bits first, the top bit set on every byte but the last
+fn write_varint(out: &mut Vec<u8>, mut value: u128) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(input: &mut &[u8]) -> Result<u128, SnapshotError> {
+    let mut value = 0u128;
+    for shift in (0..128).step_by(7) {
+        let (&byte, rest) = input.split_first().ok_or(SnapshotError::Truncated)?;
+        *input = rest;
+        value |= u128::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(SnapshotError::InvalidValue("varint"))
+}
+
+fn read_bytes<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8], SnapshotError> {
+    if input.len() < len {
+        return Err(SnapshotError::Truncated);
+    }
+    let (bytes, rest) = input.split_at(len);
+    *input = rest;
+    Ok(bytes)
+}
+
+// Unsigned integers are written as varints, so small numbers take a single byte
+macro_rules! unsigned_codec {
+    ($($int:ty),*) => {$(
+        impl Codec for $int {
+            fn encode(&self, out: &mut Vec<u8>) {
+                write_varint(out, *self as u128);
+            }
+
+            fn decode(input: &mut &[u8]) -> Result<Self, SnapshotError> {
+                <$int>::try_from(read_varint(input)?)
+                    .map_err(|_| SnapshotError::InvalidValue(stringify!($int)))
+            }
+        }
+    )*};
+}
+
+// Signed integers are zigzag encoded first (0, -1, 1, -2, ... become 0, 1, 2, 3, ...) so that small
+// negative numbers stay small too
+macro_rules! signed_codec {
+    ($($int:ty),*) => {$(
+        impl Codec for $int {
+            fn encode(&self, out: &mut Vec<u8>) {
+                let value = *self as i128;
+                write_varint(out, ((value << 1) ^ (value >> 127)) as u128);
+            }
+
+            fn decode(input: &mut &[u8]) -> Result<Self, SnapshotError> {
+                let zigzag = read_varint(input)?;
+                let value = (zigzag >> 1) as i128 ^ -((zigzag & 1) as i128);
+                <$int>::try_from(value).map_err(|_| SnapshotError::InvalidValue(stringify!($int)))
+            }
+        }
+    )*};
+}
+
+unsigned_codec!(u8, u16, u32, u64, u128, usize);
+signed_codec!(i8, i16, i32, i64, i128, isize);
+
+impl Codec for bool {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, SnapshotError> {
+        match read_bytes(input, 1)?[0] {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SnapshotError::InvalidValue("bool")),
+        }
+    }
+}
+
+impl Codec for char {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as u32).encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, SnapshotError> {
+        char::from_u32(u32::decode(input)?).ok_or(SnapshotError::InvalidValue("char"))
+    }
+}
+
+// The length in bytes followed by the UTF-8 bytes
+impl Codec for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.len().encode(out);
+        out.extend_from_slice(self.as_bytes());
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, SnapshotError> {
+        let len = usize::decode(input)?;
+        let bytes = read_bytes(input, len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| SnapshotError::InvalidValue("string"))
+    }
+}
+
+// The value of every set entry, it takes no space
+impl Codec for () {
+    fn encode(&self, _out: &mut Vec<u8>) {}
+
+    fn decode(_input: &mut &[u8]) -> Result<Self, SnapshotError> {
+        Ok(())
+    }
+}
+
+// CRC-32 as used by zip and PNG (reflected polynomial 0xedb88320), one table lookup per byte
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, &byte| {
+        CRC_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+// Writes `len` entries, which have to come in key order, as a snapshot
+pub fn encode<'a, K, V, I>(len: usize, entries: I) -> Vec<u8>
+where
+    K: Codec + 'a,
+    V: Codec + 'a,
+    I: IntoIterator<Item = (&'a K, &'a V)>,
+{
+    let mut out = Vec::with_capacity(MAGIC.len() + 1 + 10 + 4);
+    out.extend_from_slice(MAGIC);
+    out.push(FORMAT_VERSION);
+    len.encode(&mut out);
+    for (key, value) in entries {
+        key.encode(&mut out);
+        value.encode(&mut out);
+    }
+    let checksum = crc32(&out);
+    out.extend_from_slice(&checksum.to_le_bytes());
+    out
+}
+
+// Reads the entries back out of a snapshot, checked to be in strictly ascending key order so they
+// can go straight into a bulk build
+pub fn decode<K: Codec + Ord, V: Codec>(bytes: &[u8]) -> Result<Vec<(K, V)>, SnapshotError> {
+    if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+        return Err(SnapshotError::BadMagic);
+    }
+    let version = *bytes.get(MAGIC.len()).ok_or(SnapshotError::Truncated)?;
+    if version != FORMAT_VERSION {
+        return Err(SnapshotError::UnsupportedVersion(version));
+    }
+    let body_len = bytes
+        .len()
+        .checked_sub(4)
+        .filter(|&len| len > MAGIC.len())
+        .ok_or(SnapshotError::Truncated)?;
+    let (body, checksum) = bytes.split_at(body_len);
+    let expected = u32::from_le_bytes(checksum.try_into().expect("checksum is 4 bytes"));
+    let found = crc32(body);
+    if expected != found {
+        return Err(SnapshotError::Checksum { expected, found });
+    }
+
+    let mut input = &body[MAGIC.len() + 1..];
+    let count = usize::decode(&mut input)?;
+    // every entry takes at least one byte unless it is a set of (), so a bad count can not make this
+    // reserve much more than the snapshot itself
+    let mut entries: Vec<(K, V)> = Vec::with_capacity(count.min(input.len()));
+    for _ in 0..count {
+        let key = K::decode(&mut input)?;
+        let value = V::decode(&mut input)?;
+        if entries.last().is_some_and(|(last, _)| *last >= key) {
+            return Err(SnapshotError::Unsorted);
+        }
+        entries.push((key, value));
+    }
+    if !input.is_empty() {
+        return Err(SnapshotError::TrailingBytes);
+    }
+    Ok(entries)
+}
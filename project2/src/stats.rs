@@ -0,0 +1,109 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+// How a binary tree is shaped, as the stats method of the red-black tree, the AVL tree and the treap
+// reports it. Depths count edges, so the root is at depth 0, while the height counts levels the way
+// get_height does. An empty tree has all of its numbers at 0.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TreeStats {
+    pub nodes: usize,
+    // nodes without children
+    pub leaves: usize,
+    pub min_leaf_depth: usize,
+    pub max_leaf_depth: usize,
+    pub average_leaf_depth: f64,
+    // the depths of all nodes added up, so a search for a random key present in the tree visits
+    // internal_path_length / nodes + 1 nodes on average
+    pub internal_path_length: usize,
+    pub height: usize,
+    // red-black trees only: black nodes on every path from the root down to an empty child
+    pub black_height: Option<usize>,
+    // AVL trees only: how many nodes have each balance factor (left height minus right height)
+    pub balance_factors: Option<BTreeMap<i32, usize>>,
+}
+
+impl TreeStats {
+    // ⌈log2(n + 1)⌉, the fewest levels a binary tree with this many nodes can have
+    pub fn optimal_height(&self) -> usize {
+        (usize::BITS - self.nodes.leading_zeros()) as usize
+    }
+
+    // How many times taller the tree is than it has to be, 1 for a perfectly balanced or empty one
+    pub fn height_ratio(&self) -> f64 {
+        match self.optimal_height() {
+            0 => 1.0,
+            optimal => self.height as f64 / optimal as f64,
+        }
+    }
+}
+
+// Walks the tree below `root` and works out everything but the figures of one tree type, which the
+// caller fills in. `children` is called once for every node and lists its children.
+pub(crate) fn collect<N, I, F>(root: Option<N>, mut children: F) -> TreeStats
+where
+    I: IntoIterator<Item = N>,
+    F: FnMut(N) -> I,
+{
+    let mut stats = TreeStats {
+        nodes: 0,
+        leaves: 0,
+        min_leaf_depth: usize::MAX,
+        max_leaf_depth: 0,
+        average_leaf_depth: 0.0,
+        internal_path_length: 0,
+        height: 0,
+        black_height: None,
+        balance_factors: None,
+    };
+    let mut leaf_depths = 0;
+    let mut stack: Vec<(N, usize)> = root.into_iter().map(|root| (root, 0)).collect();
+    while let Some((node, depth)) = stack.pop() {
+        stats.nodes += 1;
+        stats.internal_path_length += depth;
+        stats.height = stats.height.max(depth + 1);
+        let before = stack.len();
+        stack.extend(children(node).into_iter().map(|child| (child, depth + 1)));
+        if stack.len() == before {
+            stats.leaves += 1;
+            leaf_depths += depth;
+            stats.min_leaf_depth = stats.min_leaf_depth.min(depth);
+            stats.max_leaf_depth = stats.max_leaf_depth.max(depth);
+        }
+    }
+    if stats.leaves == 0 {
+        stats.min_leaf_depth = 0;
+    } else {
+        stats.average_leaf_depth = leaf_depths as f64 / stats.leaves as f64;
+    }
+    stats
+}
+
+// One line, for the stats command of the CLI
+impl fmt::Display for TreeStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "nodes {} leaves {} leaf depth {}..{} average {:.2} internal path length {} \
+             height {} optimal {} ratio {:.2}",
+            self.nodes,
+            self.leaves,
+            self.min_leaf_depth,
+            self.max_leaf_depth,
+            self.average_leaf_depth,
+            self.internal_path_length,
+            self.height,
+            self.optimal_height(),
+            self.height_ratio()
+        )?;
+        if let Some(black_height) = self.black_height {
+            write!(f, " black height {}", black_height)?;
+        }
+        if let Some(balance_factors) = &self.balance_factors {
+            f.write_str(" balance factors")?;
+            for (balance, count) in balance_factors {
+                write!(f, " {}:{}", balance, count)?;
+            }
+        }
+        Ok(())
+    }
+}
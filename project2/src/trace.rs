@@ -0,0 +1,100 @@
+use crate::rb::NodeColor;
+use std::fmt::{self, Debug};
+
+// One step of an insert or remove, as a tree reports it to its observer (see set_observer on
+// RedBlackTree and AVLMap). The observer gets each step with the keys of the nodes involved and the
+// tree as it is right after the step, so it can show an operation one rotation at a time.
+//
+// The case names are the tree's own: FixMode and DoubleBlackFix for the red-black tree, LeftLeft,
+// LeftRight, RightRight and RightLeft for the AVL tree.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Step<K> {
+    // a new node holding the key was hung into the tree, before any fix-up
+    Insert(K),
+    // the node holding the key was taken out of the tree
+    Remove(K),
+    // a node with two children is being removed, so the next key in order takes over its place
+    Replace { removed: K, by: K },
+    // the fix-up case picked at the node
+    Case { name: &'static str, at: K },
+    // the node was rotated down to the left, its right child took its place
+    RotateLeft(K),
+    // the node was rotated down to the right, its left child took its place
+    RotateRight(K),
+    // the node was given a new colour, red-black trees only
+    Recolor { node: K, color: NodeColor },
+}
+
+impl<K> Step<K> {
+    // The same step with every key turned into something else, e.g. node ids into keys
+    pub fn map<L, F: FnMut(K) -> L>(self, mut f: F) -> Step<L> {
+        match self {
+            Step::Insert(key) => Step::Insert(f(key)),
+            Step::Remove(key) => Step::Remove(f(key)),
+            Step::Replace { removed, by } => Step::Replace {
+                removed: f(removed),
+                by: f(by),
+            },
+            Step::Case { name, at } => Step::Case { name, at: f(at) },
+            Step::RotateLeft(key) => Step::RotateLeft(f(key)),
+            Step::RotateRight(key) => Step::RotateRight(f(key)),
+            Step::Recolor { node, color } => Step::Recolor {
+                node: f(node),
+                color,
+            },
+        }
+    }
+}
+
+impl<K: Debug> fmt::Display for Step<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::Insert(key) => write!(f, "insert {:?}", key),
+            Step::Remove(key) => write!(f, "remove {:?}", key),
+            Step::Replace { removed, by } => write!(f, "replace {:?} by {:?}", removed, by),
+            Step::Case { name, at } => write!(f, "case {} at {:?}", name, at),
+            Step::RotateLeft(key) => write!(f, "rotate left at {:?}", key),
+            Step::RotateRight(key) => write!(f, "rotate right at {:?}", key),
+            Step::Recolor { node, color } => write!(f, "recolour {:?} {:?}", node, color),
+        }
+    }
+}
+
+// What set_observer stores for a tree of type T with keys K
+pub(crate) type Callback<T, K> = Box<dyn FnMut(Step<&K>, &T) + Send + Sync>;
+
+// The observer slot a tree carries, empty unless set_observer was called
+pub(crate) struct Observer<T, K>(Option<Callback<T, K>>);
+
+impl<T, K> Observer<T, K> {
+    pub(crate) fn new() -> Self {
+        Observer(None)
+    }
+
+    pub(crate) fn set<F: FnMut(Step<&K>, &T) + Send + Sync + 'static>(&mut self, observer: F) {
+        self.0 = Some(Box::new(observer));
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.0 = None;
+    }
+
+    pub(crate) fn is_set(&self) -> bool {
+        self.0.is_some()
+    }
+
+    // Takes the observer out while it runs, so that it can be handed the tree that owns it
+    pub(crate) fn take(&mut self) -> Option<Callback<T, K>> {
+        self.0.take()
+    }
+
+    pub(crate) fn put_back(&mut self, observer: Callback<T, K>) {
+        self.0 = Some(observer);
+    }
+}
+
+impl<T, K> fmt::Debug for Observer<T, K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(if self.is_set() { "Some(..)" } else { "None" })
+    }
+}
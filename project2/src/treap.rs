@@ -0,0 +1,839 @@
+use crate::invariant::{Invariant, InvariantViolation};
+use crate::ordered_set::OrderedSet;
+use crate::rng::Rng;
+use crate::snapshot::{self, Codec, SnapshotError};
+use crate::stats::{self, TreeStats};
+use std::cmp::Ordering;
+use std::fmt::Debug;
+use std::fs;
+use std::iter::FusedIterator;
+use std::ops::{Bound, RangeBounds};
+use std::path::Path;
+
+type Link<T> = Option<Box<Node<T>>>;
+
+#[derive(Debug)]
+struct Node<T> {
+    key: T,
+    priority: u64,
+    // number of values in the subtree, for rank, select and ranges
+    size: usize,
+    left: Link<T>,
+    right: Link<T>,
+}
+
+impl<T> Node<T> {
+    fn new(key: T, priority: u64) -> Box<Self> {
+        Box::new(Node {
+            key,
+            priority,
+            size: 1,
+            left: None,
+            right: None,
+        })
+    }
+
+    fn update(&mut self) {
+        self.size = 1 + size(&self.left) + size(&self.right);
+    }
+}
+
+fn size<T>(link: &Link<T>) -> usize {
+    link.as_ref().map_or(0, |node| node.size)
+}
+
+// ---------------------------------------- Split and merge ------------------------------------------
+// Every update of the treap is built from these two. Each node keeps the order of a binary search
+// tree by key and the order of a max-heap by its random priority, which makes the shape the same as
+// if the values had been inserted in random order, O(log n) deep in expectation.
+
+// Splits a subtree in the values below `key`, the node holding `key` if there is one, and the values
+// above `key`
+fn split<T: Ord>(link: Link<T>, key: &T) -> (Link<T>, Link<T>, Link<T>) {
+    let mut node = match link {
+        Some(node) => node,
+        None => return (None, None, None),
+    };
+    match key.cmp(&node.key) {
+        Ordering::Less => {
+            let (left, found, right) = split(node.left.take(), key);
+            node.left = right;
+            node.update();
+            (left, found, Some(node))
+        }
+        Ordering::Greater => {
+            let (left, found, right) = split(node.right.take(), key);
+            node.right = left;
+            node.update();
+            (Some(node), found, right)
+        }
+        Ordering::Equal => {
+            let (left, right) = (node.left.take(), node.right.take());
+            node.update();
+            (left, Some(node), right)
+        }
+    }
+}
+
+// Puts two subtrees together, every value of `left` has to be below every value of `right`. Of the
+// two roots the one with the higher priority stays on top.
+fn merge<T>(left: Link<T>, right: Link<T>) -> Link<T> {
+    match (left, right) {
+        (None, link) | (link, None) => link,
+        (Some(mut left), Some(mut right)) => {
+            if left.priority > right.priority {
+                left.right = merge(left.right.take(), Some(right));
+                left.update();
+                Some(left)
+            } else {
+                right.left = merge(Some(left), right.left.take());
+                right.update();
+                Some(right)
+            }
+        }
+    }
+}
+
+fn contains<T: Ord>(link: &Link<T>, key: &T) -> bool {
+    let mut current = link;
+    while let Some(node) = current {
+        current = match key.cmp(&node.key) {
+            Ordering::Less => &node.left,
+            Ordering::Greater => &node.right,
+            Ordering::Equal => return true,
+        };
+    }
+    false
+}
+
+// Removes the node holding `key` from the subtree and merges its children in its place
+fn remove<T: Ord>(link: &mut Link<T>, key: &T) -> Option<T> {
+    let node = link.as_mut()?;
+    let removed = match key.cmp(&node.key) {
+        Ordering::Less => remove(&mut node.left, key)?,
+        Ordering::Greater => remove(&mut node.right, key)?,
+        Ordering::Equal => {
+            let mut node = link.take()?;
+            *link = merge(node.left.take(), node.right.take());
+            return Some(node.key);
+        }
+    };
+    node.size -= 1;
+    Some(removed)
+}
+
+// Removes the smallest value of the subtree, its node has no left child to merge
+fn remove_first<T>(link: &mut Link<T>) -> Option<T> {
+    let node = link.as_mut()?;
+    if node.left.is_some() {
+        let first = remove_first(&mut node.left);
+        node.size -= 1;
+        return first;
+    }
+    let node = link.take()?;
+    *link = node.right;
+    Some(node.key)
+}
+
+fn remove_last<T>(link: &mut Link<T>) -> Option<T> {
+    let node = link.as_mut()?;
+    if node.right.is_some() {
+        let last = remove_last(&mut node.right);
+        node.size -= 1;
+        return last;
+    }
+    let node = link.take()?;
+    *link = node.left;
+    Some(node.key)
+}
+
+// ---------------------------------------- Set operations -------------------------------------------
+// The root with the higher priority of the two stays on top, the other subtree is split at its value
+// and the halves are combined with the children on each side
+
+fn union<T: Ord>(a: Link<T>, b: Link<T>) -> Link<T> {
+    let (mut a, b) = match (a, b) {
+        (None, link) | (link, None) => return link,
+        (Some(a), Some(b)) if a.priority >= b.priority => (a, b),
+        (Some(a), Some(b)) => (b, a),
+    };
+    let (left, _, right) = split(Some(b), &a.key);
+    a.left = union(a.left.take(), left);
+    a.right = union(a.right.take(), right);
+    a.update();
+    Some(a)
+}
+
+fn intersection<T: Ord>(a: Link<T>, b: Link<T>) -> Link<T> {
+    let (mut a, b) = match (a, b) {
+        (None, _) | (_, None) => return None,
+        (Some(a), Some(b)) if a.priority >= b.priority => (a, b),
+        (Some(a), Some(b)) => (b, a),
+    };
+    let (left, found, right) = split(Some(b), &a.key);
+    let left = intersection(a.left.take(), left);
+    let right = intersection(a.right.take(), right);
+    if found.is_none() {
+        return merge(left, right);
+    }
+    a.left = left;
+    a.right = right;
+    a.update();
+    Some(a)
+}
+
+fn difference<T: Ord>(a: Link<T>, b: Link<T>) -> Link<T> {
+    let (mut a, b) = match (a, b) {
+        (None, _) => return None,
+        (a, None) => return a,
+        (Some(a), b) => (a, b),
+    };
+    let (left, found, right) = split(b, &a.key);
+    let left = difference(a.left.take(), left);
+    let right = difference(a.right.take(), right);
+    if found.is_some() {
+        return merge(left, right);
+    }
+    a.left = left;
+    a.right = right;
+    a.update();
+    Some(a)
+}
+
+fn symmetric_difference<T: Ord>(a: Link<T>, b: Link<T>) -> Link<T> {
+    let (mut a, b) = match (a, b) {
+        (None, link) | (link, None) => return link,
+        (Some(a), Some(b)) if a.priority >= b.priority => (a, b),
+        (Some(a), Some(b)) => (b, a),
+    };
+    let (left, found, right) = split(Some(b), &a.key);
+    let left = symmetric_difference(a.left.take(), left);
+    let right = symmetric_difference(a.right.take(), right);
+    if found.is_some() {
+        return merge(left, right);
+    }
+    a.left = left;
+    a.right = right;
+    a.update();
+    Some(a)
+}
+
+// Number of values in the subtree for which `below` holds, `below` has to hold for a prefix of the
+// values in order
+fn count_below<T, F: Fn(&T) -> bool>(link: &Link<T>, below: F) -> usize {
+    let mut count = 0;
+    let mut current = link;
+    while let Some(node) = current {
+        if below(&node.key) {
+            count += size(&node.left) + 1;
+            current = &node.right;
+        } else {
+            current = &node.left;
+        }
+    }
+    count
+}
+
+// Last value for which `below` holds, see count_below
+fn last_below<T, F: Fn(&T) -> bool>(link: &Link<T>, below: F) -> Option<&T> {
+    let mut found = None;
+    let mut current = link;
+    while let Some(node) = current {
+        if below(&node.key) {
+            found = Some(&node.key);
+            current = &node.right;
+        } else {
+            current = &node.left;
+        }
+    }
+    found
+}
+
+// First value for which `below` does not hold
+fn first_above<T, F: Fn(&T) -> bool>(link: &Link<T>, below: F) -> Option<&T> {
+    let mut found = None;
+    let mut current = link;
+    while let Some(node) = current {
+        if below(&node.key) {
+            current = &node.right;
+        } else {
+            found = Some(&node.key);
+            current = &node.left;
+        }
+    }
+    found
+}
+
+// A randomized balanced search tree, a binary search tree by value and a heap by random priority.
+// It offers the same operations as AVLTree, but keeps its balance only in expectation, with split
+// and merge doing all the restructuring instead of rotations. Each treap draws its priorities from
+// its own generator, seeded at random by new() or fixed by with_seed() for a reproducible shape.
+#[derive(Debug)]
+pub struct Treap<T> {
+    root: Link<T>,
+    rng: Rng,
+}
+
+impl<T: Ord> Treap<T> {
+    pub fn new() -> Self {
+        Treap {
+            root: None,
+            rng: Rng::from_entropy(),
+        }
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
+        Treap {
+            root: None,
+            rng: Rng::with_seed(seed),
+        }
+    }
+
+    pub fn contains(&self, data: T) -> bool {
+        contains(&self.root, &data)
+    }
+
+    // Returns false, and leaves the treap as it was, if the value is already in it
+    pub fn insert(&mut self, data: T) -> bool {
+        let (left, found, right) = split(self.root.take(), &data);
+        let added = found.is_none();
+        let middle = found.or_else(|| Some(Node::new(data, self.rng.next_u64())));
+        self.root = merge(merge(left, middle), right);
+        added
+    }
+
+    // Returns false if the value was not in the treap
+    pub fn delete(&mut self, data: T) -> bool {
+        remove(&mut self.root, &data).is_some()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    pub fn len(&self) -> usize {
+        size(&self.root)
+    }
+
+    // Number of levels, 0 for an empty treap
+    pub fn get_height(&self) -> i32 {
+        fn height<T>(link: &Link<T>) -> i32 {
+            link.as_ref()
+                .map_or(0, |node| 1 + height(&node.left).max(height(&node.right)))
+        }
+
+        height(&self.root)
+    }
+
+    pub fn count_leaves(&self) -> usize {
+        fn count_leaves_helper<T>(link: &Link<T>) -> usize {
+            match link {
+                None => 0,
+                Some(node) if node.left.is_none() && node.right.is_none() => 1,
+                Some(node) => count_leaves_helper(&node.left) + count_leaves_helper(&node.right),
+            }
+        }
+
+        count_leaves_helper(&self.root)
+    }
+
+    // Node count, leaf depths and path length, see TreeStats. The shape depends on the priorities
+    // drawn, so the numbers vary from run to run for the same values.
+    pub fn stats(&self) -> TreeStats {
+        stats::collect(self.root.as_deref(), |node: &Node<T>| {
+            [node.left.as_deref(), node.right.as_deref()]
+                .into_iter()
+                .flatten()
+        })
+    }
+
+    // Iterates over the values that fall in the range, from either end
+    pub fn range<R: RangeBounds<T>>(&self, range: R) -> Iter<'_, T> {
+        Iter::range(&self.root, range.start_bound(), range.end_bound())
+    }
+
+    pub fn first(&self) -> Option<&T> {
+        self.iter().next()
+    }
+
+    pub fn last(&self) -> Option<&T> {
+        self.iter().next_back()
+    }
+
+    pub fn pop_first(&mut self) -> Option<T> {
+        remove_first(&mut self.root)
+    }
+
+    pub fn pop_last(&mut self) -> Option<T> {
+        remove_last(&mut self.root)
+    }
+
+    // Largest value that is less than or equal to the given one
+    pub fn floor(&self, data: &T) -> Option<&T> {
+        last_below(&self.root, |key| key <= data)
+    }
+
+    // Smallest value that is greater than or equal to the given one
+    pub fn ceiling(&self, data: &T) -> Option<&T> {
+        first_above(&self.root, |key| key < data)
+    }
+
+    // Largest value that is strictly less than the given one
+    pub fn predecessor(&self, data: &T) -> Option<&T> {
+        last_below(&self.root, |key| key < data)
+    }
+
+    // Smallest value that is strictly greater than the given one
+    pub fn successor(&self, data: &T) -> Option<&T> {
+        first_above(&self.root, |key| key <= data)
+    }
+
+    // Builds a treap out of values that are already sorted in O(n). The nodes on the right spine of
+    // the treap built so far wait on a stack; a new value pops the ones with a lower priority, which
+    // become its left subtree, and goes on the stack itself.
+    pub fn from_sorted_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut treap = Treap::new();
+        let mut spine: Vec<Box<Node<T>>> = Vec::new();
+        // hangs the nodes popped off the spine under each other, each one as the right child of the
+        // one below it on the stack
+        fn pop_while<T>(spine: &mut Vec<Box<Node<T>>>, priority: u64) -> Link<T> {
+            let mut popped = None;
+            while spine.last().is_some_and(|top| top.priority < priority) {
+                let mut top = spine.pop().expect("the spine is not empty");
+                top.right = popped;
+                top.update();
+                popped = Some(top);
+            }
+            popped
+        }
+
+        for data in iter {
+            assert!(
+                spine.last().is_none_or(|last| last.key < data),
+                "from_sorted_iter needs values in strictly ascending order"
+            );
+            let mut node = Node::new(data, treap.rng.next_u64());
+            node.left = pop_while(&mut spine, node.priority);
+            node.update();
+            spine.push(node);
+        }
+        while let Some(mut top) = spine.pop() {
+            top.right = treap.root.take();
+            top.update();
+            treap.root = Some(top);
+        }
+        treap
+    }
+
+    // Splits the treap at the value, the left treap gets the values below it and the right one the
+    // rest. The right treap draws its priorities from a new generator seeded by this one.
+    pub fn split(mut self, data: &T) -> (Self, Self) {
+        let (left, found, right) = split(self.root.take(), data);
+        let right = Treap {
+            root: merge(found, right),
+            rng: Rng::with_seed(self.rng.next_u64()),
+        };
+        self.root = left;
+        (self, right)
+    }
+
+    // Puts two treaps back together around a value that is larger than everything in `left` and
+    // smaller than everything in `right`
+    pub fn join(left: Self, data: T, right: Self) -> Self {
+        assert!(
+            left.last().is_none_or(|last| *last < data)
+                && right.first().is_none_or(|first| data < *first),
+            "join needs the values of left below the value and the values of right above it"
+        );
+        let mut joined = left;
+        let middle = Node::new(data, joined.rng.next_u64());
+        joined.root = merge(merge(joined.root.take(), Some(middle)), right.root);
+        joined
+    }
+
+    // Puts two treaps together without a value in between, every value of `left` has to be below
+    // every value of `right`
+    pub fn merge(left: Self, right: Self) -> Self {
+        assert!(
+            match (left.last(), right.first()) {
+                (Some(last), Some(first)) => last < first,
+                _ => true,
+            },
+            "merge needs the values of left below the values of right"
+        );
+        let mut merged = left;
+        merged.root = merge(merged.root.take(), right.root);
+        merged
+    }
+
+    // Values in either treap
+    pub fn union(mut self, other: Self) -> Self {
+        self.root = union(self.root.take(), other.root);
+        self
+    }
+
+    // Values in both treaps
+    pub fn intersection(mut self, other: Self) -> Self {
+        self.root = intersection(self.root.take(), other.root);
+        self
+    }
+
+    // Values in this treap that are not in `other`
+    pub fn difference(mut self, other: Self) -> Self {
+        self.root = difference(self.root.take(), other.root);
+        self
+    }
+
+    // Values in exactly one of the treaps
+    pub fn symmetric_difference(mut self, other: Self) -> Self {
+        self.root = symmetric_difference(self.root.take(), other.root);
+        self
+    }
+
+    // Number of values in the treap that are strictly less than the given one
+    pub fn rank(&self, data: &T) -> usize {
+        count_below(&self.root, |key| key < data)
+    }
+
+    // The index-th smallest value, counting from 0
+    pub fn select(&self, mut index: usize) -> Option<&T> {
+        let mut current = &self.root;
+        while let Some(node) = current {
+            let left = size(&node.left);
+            current = match index.cmp(&left) {
+                Ordering::Less => &node.left,
+                Ordering::Equal => return Some(&node.key),
+                Ordering::Greater => {
+                    index -= left + 1;
+                    &node.right
+                }
+            };
+        }
+        None
+    }
+}
+
+impl<T: Ord + Codec> Treap<T> {
+    // The values in the binary snapshot format, see the snapshot module
+    pub fn to_snapshot(&self) -> Vec<u8> {
+        snapshot::encode(self.len(), self.iter().map(|data| (data, &())))
+    }
+
+    // Rebuilds a treap from a snapshot in O(n), the values are already in order
+    pub fn from_snapshot(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let entries = snapshot::decode::<T, ()>(bytes)?;
+        Ok(Self::from_sorted_iter(
+            entries.into_iter().map(|(data, ())| data),
+        ))
+    }
+
+    pub fn save_to<P: AsRef<Path>>(&self, path: P) -> Result<(), SnapshotError> {
+        fs::write(path, self.to_snapshot())?;
+        Ok(())
+    }
+
+    pub fn load_from<P: AsRef<Path>>(path: P) -> Result<Self, SnapshotError> {
+        Self::from_snapshot(&fs::read(path)?)
+    }
+}
+
+impl<T> Treap<T> {
+    // Iterates over the values in order, from either end
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter::new(&self.root)
+    }
+}
+
+impl<T: Ord + Debug> Treap<T> {
+    // ---------------------------------------- Validate ---------------------------------------------
+    // Checks that the values are in order, that no node has a higher priority than its parent and
+    // that the cached subtree sizes add up
+    pub fn validate(&self) -> Result<(), InvariantViolation> {
+        self.validate_node(&self.root, None, None, u64::MAX)
+            .map(|_| ())
+    }
+
+    // Returns the size of the subtree, whose values have to lie strictly between `lower` and `upper`
+    // and whose priorities must not exceed `max_priority`
+    fn validate_node(
+        &self,
+        link: &Link<T>,
+        lower: Option<&T>,
+        upper: Option<&T>,
+        max_priority: u64,
+    ) -> Result<usize, InvariantViolation> {
+        let node = match link {
+            Some(node) => node,
+            None => return Ok(0),
+        };
+        let violation = |invariant| Err(InvariantViolation::new(invariant, &node.key));
+
+        if lower.is_some_and(|lower| node.key <= *lower)
+            || upper.is_some_and(|upper| node.key >= *upper)
+        {
+            return violation(Invariant::Order);
+        }
+        if node.priority > max_priority {
+            return violation(Invariant::Priority);
+        }
+        let left = self.validate_node(&node.left, lower, Some(&node.key), node.priority)?;
+        let right = self.validate_node(&node.right, Some(&node.key), upper, node.priority)?;
+        if node.size != left + right + 1 {
+            return violation(Invariant::Size);
+        }
+        Ok(node.size)
+    }
+}
+
+impl<T: Debug> Treap<T> {
+    pub fn in_order_traversal(&self) {
+        for data in self.iter() {
+            println!("{:?}", data);
+        }
+    }
+
+    // Prints the treap on its side, the right subtree above each node and the left one below it
+    pub fn print_structure(&self) {
+        Self::print_node(&self.root, 0);
+    }
+
+    fn print_node(link: &Link<T>, depth: usize) {
+        if let Some(node) = link {
+            Self::print_node(&node.right, depth + 1);
+            println!("{:>width$}|-----{:?}", "", node.key, width = depth * 7);
+            Self::print_node(&node.left, depth + 1);
+        }
+    }
+}
+
+// ---------------------------------------- Iterators ------------------------------------------------
+// In order iterator that can be walked from both ends. Each end keeps the spine of nodes it still
+// has to visit, and `remaining` stops the two ends from running past each other.
+pub struct Iter<'a, T> {
+    front: Vec<&'a Node<T>>,
+    back: Vec<&'a Node<T>>,
+    remaining: usize,
+}
+
+impl<'a, T> Iter<'a, T> {
+    fn new(root: &'a Link<T>) -> Self {
+        let mut iter = Iter {
+            front: Vec::new(),
+            back: Vec::new(),
+            remaining: size(root),
+        };
+        iter.push_left_spine(root);
+        iter.push_right_spine(root);
+        iter
+    }
+
+    // Starts the two ends at the first and the last value in the range, the subtree sizes tell how
+    // many values lie between them
+    fn range(root: &'a Link<T>, start: Bound<&T>, end: Bound<&T>) -> Self
+    where
+        T: Ord,
+    {
+        let before_start = |key: &T| match start {
+            Bound::Included(start) => key < start,
+            Bound::Excluded(start) => key <= start,
+            Bound::Unbounded => false,
+        };
+        let up_to_end = |key: &T| match end {
+            Bound::Included(end) => key <= end,
+            Bound::Excluded(end) => key < end,
+            Bound::Unbounded => true,
+        };
+        let mut iter = Iter {
+            front: Vec::new(),
+            back: Vec::new(),
+            remaining: count_below(root, up_to_end).saturating_sub(count_below(root, before_start)),
+        };
+        let mut current = root;
+        while let Some(node) = current {
+            if before_start(&node.key) {
+                current = &node.right;
+            } else {
+                iter.front.push(node);
+                current = &node.left;
+            }
+        }
+        let mut current = root;
+        while let Some(node) = current {
+            if up_to_end(&node.key) {
+                iter.back.push(node);
+                current = &node.right;
+            } else {
+                current = &node.left;
+            }
+        }
+        iter
+    }
+
+    fn push_left_spine(&mut self, mut link: &'a Link<T>) {
+        while let Some(node) = link {
+            self.front.push(node);
+            link = &node.left;
+        }
+    }
+
+    fn push_right_spine(&mut self, mut link: &'a Link<T>) {
+        while let Some(node) = link {
+            self.back.push(node);
+            link = &node.right;
+        }
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let node = self.front.pop()?;
+        self.push_left_spine(&node.right);
+        self.remaining -= 1;
+        Some(&node.key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let node = self.back.pop()?;
+        self.push_right_spine(&node.left);
+        self.remaining -= 1;
+        Some(&node.key)
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+
+impl<T> FusedIterator for Iter<'_, T> {}
+
+impl<'a, T> IntoIterator for &'a Treap<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<T: Ord> Default for Treap<T> {
+    fn default() -> Self {
+        Treap::new()
+    }
+}
+
+impl<T: Ord> FromIterator<T> for Treap<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut treap = Treap::new();
+        treap.extend(iter);
+        treap
+    }
+}
+
+impl<T: Ord> Extend<T> for Treap<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for data in iter {
+            self.insert(data);
+        }
+    }
+}
+
+impl<T: Ord> OrderedSet<T> for Treap<T> {
+    type Iter<'a>
+        = Iter<'a, T>
+    where
+        T: 'a;
+
+    const NAME: &'static str = "Treap";
+
+    fn new() -> Self {
+        Treap::new()
+    }
+
+    fn insert(&mut self, value: T) -> bool {
+        Treap::insert(self, value)
+    }
+
+    fn remove(&mut self, value: &T) -> bool {
+        remove(&mut self.root, value).is_some()
+    }
+
+    fn contains(&self, value: &T) -> bool {
+        contains(&self.root, value)
+    }
+
+    fn len(&self) -> usize {
+        Treap::len(self)
+    }
+
+    fn height(&self) -> i32 {
+        self.get_height()
+    }
+
+    fn count_leaves(&self) -> usize {
+        Treap::count_leaves(self)
+    }
+
+    fn stats(&self) -> Option<TreeStats> {
+        Some(Treap::stats(self))
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        Treap::iter(self)
+    }
+
+    fn range<'a>(
+        &'a self,
+        start: Bound<&T>,
+        end: Bound<&T>,
+    ) -> Box<dyn DoubleEndedIterator<Item = &'a T> + 'a> {
+        Box::new(Iter::range(&self.root, start, end))
+    }
+
+    fn rank(&self, value: &T) -> usize {
+        Treap::rank(self, value)
+    }
+
+    fn select(&self, index: usize) -> Option<&T> {
+        Treap::select(self, index)
+    }
+
+    fn validate(&self) -> Result<(), InvariantViolation>
+    where
+        T: Debug,
+    {
+        Treap::validate(self)
+    }
+
+    fn print(&self)
+    where
+        T: Debug,
+    {
+        self.print_structure();
+    }
+
+    fn save_to(&self, path: &Path) -> Result<(), SnapshotError>
+    where
+        T: Codec,
+    {
+        Treap::save_to(self, path)
+    }
+
+    fn load_from(path: &Path) -> Result<Self, SnapshotError>
+    where
+        T: Codec,
+    {
+        Treap::load_from(path)
+    }
+}
@@ -0,0 +1,117 @@
+// Concurrency tests: writer threads fill a shared tree and empty it again while reader threads look
+// values up the whole time. Every writer inserts its own values in increasing order and removes them
+// in decreasing order, so at any moment the values of one writer that are in the tree are a prefix of
+// its sequence. A reader that finds a value without the one before it has seen a half done write.
+use project2::rng::Rng;
+use project2::{
+    AVLTree, BTree, ConcurrentAVLTree, ConcurrentRedBlackTree, ConcurrentTree, OrderedSet,
+    RedBlackTree,
+};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+
+const WRITERS: u32 = 4;
+const READERS: u64 = 4;
+const PER_WRITER: u32 = 2_000;
+
+// Writer w owns the values w, w + WRITERS, w + 2 * WRITERS, ...
+fn value(writer: u32, index: u32) -> u32 {
+    writer + index * WRITERS
+}
+
+fn stress<S>(tree: ConcurrentTree<S>)
+where
+    S: OrderedSet<u32> + Send + Sync,
+{
+    let writing = AtomicUsize::new(WRITERS as usize);
+    let done = AtomicBool::new(false);
+    let lookups = AtomicUsize::new(0);
+    thread::scope(|scope| {
+        for writer in 0..WRITERS {
+            let (tree, writing, done) = (&tree, &writing, &done);
+            scope.spawn(move || {
+                for index in 0..PER_WRITER {
+                    assert!(tree.insert(value(writer, index)));
+                }
+                for index in (0..PER_WRITER).rev() {
+                    assert!(tree.remove(&value(writer, index)));
+                }
+                if writing.fetch_sub(1, Ordering::SeqCst) == 1 {
+                    done.store(true, Ordering::SeqCst);
+                }
+            });
+        }
+        for reader in 0..READERS {
+            let (tree, done, lookups) = (&tree, &done, &lookups);
+            scope.spawn(move || {
+                let mut rng = Rng::with_seed(reader);
+                let mut rounds = 0u64;
+                while !done.load(Ordering::SeqCst) {
+                    let tree = tree.read();
+                    for _ in 0..16 {
+                        let probe = (rng.next_u64() % u64::from(WRITERS * PER_WRITER)) as u32;
+                        if probe >= WRITERS && tree.contains(&probe) {
+                            assert!(
+                                tree.contains(&(probe - WRITERS)),
+                                "{probe} without the one before it"
+                            );
+                        }
+                    }
+                    // A full check now and then, it walks the whole tree
+                    if rounds.is_multiple_of(64) {
+                        tree.validate().unwrap();
+                    }
+                    rounds += 1;
+                    lookups.fetch_add(16, Ordering::Relaxed);
+                }
+            });
+        }
+    });
+    assert!(lookups.load(Ordering::Relaxed) > 0);
+    let tree = tree.into_inner();
+    assert!(tree.is_empty());
+    tree.validate().unwrap();
+}
+
+#[test]
+fn trees_can_be_shared_between_threads() {
+    fn send_sync<T: Send + Sync>() {}
+    send_sync::<AVLTree<String>>();
+    send_sync::<RedBlackTree<String, Vec<u8>>>();
+    send_sync::<BTree<String>>();
+    send_sync::<ConcurrentAVLTree<String>>();
+    send_sync::<ConcurrentRedBlackTree<String, Vec<u8>>>();
+}
+
+#[test]
+fn avl_lookups_run_during_inserts_and_removes() {
+    stress(ConcurrentAVLTree::<u32>::default());
+}
+
+#[test]
+fn red_black_lookups_run_during_inserts_and_removes() {
+    stress(ConcurrentRedBlackTree::<u32>::default());
+}
+
+#[test]
+fn btree_lookups_run_during_inserts_and_removes() {
+    stress(ConcurrentTree::new(BTree::with_order(4)));
+}
+
+#[test]
+fn writers_take_turns() {
+    let tree = ConcurrentRedBlackTree::<u32, u32>::default();
+    thread::scope(|scope| {
+        for _ in 0..WRITERS {
+            scope.spawn(|| {
+                for key in 0..PER_WRITER {
+                    *tree.write().entry(key).or_insert(0) += 1;
+                }
+            });
+        }
+    });
+    let tree = tree.read();
+    assert_eq!(tree.len(), PER_WRITER as usize);
+    assert!(tree.values().all(|count| *count == WRITERS));
+    tree.validate().unwrap();
+}
@@ -0,0 +1,604 @@
+// Differential tests: random sequences of operations are applied to every tree type and to std's
+// BTreeSet, and after every step the trees have to hold the same values as the BTreeSet and still
+// pass their own invariant checks. proptest shrinks a failing sequence down to a short one.
+use project2::avl::{self, AVLMap, AVLTree};
+use project2::btree::BTree;
+use project2::ordered_set::OrderedSet;
+use project2::rb::{self, RedBlackTree};
+use project2::skiplist::SkipList;
+use project2::treap::Treap;
+use proptest::prelude::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Bound;
+
+#[derive(Clone, Debug)]
+enum Op {
+    Insert(u32),
+    Delete(u32),
+    Contains(u32),
+}
+
+// Keys come from a small range so that deletes and lookups hit values that are in the trees
+fn op(max_key: u32) -> impl Strategy<Value = Op> {
+    prop_oneof![
+        3 => (0..max_key).prop_map(Op::Insert),
+        2 => (0..max_key).prop_map(Op::Delete),
+        1 => (0..max_key).prop_map(Op::Contains),
+    ]
+}
+
+fn ops() -> impl Strategy<Value = Vec<Op>> {
+    (1..200u32).prop_flat_map(|max_key| prop::collection::vec(op(max_key), 0..400))
+}
+
+// Compares one tree against the model after a step. It goes through OrderedSet, so a new tree type
+// only needs a line in `check` to get the same checks.
+fn check_set<S: OrderedSet<u32>>(
+    step: usize,
+    tree: &S,
+    model: &BTreeSet<u32>,
+) -> Result<(), TestCaseError> {
+    prop_assert_eq!(
+        tree.iter().copied().collect::<Vec<_>>(),
+        model.iter().copied().collect::<Vec<_>>(),
+        "{} contents after step {}",
+        S::NAME,
+        step
+    );
+    prop_assert_eq!(tree.len(), model.len(), "{} length after step {}", S::NAME, step);
+    prop_assert_eq!(
+        tree.is_empty(),
+        model.is_empty(),
+        "{} is_empty after step {}",
+        S::NAME,
+        step
+    );
+    prop_assert_eq!(tree.first(), model.first(), "{} first after step {}", S::NAME, step);
+    prop_assert_eq!(tree.last(), model.last(), "{} last after step {}", S::NAME, step);
+    if let Err(violation) = tree.validate() {
+        return Err(TestCaseError::fail(format!(
+            "{} after step {}: {}",
+            S::NAME,
+            step,
+            violation
+        )));
+    }
+    Ok(())
+}
+
+// Compares both trees against the model after a step
+fn check(
+    step: usize,
+    avl: &AVLTree<u32>,
+    rb: &RedBlackTree<u32>,
+    model: &BTreeSet<u32>,
+) -> Result<(), TestCaseError> {
+    check_set(step, avl, model)?;
+    check_set(step, rb, model)
+}
+
+// The key of an entry of a red-black set, to compare it with what BTreeSet hands out
+fn key<'a>(entry: Option<(&'a u32, &'a ())>) -> Option<&'a u32> {
+    entry.map(|(key, _)| key)
+}
+
+// Applies the operations to one tree and the model side by side, what insert, remove and contains
+// return has to match too
+fn run_ops<S: OrderedSet<u32>>(ops: &[Op]) -> Result<(), TestCaseError> {
+    run_ops_on(S::new(), ops)
+}
+
+fn run_ops_on<S: OrderedSet<u32>>(mut tree: S, ops: &[Op]) -> Result<(), TestCaseError> {
+    let mut model = BTreeSet::new();
+    for (step, op) in ops.iter().enumerate() {
+        match *op {
+            Op::Insert(key) => {
+                prop_assert_eq!(tree.insert(key), model.insert(key), "{} insert({}) at step {}", S::NAME, key, step);
+            }
+            Op::Delete(key) => {
+                prop_assert_eq!(tree.remove(&key), model.remove(&key), "{} remove({}) at step {}", S::NAME, key, step);
+            }
+            Op::Contains(key) => {
+                prop_assert_eq!(tree.contains(&key), model.contains(&key), "{} contains({}) at step {}", S::NAME, key, step);
+            }
+        }
+        check_set(step, &tree, &model)?;
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug)]
+enum MapOp {
+    Insert(u32, u32),
+    Remove(u32),
+    Get(u32),
+    OrInsertWith(u32, u32),
+    AndModify(u32),
+    RemoveOccupied(u32),
+}
+
+fn map_op(max_key: u32) -> impl Strategy<Value = MapOp> {
+    prop_oneof![
+        3 => (0..max_key, any::<u32>()).prop_map(|(key, value)| MapOp::Insert(key, value)),
+        2 => (0..max_key).prop_map(MapOp::Remove),
+        1 => (0..max_key).prop_map(MapOp::Get),
+        2 => (0..max_key, any::<u32>()).prop_map(|(key, value)| MapOp::OrInsertWith(key, value)),
+        1 => (0..max_key).prop_map(MapOp::AndModify),
+        1 => (0..max_key).prop_map(MapOp::RemoveOccupied),
+    ]
+}
+
+fn map_ops() -> impl Strategy<Value = Vec<MapOp>> {
+    (1..200u32).prop_flat_map(|max_key| prop::collection::vec(map_op(max_key), 0..400))
+}
+
+// Applies the map operations to one map type and to BTreeMap side by side. What every operation
+// returns has to match, and so do all the entries after it, so a value that gets separated from
+// its key when a node with two children is deleted shows up right away. Both maps have the same
+// inherent API but no trait in common, so the body is shared by a macro.
+macro_rules! run_map_ops {
+    ($tree:ident, $map:expr, $name:expr, $ops:expr) => {{
+        let mut map = $map;
+        let mut model: BTreeMap<u32, u32> = BTreeMap::new();
+        for (step, op) in $ops.iter().enumerate() {
+            match *op {
+                MapOp::Insert(key, value) => {
+                    prop_assert_eq!(map.insert(key, value), model.insert(key, value), "{} insert({}) at step {}", $name, key, step);
+                }
+                MapOp::Remove(key) => {
+                    prop_assert_eq!(map.remove(&key), model.remove(&key), "{} remove({}) at step {}", $name, key, step);
+                }
+                MapOp::Get(key) => {
+                    prop_assert_eq!(map.get(&key), model.get(&key), "{} get({}) at step {}", $name, key, step);
+                }
+                MapOp::OrInsertWith(key, value) => {
+                    let got = *map.entry(key).or_insert_with(|| value);
+                    prop_assert_eq!(got, *model.entry(key).or_insert_with(|| value), "{} or_insert_with({}) at step {}", $name, key, step);
+                }
+                MapOp::AndModify(key) => {
+                    let got = *map.entry(key).and_modify(|value| *value = value.wrapping_mul(3)).or_insert(key);
+                    let expected = *model.entry(key).and_modify(|value| *value = value.wrapping_mul(3)).or_insert(key);
+                    prop_assert_eq!(got, expected, "{} and_modify({}) at step {}", $name, key, step);
+                }
+                MapOp::RemoveOccupied(key) => {
+                    let got = match map.entry(key) {
+                        $tree::Entry::Occupied(entry) => Some(entry.remove()),
+                        $tree::Entry::Vacant(_) => None,
+                    };
+                    prop_assert_eq!(got, model.remove(&key), "{} OccupiedEntry::remove({}) at step {}", $name, key, step);
+                }
+            }
+            prop_assert_eq!(
+                map.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(),
+                model.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(),
+                "{} entries after step {}",
+                $name,
+                step
+            );
+            prop_assert_eq!(map.len(), model.len(), "{} length after step {}", $name, step);
+            if let Err(violation) = map.validate() {
+                return Err(TestCaseError::fail(format!("{} after step {}: {}", $name, step, violation)));
+            }
+        }
+    }};
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(256))]
+
+    #[test]
+    fn trees_match_btreeset(ops in ops()) {
+        run_ops::<AVLTree<u32>>(&ops)?;
+        run_ops::<RedBlackTree<u32>>(&ops)?;
+        run_ops::<BTree<u32>>(&ops)?;
+        run_ops::<Treap<u32>>(&ops)?;
+        run_ops::<SkipList<u32>>(&ops)?;
+    }
+
+    // The map API of both trees against BTreeMap: the values insert and remove hand back, the
+    // entry API and the values that go with each key
+    #[test]
+    fn maps_match_btreemap(ops in map_ops()) {
+        run_map_ops!(avl, AVLMap::<u32, u32>::new(), "AVL map", ops);
+        run_map_ops!(rb, RedBlackTree::<u32, u32>::new(), "red-black map", ops);
+    }
+
+    // Small orders split, borrow and merge on almost every step, and odd and even orders split the
+    // values differently
+    #[test]
+    fn btree_of_any_order_matches_btreeset(order in 3..9usize, ops in ops()) {
+        run_ops_on(BTree::with_order(order), &ops)?;
+    }
+
+    // Both ends of a range meet in the middle without handing out a value twice
+    #[test]
+    fn btree_ranges_match_btreeset(
+        keys in prop::collection::btree_set(0..300u32, 0..300),
+        order in 3..9usize,
+        low in 0..310u32,
+        high in 0..310u32,
+        from_back in prop::collection::vec(any::<bool>(), 0..40),
+    ) {
+        let mut tree = BTree::with_order(order);
+        tree.extend(keys.iter().copied());
+        let bounds = (Bound::Included(low), Bound::Excluded(high));
+        if low <= high {
+            let mut expected = keys.range(bounds);
+            let mut range = tree.range(bounds);
+            for back in from_back {
+                if back {
+                    prop_assert_eq!(range.next_back(), expected.next_back());
+                } else {
+                    prop_assert_eq!(range.next(), expected.next());
+                }
+            }
+            prop_assert!(range.eq(expected));
+        } else {
+            prop_assert_eq!(tree.range(bounds).count(), 0);
+        }
+    }
+
+    // Sorted and reverse sorted runs are the worst case for the rotations, so they get a test of their own
+    #[test]
+    fn sorted_runs_stay_balanced(len in 0..500u32, reverse in any::<bool>()) {
+        let mut avl = AVLTree::new();
+        let mut rb: RedBlackTree<u32> = RedBlackTree::new();
+        let mut model = BTreeSet::new();
+
+        let keys: Vec<u32> = if reverse { (0..len).rev().collect() } else { (0..len).collect() };
+        for (step, key) in keys.iter().enumerate() {
+            avl.insert(*key);
+            rb.tree_insert(*key);
+            model.insert(*key);
+            check(step, &avl, &rb, &model)?;
+        }
+        for (step, key) in keys.iter().enumerate() {
+            avl.delete(*key);
+            rb.delete(key);
+            model.remove(key);
+            check(keys.len() + step, &avl, &rb, &model)?;
+        }
+    }
+
+    // Trees built in bulk have to be as valid as the ones built one insert at a time, and the ones built
+    // from sorted input as short as possible
+    #[test]
+    fn bulk_construction_matches_btreeset(keys in prop::collection::vec(0..300u32, 0..400), more in prop::collection::vec(0..300u32, 0..50)) {
+        let mut model: BTreeSet<u32> = keys.iter().copied().collect();
+        let mut avl: AVLTree<u32> = keys.iter().copied().collect();
+        let mut rb: RedBlackTree<u32> = keys.iter().copied().collect();
+        check(0, &avl, &rb, &model)?;
+
+        let mut sorted_avl = AVLTree::from_sorted_iter(model.iter().copied());
+        let mut sorted_rb = RedBlackTree::from_sorted_iter(model.iter().map(|key| (*key, ())));
+        check(1, &sorted_avl, &sorted_rb, &model)?;
+        let min_height = (usize::BITS - model.len().leading_zeros()) as i32;
+        prop_assert_eq!(sorted_avl.get_height(), min_height);
+        prop_assert_eq!(sorted_rb.get_height(), min_height);
+        // the deletes lean on the colours the bulk build picked
+        let mut thinned = model.clone();
+        for key in model.iter().step_by(2) {
+            sorted_avl.delete(*key);
+            sorted_rb.delete(key);
+            thinned.remove(key);
+            check(1, &sorted_avl, &sorted_rb, &thinned)?;
+        }
+
+        avl.extend(more.iter().copied());
+        rb.extend(more.iter().copied());
+        model.extend(more.iter().copied());
+        check(2, &avl, &rb, &model)?;
+
+        // with values, a key that shows up twice keeps the value that came last
+        let entries: Vec<(u32, usize)> = keys.iter().copied().zip(0..).collect();
+        let model: BTreeMap<u32, usize> = entries.iter().copied().collect();
+        let avl: AVLMap<u32, usize> = entries.iter().copied().collect();
+        let rb: RedBlackTree<u32, usize> = entries.iter().copied().collect();
+        prop_assert_eq!(avl.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(), model.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>());
+        prop_assert_eq!(rb.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(), model.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>());
+        prop_assert!(avl.validate().is_ok());
+        prop_assert!(rb.validate().is_ok());
+    }
+
+    // Every set operation, split and join against the same operation on BTreeSet. The results are used
+    // for a few more inserts and deletes afterwards to make sure they are ordinary trees again.
+    #[test]
+    fn set_operations_match_btreeset(
+        a in prop::collection::btree_set(0..300u32, 0..200),
+        b in prop::collection::btree_set(0..300u32, 0..200),
+        at in 0..301u32,
+    ) {
+        let avl = |set: &BTreeSet<u32>| set.iter().copied().collect::<AVLTree<u32>>();
+        let rb = |set: &BTreeSet<u32>| set.iter().copied().collect::<RedBlackTree<u32>>();
+        let results: [(AVLTree<u32>, RedBlackTree<u32>, BTreeSet<u32>); 4] = [
+            (avl(&a).union(avl(&b)), rb(&a).union(rb(&b)), a.union(&b).copied().collect()),
+            (avl(&a).intersection(avl(&b)), rb(&a).intersection(rb(&b)), a.intersection(&b).copied().collect()),
+            (avl(&a).difference(avl(&b)), rb(&a).difference(rb(&b)), a.difference(&b).copied().collect()),
+            (
+                avl(&a).symmetric_difference(avl(&b)),
+                rb(&a).symmetric_difference(rb(&b)),
+                a.symmetric_difference(&b).copied().collect(),
+            ),
+        ];
+        for (step, (mut avl, mut rb, mut model)) in results.into_iter().enumerate() {
+            check(step, &avl, &rb, &model)?;
+            for key in [at, at / 2, at + 1] {
+                avl.insert(key);
+                rb.tree_insert(key);
+                model.insert(key);
+                avl.delete(key / 3);
+                rb.delete(&(key / 3));
+                model.remove(&(key / 3));
+                check(step, &avl, &rb, &model)?;
+            }
+        }
+
+        let (avl_left, avl_right) = avl(&a).split(&at);
+        let (rb_left, rb_right) = rb(&a).split(&at);
+        check(4, &avl_left, &rb_left, &a.range(..at).copied().collect())?;
+        check(5, &avl_right, &rb_right, &a.range(at..).copied().collect())?;
+
+        // join them back with `at` in between, the right halves lose `at` first if they have it
+        let (mut avl_right, mut rb_right) = (avl_right, rb_right);
+        avl_right.delete(at);
+        rb_right.delete(&at);
+        let avl_joined = AVLTree::join(avl_left, at, avl_right);
+        let rb_joined = RedBlackTree::join(rb_left, at, (), rb_right);
+        let mut model = a.clone();
+        model.insert(at);
+        check(6, &avl_joined, &rb_joined, &model)?;
+    }
+
+    // On a key in both maps the union and intersection keep the entry of the map they were called on
+    #[test]
+    fn map_set_operations_keep_self_values(
+        a in prop::collection::btree_map(0..100u32, 0..1000u32, 0..100),
+        b in prop::collection::btree_map(0..100u32, 0..1000u32, 0..100),
+    ) {
+        let avl = |map: &BTreeMap<u32, u32>| map.iter().map(|(k, v)| (*k, *v)).collect::<AVLMap<u32, u32>>();
+        let rb = |map: &BTreeMap<u32, u32>| map.iter().map(|(k, v)| (*k, *v)).collect::<RedBlackTree<u32, u32>>();
+        let mut union = b.clone();
+        union.extend(a.iter().map(|(k, v)| (*k, *v)));
+        let intersection: Vec<(u32, u32)> = a.iter().filter(|(k, _)| b.contains_key(k)).map(|(k, v)| (*k, *v)).collect();
+
+        let avl_union = avl(&a).union(avl(&b));
+        let rb_union = rb(&a).union(rb(&b));
+        prop_assert_eq!(avl_union.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(), union.clone().into_iter().collect::<Vec<_>>());
+        prop_assert_eq!(rb_union.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(), union.into_iter().collect::<Vec<_>>());
+        prop_assert_eq!(avl(&a).intersection(avl(&b)).iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(), intersection.clone());
+        prop_assert_eq!(rb(&a).intersection(rb(&b)).iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(), intersection);
+        prop_assert!(avl_union.validate().is_ok());
+        prop_assert!(rb_union.validate().is_ok());
+    }
+
+    // The subtree sizes behind rank and select have to follow every rotation of the inserts and
+    // deletes, so both are checked after each step and for every key and index at the end
+    #[test]
+    fn tree_rank_and_select_match_btreeset(ops in ops()) {
+        let mut avl = AVLTree::new();
+        let mut rb: RedBlackTree<u32> = RedBlackTree::new();
+        let mut model = BTreeSet::new();
+        for (step, op) in ops.iter().enumerate() {
+            let probe = match *op {
+                Op::Insert(key) => {
+                    avl.insert(key);
+                    rb.tree_insert(key);
+                    model.insert(key);
+                    key
+                }
+                Op::Delete(key) => {
+                    avl.delete(key);
+                    rb.delete(&key);
+                    model.remove(&key);
+                    key
+                }
+                Op::Contains(key) => key,
+            };
+            let rank = model.range(..probe).count();
+            prop_assert_eq!(avl.rank(&probe), rank, "AVL rank({}) at step {}", probe, step);
+            prop_assert_eq!(rb.rank(&probe), rank, "red-black rank({}) at step {}", probe, step);
+            let index = probe as usize % (model.len() + 1);
+            let value = model.iter().nth(index);
+            prop_assert_eq!(avl.select(index), value, "AVL select({}) at step {}", index, step);
+            prop_assert_eq!(key(rb.select(index)), value, "red-black select({}) at step {}", index, step);
+        }
+        let max_key = model.last().map_or(0, |key| key + 1);
+        for probe in 0..=max_key {
+            prop_assert_eq!(avl.rank(&probe), model.range(..probe).count());
+            prop_assert_eq!(rb.rank(&probe), model.range(..probe).count());
+        }
+        for index in 0..=model.len() {
+            prop_assert_eq!(avl.select(index), model.iter().nth(index));
+            prop_assert_eq!(key(rb.select(index)), model.iter().nth(index));
+        }
+    }
+
+    // The neighbour lookups, ranges and pops of both balanced trees. Their ranges are walked from
+    // both ends until the ends meet, which is where a value could be handed out twice.
+    #[test]
+    fn tree_lookups_match_btreeset(
+        keys in prop::collection::btree_set(0..300u32, 0..300),
+        low in 0..310u32,
+        high in 0..310u32,
+        from_back in prop::collection::vec(any::<bool>(), 0..40),
+    ) {
+        let mut avl: AVLTree<u32> = keys.iter().copied().collect();
+        let mut rb: RedBlackTree<u32> = keys.iter().copied().collect();
+
+        let below = keys.range(..low).next_back();
+        let above = keys.range(low + 1..).next();
+        let at = keys.get(&low);
+        prop_assert_eq!(avl.floor(&low), at.or(below));
+        prop_assert_eq!(key(rb.floor(&low)), at.or(below));
+        prop_assert_eq!(avl.ceiling(&low), at.or(above));
+        prop_assert_eq!(key(rb.ceiling(&low)), at.or(above));
+        prop_assert_eq!(avl.predecessor(&low), below);
+        prop_assert_eq!(key(rb.predecessor(&low)), below);
+        prop_assert_eq!(avl.successor(&low), above);
+        prop_assert_eq!(key(rb.successor(&low)), above);
+
+        let bounds = (Bound::Included(low), Bound::Excluded(high));
+        if low <= high {
+            let mut expected = keys.range(bounds);
+            let mut avl_range = avl.range(bounds);
+            let mut rb_range = rb.range(bounds);
+            for &back in &from_back {
+                if back {
+                    let value = expected.next_back();
+                    prop_assert_eq!(avl_range.next_back(), value);
+                    prop_assert_eq!(key(rb_range.next_back()), value);
+                } else {
+                    let value = expected.next();
+                    prop_assert_eq!(avl_range.next(), value);
+                    prop_assert_eq!(key(rb_range.next()), value);
+                }
+            }
+            let rest: Vec<u32> = expected.copied().collect();
+            prop_assert_eq!(avl_range.copied().collect::<Vec<_>>(), rest.clone());
+            prop_assert_eq!(rb_range.map(|(key, _)| *key).collect::<Vec<_>>(), rest);
+        } else {
+            prop_assert_eq!(avl.range(bounds).count(), 0);
+            prop_assert_eq!(rb.range(bounds).count(), 0);
+        }
+
+        // the same pattern of ends empties both trees from the outside in
+        let mut model = keys.clone();
+        let ends = from_back.iter().copied().chain([false]).cycle();
+        for (step, back) in ends.take(keys.len() + 1).enumerate() {
+            if back {
+                let value = model.pop_last();
+                prop_assert_eq!(avl.pop_last(), value);
+                prop_assert_eq!(rb.pop_last().map(|(key, _)| key), value);
+            } else {
+                let value = model.pop_first();
+                prop_assert_eq!(avl.pop_first(), value);
+                prop_assert_eq!(rb.pop_first().map(|(key, _)| key), value);
+            }
+            check(step, &avl, &rb, &model)?;
+        }
+    }
+
+    // The treap's split, join, merge and set operations against BTreeSet, the same way as for the
+    // deterministic trees above
+    #[test]
+    fn treap_operations_match_btreeset(
+        a in prop::collection::btree_set(0..300u32, 0..200),
+        b in prop::collection::btree_set(0..300u32, 0..200),
+        at in 0..301u32,
+        seed in any::<u64>(),
+    ) {
+        let treap = |set: &BTreeSet<u32>| {
+            let mut treap = Treap::with_seed(seed);
+            treap.extend(set.iter().copied());
+            treap
+        };
+        let results: [(Treap<u32>, BTreeSet<u32>); 4] = [
+            (treap(&a).union(treap(&b)), a.union(&b).copied().collect()),
+            (treap(&a).intersection(treap(&b)), a.intersection(&b).copied().collect()),
+            (treap(&a).difference(treap(&b)), a.difference(&b).copied().collect()),
+            (treap(&a).symmetric_difference(treap(&b)), a.symmetric_difference(&b).copied().collect()),
+        ];
+        for (step, (treap, model)) in results.iter().enumerate() {
+            check_set(step, treap, model)?;
+            prop_assert_eq!(treap.rank(&at), model.range(..at).count());
+            prop_assert_eq!(treap.select(at as usize / 2), model.iter().nth(at as usize / 2));
+        }
+
+        let (left, right) = treap(&a).split(&at);
+        check_set(4, &left, &a.range(..at).copied().collect())?;
+        check_set(5, &right, &a.range(at..).copied().collect())?;
+        let mut right = right;
+        right.delete(at);
+        let joined = Treap::join(left, at, right);
+        let mut model = a.clone();
+        model.insert(at);
+        check_set(6, &joined, &model)?;
+
+        let (left, right) = joined.split(&at);
+        let mut merged = Treap::merge(left, right);
+        check_set(7, &merged, &model)?;
+        prop_assert_eq!(merged.pop_first(), model.pop_first());
+        prop_assert_eq!(merged.pop_last(), model.pop_last());
+        check_set(8, &merged, &model)?;
+
+        let sorted = Treap::from_sorted_iter(a.iter().copied());
+        check_set(9, &sorted, &a)?;
+    }
+
+    // Ranges and the neighbour lookups of both randomized structures, whose iterators work
+    // differently from the trees'
+    #[test]
+    fn randomized_lookups_match_btreeset(
+        keys in prop::collection::btree_set(0..300u32, 0..300),
+        low in 0..310u32,
+        high in 0..310u32,
+        from_back in prop::collection::vec(any::<bool>(), 0..40),
+        seed in any::<u64>(),
+    ) {
+        let treap = Treap::from_sorted_iter(keys.iter().copied());
+        let mut list = SkipList::with_seed(seed);
+        list.extend(keys.iter().copied());
+        check_set(0, &list, &keys)?;
+        let sorted_list = SkipList::from_sorted_iter(keys.iter().copied());
+        check_set(1, &sorted_list, &keys)?;
+
+        let below = keys.range(..low).next_back();
+        let above = keys.range(low + 1..).next();
+        let at = keys.get(&low);
+        prop_assert_eq!(treap.floor(&low), at.or(below));
+        prop_assert_eq!(list.floor(&low), at.or(below));
+        prop_assert_eq!(treap.ceiling(&low), at.or(above));
+        prop_assert_eq!(list.ceiling(&low), at.or(above));
+        prop_assert_eq!(treap.predecessor(&low), below);
+        prop_assert_eq!(list.predecessor(&low), below);
+        prop_assert_eq!(treap.successor(&low), above);
+        prop_assert_eq!(list.successor(&low), above);
+
+        let bounds = (Bound::Included(low), Bound::Excluded(high));
+        if low <= high {
+            let mut expected = keys.range(bounds);
+            let mut treap_range = treap.range(bounds);
+            let mut list_range = list.range(bounds);
+            for back in from_back {
+                if back {
+                    let value = expected.next_back();
+                    prop_assert_eq!(treap_range.next_back(), value);
+                    prop_assert_eq!(list_range.next_back(), value);
+                } else {
+                    let value = expected.next();
+                    prop_assert_eq!(treap_range.next(), value);
+                    prop_assert_eq!(list_range.next(), value);
+                }
+            }
+            let rest: Vec<u32> = expected.copied().collect();
+            prop_assert_eq!(treap_range.copied().collect::<Vec<_>>(), rest.clone());
+            prop_assert_eq!(list_range.copied().collect::<Vec<_>>(), rest);
+        } else {
+            prop_assert_eq!(treap.range(bounds).count(), 0);
+            prop_assert_eq!(list.range(bounds).count(), 0);
+        }
+    }
+}
+
+// A fixed seed gives the same shape every time, so a failure with a seed can be replayed
+#[test]
+fn seeded_structures_are_reproducible() {
+    let build = || {
+        let mut treap = Treap::with_seed(7);
+        let mut list = SkipList::with_seed(7);
+        for key in (0..1000u32).map(|i| (i * 7919) % 1000) {
+            treap.insert(key);
+            list.insert(key);
+        }
+        (treap, list)
+    };
+    let (treap, list) = build();
+    let (same_treap, same_list) = build();
+    assert_eq!(treap.get_height(), same_treap.get_height());
+    assert_eq!(treap.count_leaves(), same_treap.count_leaves());
+    assert_eq!(list.get_height(), same_list.get_height());
+    assert_eq!(list.count_leaves(), same_list.count_leaves());
+    // expected O(log n) depth, with a lot of room for bad luck
+    assert!(treap.get_height() < 40);
+    assert!(list.get_height() < 25);
+}
@@ -0,0 +1,131 @@
+// Export tests: the DOT output of small trees is compared as a whole, and the JSON dump of larger
+// trees is parsed back and checked against the trees it came from.
+use project2::avl::AVLTree;
+use project2::rb::RedBlackTree;
+use serde_json::Value;
+
+fn small_rb() -> RedBlackTree<u32> {
+    let mut tree = RedBlackTree::new();
+    for key in [2, 1, 3, 4] {
+        tree.tree_insert(key);
+    }
+    tree
+}
+
+fn small_avl() -> AVLTree<u32> {
+    let mut tree = AVLTree::new();
+    for key in [2, 1, 3, 4] {
+        tree.insert(key);
+    }
+    tree
+}
+
+#[test]
+fn rb_dot() {
+    let expected = r#"digraph RedBlackTree {
+    node [shape=circle, style=filled, fontcolor=white];
+    n0 [label="2", fillcolor=black];
+    n1 [label="1", fillcolor=black];
+    n0 -> n1;
+    n2 [label="3", fillcolor=black];
+    n3 [style=invis];
+    n2 -> n3 [style=invis];
+    n4 [label="4", fillcolor=red];
+    n2 -> n4;
+    n0 -> n2;
+}
+"#;
+    assert_eq!(small_rb().to_dot(false), expected);
+
+    let with_leaves = small_rb().to_dot(true);
+    // every node has two children once the empty ones are drawn, so there are n + 1 points
+    assert_eq!(with_leaves.matches("[shape=point]").count(), 5);
+    assert_eq!(with_leaves.matches("->").count(), 8);
+    assert!(!with_leaves.contains("invis"));
+}
+
+#[test]
+fn avl_dot() {
+    let expected = r#"digraph AVLTree {
+    node [shape=circle];
+    n0 [label="2", xlabel="-1"];
+    n1 [label="1", xlabel="0"];
+    n0 -> n1;
+    n2 [label="3", xlabel="-1"];
+    n3 [style=invis];
+    n2 -> n3 [style=invis];
+    n4 [label="4", xlabel="0"];
+    n2 -> n4;
+    n0 -> n2;
+}
+"#;
+    assert_eq!(small_avl().to_dot(false), expected);
+
+    let mut written = Vec::new();
+    small_avl().write_dot(&mut written, true).unwrap();
+    assert_eq!(String::from_utf8(written).unwrap(), small_avl().to_dot(true));
+}
+
+#[test]
+fn empty_trees() {
+    assert_eq!(
+        RedBlackTree::<u32>::new().to_json(),
+        "{\n  \"kind\": \"red-black\",\n  \"len\": 0,\n  \"root\": null\n}\n"
+    );
+    assert_eq!(AVLTree::<u32>::new().to_dot(true), "digraph AVLTree {\n    node [shape=circle];\n}\n");
+}
+
+#[test]
+fn keys_are_escaped() {
+    let mut tree: RedBlackTree<String> = RedBlackTree::new();
+    tree.tree_insert("say \"hi\"\\\n".to_string());
+    assert!(tree.to_dot(false).contains(r#"label="\"say \\\"hi\\\"\\\\\\n\"""#));
+    let json: Value = serde_json::from_str(&tree.to_json()).unwrap();
+    assert_eq!(json["root"]["key"], r#""say \"hi\"\\\n""#);
+}
+
+// Walks a parsed JSON node, checks the sizes add up and collects the keys in order
+fn walk(node: &Value, keys: &mut Vec<String>) -> u64 {
+    if node.is_null() {
+        return 0;
+    }
+    let left = walk(&node["left"], keys);
+    keys.push(node["key"].as_str().unwrap().to_string());
+    let right = walk(&node["right"], keys);
+    assert_eq!(node["size"].as_u64().unwrap(), left + right + 1);
+    left + right + 1
+}
+
+fn height(node: &Value) -> i64 {
+    if node.is_null() {
+        0
+    } else {
+        node["height"].as_i64().unwrap()
+    }
+}
+
+#[test]
+fn json_matches_the_tree() {
+    let rb: RedBlackTree<u32> = (0..500).map(|i| (i * 7919) % 1000).collect();
+    let json: Value = serde_json::from_str(&rb.to_json()).unwrap();
+    assert_eq!(json["kind"], "red-black");
+    assert_eq!(json["len"], rb.len());
+    let mut keys = Vec::new();
+    walk(&json["root"], &mut keys);
+    assert_eq!(keys, rb.keys().map(|key| key.to_string()).collect::<Vec<_>>());
+    assert_eq!(json["root"]["color"], "black");
+
+    let mut avl = AVLTree::new();
+    for i in 0..500 {
+        avl.insert((i * 7919) % 1000);
+    }
+    let json: Value = serde_json::from_str(&avl.to_json()).unwrap();
+    assert_eq!(json["kind"], "avl");
+    assert_eq!(json["len"], avl.len());
+    let mut keys = Vec::new();
+    walk(&json["root"], &mut keys);
+    assert_eq!(keys, avl.iter().map(|key| key.to_string()).collect::<Vec<_>>());
+    assert_eq!(height(&json["root"]), i64::from(avl.get_height()));
+    let root = &json["root"];
+    assert_eq!(root["balance"].as_i64().unwrap(), height(&root["left"]) - height(&root["right"]));
+}
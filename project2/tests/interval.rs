@@ -0,0 +1,110 @@
+// Interval tree tests: random inserts, removals and overlap queries are applied to an interval tree
+// and to a BTreeSet of intervals, which answers the queries by looking at every interval. The tree
+// has to give the same answers, in any order, and keep its priority search heap right.
+use project2::IntervalTree;
+use proptest::prelude::*;
+use std::collections::BTreeSet;
+
+#[derive(Clone, Debug)]
+enum Op {
+    Insert(u32, u32),
+    Remove(u32, u32),
+    Point(u32),
+    Range(u32, u32),
+}
+
+// Short intervals over a small range, so that removals hit and queries find several answers
+fn op() -> impl Strategy<Value = Op> {
+    let interval = (0..100u32, 0..20u32).prop_map(|(start, length)| (start, start + length));
+    prop_oneof![
+        4 => interval.clone().prop_map(|(start, end)| Op::Insert(start, end)),
+        2 => interval.clone().prop_map(|(start, end)| Op::Remove(start, end)),
+        2 => (0..130u32).prop_map(Op::Point),
+        2 => interval.prop_map(|(start, end)| Op::Range(start, end)),
+    ]
+}
+
+fn overlapping(model: &BTreeSet<(u32, u32)>, start: u32, end: u32) -> Vec<(u32, u32)> {
+    model
+        .iter()
+        .filter(|(from, to)| *from <= end && *to >= start)
+        .copied()
+        .collect()
+}
+
+// The answers sorted, queries hand them out in no particular order
+fn owned<'a>(found: impl Iterator<Item = (&'a u32, &'a u32)>) -> Vec<(u32, u32)> {
+    let mut found: Vec<_> = found.map(|(start, end)| (*start, *end)).collect();
+    found.sort();
+    found
+}
+
+proptest! {
+    #[test]
+    fn queries_match_a_full_scan(ops in prop::collection::vec(op(), 0..300)) {
+        let mut tree = IntervalTree::new();
+        let mut model = BTreeSet::new();
+        for (step, op) in ops.into_iter().enumerate() {
+            match op {
+                Op::Insert(start, end) => {
+                    let added = model.insert((start, end));
+                    prop_assert_eq!(tree.insert_interval(start, end), added);
+                }
+                Op::Remove(start, end) => {
+                    let removed = model.remove(&(start, end));
+                    prop_assert_eq!(tree.remove_interval(&start, &end), removed);
+                }
+                Op::Point(point) => {
+                    let expected = overlapping(&model, point, point);
+                    prop_assert_eq!(owned(tree.overlapping(&point)), expected);
+                }
+                Op::Range(start, end) => {
+                    prop_assert_eq!(
+                        owned(tree.overlapping_range(&start, &end)),
+                        overlapping(&model, start, end)
+                    );
+                }
+            }
+            prop_assert_eq!(tree.len(), model.len());
+            if let Err(violation) = tree.validate() {
+                return Err(TestCaseError::fail(format!("after step {}: {}", step, violation)));
+            }
+        }
+        prop_assert_eq!(owned(tree.iter()), model.into_iter().collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn stabbing_queries_find_every_interval_over_the_point() {
+    let tree: IntervalTree<u32> = [(15, 20), (10, 30), (17, 19), (5, 20), (12, 15), (30, 40)]
+        .into_iter()
+        .collect();
+    assert_eq!(
+        owned(tree.overlapping(&18)),
+        [(5, 20), (10, 30), (15, 20), (17, 19)]
+    );
+    assert_eq!(owned(tree.overlapping(&30)), [(10, 30), (30, 40)]);
+    assert_eq!(owned(tree.overlapping(&41)), []);
+    assert_eq!(owned(tree.overlapping_range(&0, &5)), [(5, 20)]);
+    assert_eq!(owned(tree.overlapping_range(&21, &29)), [(10, 30)]);
+    // a range that ends before it starts overlaps nothing
+    assert_eq!(owned(tree.overlapping_range(&20, &10)), []);
+}
+
+#[test]
+fn removing_the_widest_interval_takes_it_out_of_the_heap() {
+    let mut tree: IntervalTree<u32> = (0..100).map(|start| (start, start + 1)).collect();
+    assert!(tree.insert_interval(0, 1_000));
+    assert!(!tree.insert_interval(0, 1_000));
+    assert_eq!(owned(tree.overlapping(&500)), [(0, 1_000)]);
+    assert!(tree.remove_interval(&0, &1_000));
+    assert!(!tree.contains_interval(&0, &1_000));
+    assert_eq!(owned(tree.overlapping(&500)), []);
+    tree.validate().unwrap();
+}
+
+#[test]
+#[should_panic(expected = "an interval cannot end before it starts")]
+fn reversed_intervals_are_turned_away() {
+    IntervalTree::new().insert_interval(5, 1);
+}
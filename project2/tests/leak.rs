@@ -0,0 +1,190 @@
+// Leak tests: every key and value handed to a tree is a Tracked that counts itself in and out of a
+// shared counter, so once a tree is dropped, or taken apart by into_iter, the counter has to be back
+// at zero. A parent link that kept its node alive would leave the counter above zero.
+use project2::avl::AVLMap;
+use project2::rb::RedBlackTree;
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+// A key or value that is counted as live from the moment it is made until it is dropped
+#[derive(Debug)]
+struct Tracked {
+    id: u32,
+    live: Rc<Cell<usize>>,
+}
+
+impl Tracked {
+    fn new(id: u32, live: &Rc<Cell<usize>>) -> Self {
+        live.set(live.get() + 1);
+        Tracked {
+            id,
+            live: Rc::clone(live),
+        }
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        self.live.set(self.live.get() - 1);
+    }
+}
+
+impl PartialEq for Tracked {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for Tracked {}
+
+impl PartialOrd for Tracked {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Tracked {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.id.cmp(&other.id)
+    }
+}
+
+// Keys in a scrambled order so the rotations on both sides get used
+fn keys(n: u32) -> impl Iterator<Item = u32> {
+    (0..n).map(move |i| i.wrapping_mul(2_654_435_761) % (n * 2))
+}
+
+// Fills a tree with tracked entries and removes some of them again through the different removal paths
+fn build_rb(live: &Rc<Cell<usize>>, n: u32) -> RedBlackTree<Tracked, Tracked> {
+    let mut tree = RedBlackTree::new();
+    for key in keys(n) {
+        tree.insert(Tracked::new(key, live), Tracked::new(key, live));
+    }
+    for key in keys(n).step_by(3) {
+        tree.remove(&Tracked::new(key, live));
+    }
+    tree.pop_first();
+    tree.pop_last();
+    tree
+}
+
+fn build_avl(live: &Rc<Cell<usize>>, n: u32) -> AVLMap<Tracked, Tracked> {
+    let mut map = AVLMap::new();
+    for key in keys(n) {
+        map.insert(Tracked::new(key, live), Tracked::new(key, live));
+    }
+    for key in keys(n).step_by(3) {
+        map.remove(&Tracked::new(key, live));
+    }
+    map.pop_first();
+    map.pop_last();
+    map
+}
+
+#[test]
+fn rb_drop_frees_every_node() {
+    let live = Rc::new(Cell::new(0));
+    let tree = build_rb(&live, 1000);
+    // one key and one value per node are all that is left alive
+    assert_eq!(live.get(), 2 * tree.len());
+    drop(tree);
+    assert_eq!(live.get(), 0);
+}
+
+#[test]
+fn avl_drop_frees_every_node() {
+    let live = Rc::new(Cell::new(0));
+    let map = build_avl(&live, 1000);
+    assert_eq!(live.get(), 2 * map.len());
+    drop(map);
+    assert_eq!(live.get(), 0);
+}
+
+#[test]
+fn replaced_and_removed_entries_are_dropped() {
+    let live = Rc::new(Cell::new(0));
+    let mut tree = RedBlackTree::new();
+    let mut map = AVLMap::new();
+    for key in 0..100 {
+        tree.insert(Tracked::new(key, &live), Tracked::new(key, &live));
+        map.insert(Tracked::new(key, &live), Tracked::new(key, &live));
+    }
+    // inserting a key that is already there replaces the value and drops the new key
+    for key in 0..100 {
+        drop(tree.insert(Tracked::new(key, &live), Tracked::new(key, &live)));
+        drop(map.insert(Tracked::new(key, &live), Tracked::new(key, &live)));
+    }
+    assert_eq!(live.get(), 400);
+    for key in 0..50 {
+        drop(tree.remove(&Tracked::new(key, &live)));
+        drop(map.remove(&Tracked::new(key, &live)));
+    }
+    assert_eq!(live.get(), 200);
+    drop(tree);
+    drop(map);
+    assert_eq!(live.get(), 0);
+}
+
+#[test]
+fn partly_used_into_iter_frees_the_rest() {
+    let live = Rc::new(Cell::new(0));
+    let mut entries = build_rb(&live, 500).into_iter();
+    entries.next();
+    entries.next_back();
+    drop(entries);
+    assert_eq!(live.get(), 0);
+
+    let mut entries = build_avl(&live, 500).into_iter();
+    entries.next();
+    entries.next_back();
+    drop(entries);
+    assert_eq!(live.get(), 0);
+}
+
+// What a long running process does: build a tree, use it and throw it away, over and over
+#[test]
+fn repeated_build_and_drop_does_not_grow() {
+    let live = Rc::new(Cell::new(0));
+    for round in 0..50 {
+        let tree = build_rb(&live, 200 + round);
+        let map = build_avl(&live, 200 + round);
+        assert!(tree.validate().is_ok());
+        assert!(map.validate().is_ok());
+        drop(tree);
+        drop(map);
+        assert_eq!(live.get(), 0, "entries left alive after round {}", round);
+    }
+}
+
+// The set operations free the entries they leave out straight away, so a result holds exactly its own
+// entries. A node left behind in the arena would only be freed with the whole tree.
+#[test]
+fn set_operations_free_what_they_drop() {
+    let live = Rc::new(Cell::new(0));
+    type RbOp = fn(RedBlackTree<Tracked, Tracked>, RedBlackTree<Tracked, Tracked>) -> RedBlackTree<Tracked, Tracked>;
+    type AvlOp = fn(AVLMap<Tracked, Tracked>, AVLMap<Tracked, Tracked>) -> AVLMap<Tracked, Tracked>;
+    let rb_ops: [RbOp; 4] = [
+        RedBlackTree::union,
+        RedBlackTree::intersection,
+        RedBlackTree::difference,
+        RedBlackTree::symmetric_difference,
+    ];
+    let avl_ops: [AvlOp; 4] = [AVLMap::union, AVLMap::intersection, AVLMap::difference, AVLMap::symmetric_difference];
+    for (rb_op, avl_op) in rb_ops.into_iter().zip(avl_ops) {
+        let tree = rb_op(build_rb(&live, 300), build_rb(&live, 200));
+        assert_eq!(live.get(), 2 * tree.len());
+        drop(tree);
+        let map = avl_op(build_avl(&live, 300), build_avl(&live, 200));
+        assert_eq!(live.get(), 2 * map.len());
+        drop(map);
+        assert_eq!(live.get(), 0);
+    }
+
+    let (left, right) = build_rb(&live, 300).split(&Tracked::new(150, &live));
+    assert_eq!(live.get(), 2 * (left.len() + right.len()));
+    let joined = RedBlackTree::join(left, Tracked::new(1000, &live), Tracked::new(1000, &live), RedBlackTree::new());
+    drop(joined);
+    drop(right);
+    assert_eq!(live.get(), 0);
+}
@@ -0,0 +1,86 @@
+// Library tests: the trees are used the way another crate in the workspace would use them, only
+// through the names lib.rs exports, so a change that drops or renames part of the public API shows
+// up here as a compile error.
+use project2::{
+    AVLMap, AVLMultiset, AVLTree, BTree, InvariantViolation, OrderedSet, PersistentAVLMap,
+    RedBlackMultiset, RedBlackTree, SkipList, Treap,
+};
+use std::ops::Bound;
+
+// Walks every iterator the two main trees hand out, naming their types so they stay exported
+fn walk_map<'a>(
+    iter: project2::rb::Iter<'a, u32, &'static str>,
+    keys: project2::rb::Keys<'a, u32, &'static str>,
+    values: project2::rb::Values<'a, u32, &'static str>,
+    range: project2::rb::Range<'a, u32, &'static str>,
+) -> (Vec<u32>, Vec<u32>, Vec<&'static str>, Vec<u32>) {
+    (
+        iter.map(|(key, _)| *key).collect(),
+        keys.copied().collect(),
+        values.copied().collect(),
+        range.map(|(key, _)| *key).rev().collect(),
+    )
+}
+
+fn walk_avl<'a>(
+    iter: project2::avl::Iter<'a, u32, ()>,
+    range: project2::avl::Range<'a, u32, ()>,
+) -> (Vec<u32>, Vec<u32>) {
+    (
+        iter.map(|(key, _)| *key).collect(),
+        range.map(|(key, _)| *key).collect(),
+    )
+}
+
+fn fill<S: OrderedSet<u32> + Default>() -> Result<S, InvariantViolation> {
+    let mut set = S::default();
+    for value in [5, 1, 4, 2, 3, 1] {
+        set.insert(value);
+    }
+    set.validate()?;
+    Ok(set)
+}
+
+#[test]
+fn trees_are_usable_through_the_exports() {
+    let map: RedBlackTree<u32, &str> = [(2, "two"), (1, "one"), (3, "three")].into_iter().collect();
+    let (keys, keys_again, values, range) =
+        walk_map(map.iter(), map.keys(), map.values(), map.range(2..));
+    assert_eq!(keys, [1, 2, 3]);
+    assert_eq!(keys_again, [1, 2, 3]);
+    assert_eq!(values, ["one", "two", "three"]);
+    assert_eq!(range, [3, 2]);
+
+    let avl: AVLMap<u32, ()> = (1..=4).map(|key| (key, ())).collect();
+    let (keys, range) = walk_avl(avl.iter(), avl.range(2..4));
+    assert_eq!(keys, [1, 2, 3, 4]);
+    assert_eq!(range, [2, 3]);
+
+    let owned: Vec<(u32, &str)> = map.into_iter().collect();
+    assert_eq!(owned, [(1, "one"), (2, "two"), (3, "three")]);
+}
+
+#[test]
+fn every_set_is_default_and_generic() {
+    fn check<S: OrderedSet<u32> + Default>() {
+        let set: S = fill().unwrap();
+        assert_eq!(set.iter().copied().collect::<Vec<_>>(), [1, 2, 3, 4, 5]);
+        let inside: Vec<u32> = set
+            .range(Bound::Excluded(&1), Bound::Included(&3))
+            .copied()
+            .collect();
+        assert_eq!(inside, [2, 3], "{}", S::NAME);
+    }
+    check::<RedBlackTree<u32>>();
+    check::<AVLTree<u32>>();
+    check::<BTree<u32>>();
+    check::<Treap<u32>>();
+    check::<SkipList<u32>>();
+
+    let persistent = PersistentAVLMap::<u32, ()>::default().insert(1, ());
+    assert_eq!(persistent.len(), 1);
+    let mut counts = RedBlackMultiset::<u32>::default();
+    counts.extend([1, 1, 2]);
+    assert_eq!(counts.count(&1), 2);
+    assert!(AVLMultiset::<u32>::default().is_empty());
+}
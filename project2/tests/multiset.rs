@@ -0,0 +1,104 @@
+// Multiset tests: random inserts and removals are applied to both multisets and to a BTreeMap of
+// counts, which they have to match after every step.
+use project2::avl::{AVLMap, AVLTree};
+use project2::multiset::{AVLMultiset, CountMap, Multiset, RedBlackMultiset};
+use project2::rb::RedBlackTree;
+use proptest::prelude::*;
+use std::collections::BTreeMap;
+
+#[derive(Clone, Debug)]
+enum Op {
+    Insert(u32),
+    RemoveOne(u32),
+    RemoveAll(u32),
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        4 => (0..30u32).prop_map(Op::Insert),
+        2 => (0..30u32).prop_map(Op::RemoveOne),
+        1 => (0..30u32).prop_map(Op::RemoveAll),
+    ]
+}
+
+fn run<M: CountMap<u32>>(ops: &[Op]) -> Result<(), TestCaseError> {
+    let mut multiset: Multiset<u32, M> = Multiset::new();
+    let mut model: BTreeMap<u32, usize> = BTreeMap::new();
+    for (step, op) in ops.iter().enumerate() {
+        match *op {
+            Op::Insert(key) => {
+                let count = model.entry(key).or_insert(0);
+                *count += 1;
+                prop_assert_eq!(multiset.insert(key), *count == 1, "insert({}) at step {}", key, step);
+            }
+            Op::RemoveOne(key) => {
+                let expected = match model.get_mut(&key) {
+                    None => false,
+                    Some(count) => {
+                        *count -= 1;
+                        if *count == 0 {
+                            model.remove(&key);
+                        }
+                        true
+                    }
+                };
+                prop_assert_eq!(multiset.remove_one(&key), expected, "remove_one({}) at step {}", key, step);
+            }
+            Op::RemoveAll(key) => {
+                let expected = model.remove(&key).unwrap_or(0);
+                prop_assert_eq!(multiset.remove_all(&key), expected, "remove_all({}) at step {}", key, step);
+            }
+        }
+        prop_assert_eq!(multiset.iter().map(|(key, count)| (*key, count)).collect::<Vec<_>>(), model.iter().map(|(key, count)| (*key, *count)).collect::<Vec<_>>());
+        prop_assert_eq!(multiset.len(), model.values().sum::<usize>());
+        prop_assert_eq!(multiset.distinct_len(), model.len());
+        for key in 0..30 {
+            prop_assert_eq!(multiset.count(&key), model.get(&key).copied().unwrap_or(0));
+        }
+        if let Err(violation) = multiset.validate() {
+            return Err(TestCaseError::fail(format!("after step {}: {}", step, violation)));
+        }
+    }
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn multisets_match_a_map_of_counts(ops in prop::collection::vec(op(), 0..300)) {
+        run::<RedBlackTree<u32, usize>>(&ops)?;
+        run::<AVLMap<u32, usize>>(&ops)?;
+    }
+}
+
+#[test]
+fn elements_repeat_each_key() {
+    let words: RedBlackMultiset<&str> = "the cat and the hat and the bat".split(' ').collect();
+    assert_eq!(words.count(&"the"), 3);
+    assert_eq!(words.count(&"dog"), 0);
+    assert_eq!(
+        words.elements().copied().collect::<Vec<_>>(),
+        ["and", "and", "bat", "cat", "hat", "the", "the", "the"]
+    );
+    let mut numbers: AVLMultiset<u32> = [3, 1, 3].into_iter().collect();
+    assert_eq!(numbers.iter().collect::<Vec<_>>(), [(&1, 1), (&3, 2)]);
+    assert!(numbers.remove_one(&3));
+    assert!(numbers.remove_one(&3));
+    assert!(!numbers.contains(&3));
+    assert!(!numbers.remove_one(&3));
+    assert_eq!(numbers.len(), 1);
+}
+
+// insert on the sets reports whether the value was new, and leaves a value that was there alone
+#[test]
+fn set_inserts_report_duplicates() {
+    let mut avl = AVLTree::new();
+    assert!(avl.insert(5));
+    assert!(!avl.insert(5));
+    assert_eq!(avl.len(), 1);
+
+    let mut rb: RedBlackTree<u32, Vec<u32>> = RedBlackTree::new();
+    assert!(rb.tree_insert(5));
+    rb.get_mut(&5).unwrap().push(1);
+    assert!(!rb.tree_insert(5));
+    assert_eq!(rb.get(&5), Some(&vec![1]));
+}
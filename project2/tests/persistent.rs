@@ -0,0 +1,150 @@
+// Tests for the persistent map: every version made along the way has to keep its own contents while
+// newer versions are made from it, and keeping all of them around has to cost O(log n) nodes per
+// update rather than a full copy of the map.
+use project2::persistent::PersistentAVLMap;
+use proptest::prelude::*;
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+#[derive(Clone, Debug)]
+enum Op {
+    Insert(u32, u32),
+    Remove(u32),
+    // go back to an older version and carry on from there, like an undo
+    Undo(usize),
+}
+
+fn ops() -> impl Strategy<Value = Vec<Op>> {
+    (1..200u32).prop_flat_map(|max_key| {
+        prop::collection::vec(
+            prop_oneof![
+                4 => (0..max_key, any::<u32>()).prop_map(|(key, value)| Op::Insert(key, value)),
+                3 => (0..max_key).prop_map(Op::Remove),
+                1 => any::<usize>().prop_map(Op::Undo),
+            ],
+            0..300,
+        )
+    })
+}
+
+fn check(
+    version: usize,
+    map: &PersistentAVLMap<u32, u32>,
+    model: &BTreeMap<u32, u32>,
+) -> Result<(), TestCaseError> {
+    prop_assert_eq!(
+        map.iter().map(|(&key, &value)| (key, value)).collect::<Vec<_>>(),
+        model.iter().map(|(&key, &value)| (key, value)).collect::<Vec<_>>(),
+        "contents of version {}",
+        version
+    );
+    prop_assert_eq!(map.len(), model.len(), "length of version {}", version);
+    if let Err(violation) = map.validate() {
+        return Err(TestCaseError::fail(format!("version {}: {}", version, violation)));
+    }
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn every_version_keeps_its_contents(ops in ops()) {
+        let mut versions = vec![PersistentAVLMap::new()];
+        let mut models = vec![BTreeMap::new()];
+        for op in ops {
+            let (map, model) = (versions.last().unwrap(), models.last().unwrap());
+            let (next, mut next_model) = match op {
+                Op::Insert(key, value) => (map.insert(key, value), model.clone()),
+                Op::Remove(key) => (map.remove(&key), model.clone()),
+                Op::Undo(back) => {
+                    let index = back % versions.len();
+                    (versions[index].clone(), models[index].clone())
+                }
+            };
+            match op {
+                Op::Insert(key, value) => {
+                    next_model.insert(key, value);
+                }
+                Op::Remove(key) => {
+                    // removing a key that is not there hands back the same version
+                    prop_assert_eq!(next.ptr_eq(map), !next_model.contains_key(&key));
+                    next_model.remove(&key);
+                }
+                Op::Undo(_) => {}
+            }
+            versions.push(next);
+            models.push(next_model);
+        }
+        for (version, (map, model)) in versions.iter().zip(&models).enumerate() {
+            check(version, map, model)?;
+            for key in model.keys() {
+                prop_assert_eq!(map.get(key), model.get(key));
+            }
+            prop_assert_eq!(map.first().map(|(&key, _)| key), model.keys().next().copied());
+            prop_assert_eq!(map.last().map(|(&key, _)| key), model.keys().next_back().copied());
+            prop_assert_eq!(
+                map.keys().rev().copied().collect::<Vec<_>>(),
+                model.keys().rev().copied().collect::<Vec<_>>()
+            );
+        }
+    }
+}
+
+// A value that counts how many copies of it are alive, so the test can see how many nodes all the
+// versions hold between them
+#[derive(Debug)]
+struct Counted(Rc<Cell<usize>>);
+
+impl Counted {
+    fn new(live: &Rc<Cell<usize>>) -> Self {
+        live.set(live.get() + 1);
+        Counted(Rc::clone(live))
+    }
+}
+
+impl Clone for Counted {
+    fn clone(&self) -> Self {
+        Counted::new(&self.0)
+    }
+}
+
+impl Drop for Counted {
+    fn drop(&mut self) {
+        self.0.set(self.0.get() - 1);
+    }
+}
+
+#[test]
+fn versions_share_unchanged_nodes() {
+    let live = Rc::new(Cell::new(0));
+    let n = 2000u32;
+    let mut versions = vec![PersistentAVLMap::new()];
+    for i in 0..n {
+        let key = i.wrapping_mul(2_654_435_761) % (n * 2);
+        let next = versions.last().unwrap().insert(key, Counted::new(&live));
+        versions.push(next);
+    }
+    for i in (0..n).step_by(2) {
+        let key = i.wrapping_mul(2_654_435_761) % (n * 2);
+        let next = versions.last().unwrap().remove(&key);
+        versions.push(next);
+    }
+    let updates = versions.len() - 1;
+    let height = versions.iter().map(|map| map.get_height()).max().unwrap() as usize;
+    // an update copies the nodes on one path plus the few a rotation moves, a full copy per version
+    // would be close to n * updates / 2
+    assert!(
+        live.get() <= updates * (height + 4),
+        "{} nodes alive for {} updates of a tree of height {}",
+        live.get(),
+        updates,
+        height
+    );
+
+    // dropping the history leaves only the nodes of the newest version
+    let newest = versions.pop().unwrap();
+    drop(versions);
+    assert_eq!(live.get(), newest.len());
+    drop(newest);
+    assert_eq!(live.get(), 0);
+}
@@ -0,0 +1,245 @@
+// Script mode tests: the binary is run with a script on stdin or in a file, and its output is
+// compared line by line with what a BTreeSet says the answers should be.
+use project2::BTree;
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn run(args: &[&str], script: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_project2"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("the binary starts");
+    child.stdin.take().unwrap().write_all(script.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn join<'a, I: Iterator<Item = &'a i32>>(values: I) -> String {
+    values.map(i32::to_string).collect::<Vec<_>>().join(" ")
+}
+
+// A long script with its expected output, worked out on a BTreeSet
+fn script_and_expected() -> (String, String) {
+    let mut model = BTreeSet::new();
+    let mut script = String::new();
+    let mut expected = String::new();
+    let mut state = 12345u64;
+    let mut next = |bound: u64| {
+        state = state.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+        ((state >> 33) % bound) as i32
+    };
+    for _ in 0..1500 {
+        let value = next(200) - 100;
+        let (command, answer) = match next(12) {
+            0..=3 => (format!("insert {}", value), model.insert(value).to_string()),
+            4 | 5 => (format!("delete {}", value), model.remove(&value).to_string()),
+            6 => (format!("contains {}", value), model.contains(&value).to_string()),
+            7 => {
+                let high = value + next(50);
+                (format!("range {} {}", value, high), join(model.range(value..=high)))
+            }
+            8 => (format!("rank {}", value), model.range(..value).count().to_string()),
+            9 => {
+                let index = next(100) as usize;
+                let answer = model.iter().nth(index).map_or("none".to_string(), i32::to_string);
+                (format!("select {}", index), answer)
+            }
+            10 => {
+                let min = model.first().map_or("none".to_string(), i32::to_string);
+                ("min".to_string(), min)
+            }
+            _ => ("len".to_string(), model.len().to_string()),
+        };
+        script += &command;
+        script += "\n";
+        expected += &answer;
+        expected += "\n";
+    }
+    script += "print\nvalidate\n";
+    expected += &join(model.iter());
+    expected += "\nok\n";
+    (script, expected)
+}
+
+#[test]
+fn every_tree_answers_like_a_btreeset() {
+    let (script, expected) = script_and_expected();
+    for tree in ["rb", "avl", "btree", "treap", "skiplist"] {
+        let output = run(&["--script", "--tree", tree], &script);
+        assert!(output.status.success(), "{} exited with {}", tree, output.status);
+        assert_eq!(stdout(&output), expected, "output of the {} tree", tree);
+    }
+}
+
+#[test]
+fn comments_blank_lines_and_errors() {
+    let script = "# a comment\n\ninsert 1\nfrobnicate\ninsert\ninsert one\nrange 3\nheight\n";
+    let output = run(&["--script"], script);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        stdout(&output),
+        "true\n\
+         error: unknown command frobnicate\n\
+         error: insert takes 1 argument(s), got 0\n\
+         error: one is not a valid value\n\
+         error: range takes 2 argument(s), got 1\n\
+         1\n"
+    );
+}
+
+#[test]
+fn commands_from_a_file_and_snapshots() {
+    let dir = std::env::temp_dir();
+    let snapshot = dir.join(format!("project2-script-{}.tree", std::process::id()));
+    let script_file = dir.join(format!("project2-script-{}.txt", std::process::id()));
+    let script = format!(
+        "insert 3\ninsert 1\ninsert 2\nsave {0}\nclear\nempty\nload {0}\nprint\n",
+        snapshot.display()
+    );
+    std::fs::write(&script_file, script).unwrap();
+    let output = run(&["--tree", "avl", script_file.to_str().unwrap()], "");
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "true\ntrue\ntrue\nok\nok\ntrue\n3\n1 2 3\n");
+
+    // a snapshot written by one tree loads into the other
+    let output = run(&["--tree", "rb", "-"], &format!("load {}\nmax\n", snapshot.display()));
+    assert_eq!(stdout(&output), "3\n3\n");
+    std::fs::remove_file(&snapshot).unwrap();
+    std::fs::remove_file(&script_file).unwrap();
+
+    let output = run(&["--script"], &format!("load {}\n", snapshot.display()));
+    assert!(stdout(&output).starts_with("error: "));
+}
+
+#[test]
+fn btree_order_is_kept_by_clear_and_load() {
+    let snapshot = std::env::temp_dir().join(format!("project2-order-{}.tree", std::process::id()));
+    // seven values fit in the root of a B-tree of the default order, one of order 3 needs three
+    // levels
+    let mut tree = BTree::with_order(3);
+    let mut script = String::new();
+    for value in 1..=7 {
+        tree.insert(value);
+        script += &format!("insert {}\n", value);
+    }
+    script += &format!("height\nsave {0}\nclear\nload {0}\nheight\n", snapshot.display());
+    script += "clear\ninsert 1\ninsert 2\ninsert 3\nheight\n";
+    let output = run(&["--tree", "btree", "--order", "3"], &script);
+    assert!(output.status.success());
+    let height = tree.get_height();
+    assert_eq!(height, 3);
+    let inserted = |count| "true\n".repeat(count);
+    let expected = format!("{}{1}\nok\nok\n7\n{1}\nok\n{2}2\n", inserted(7), height, inserted(3));
+    assert_eq!(stdout(&output), expected);
+    std::fs::remove_file(&snapshot).unwrap();
+}
+
+#[test]
+fn bad_arguments() {
+    for args in [
+        &["--tree", "splay"][..],
+        &["--tree"],
+        &["--verbose"],
+        &["a", "b"],
+        &["--tree", "btree", "--order", "2"],
+        &["--tree", "btree", "--order"],
+        &["--tree", "avl", "--order", "4"],
+        &["--order", "4"],
+    ] {
+        let output = run(args, "");
+        assert_eq!(output.status.code(), Some(2), "{:?}", args);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("usage:"));
+    }
+    let output = run(&["--script", "/no/such/script"], "");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+// The lines with a newline after each one
+fn lines(lines: &[&str]) -> String {
+    lines.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+#[test]
+fn explain_prints_the_steps_before_each_result() {
+    let output = run(&["--explain"], "insert 2\ninsert 1\ninsert 3\ncontains 3\n");
+    assert!(output.status.success());
+    let expected = lines(&[
+        "  insert 2",
+        "    - R─2",
+        "  case RecolorRoot at 2",
+        "    - R─2",
+        "  recolour 2 Black",
+        "    - B─2",
+        "true",
+        "  insert 1",
+        "    - B─2",
+        "      ┌-R─1",
+        "true",
+        "  insert 3",
+        "    - B─2",
+        "      ┌-R─1",
+        "      └-R─3",
+        "true",
+        "true",
+    ]);
+    assert_eq!(stdout(&output), expected);
+
+    let script = "insert 3\ninsert 2\ninsert 1\nclear\ninsert 1\n";
+    let output = run(&["--explain", "--tree", "avl"], script);
+    let expected = lines(&[
+        "  insert 3",
+        "    3",
+        "true",
+        "  insert 2",
+        "    3",
+        "    |-----2",
+        "true",
+        "  insert 1",
+        "    3",
+        "    |-----2",
+        "           |-----1",
+        "  case LeftLeft at 3",
+        "    3",
+        "    |-----2",
+        "           |-----1",
+        "  rotate right at 3",
+        "    |-----3",
+        "    2",
+        "    |-----1",
+        "true",
+        // a cleared tree is watched as well
+        "ok",
+        "  insert 1",
+        "    1",
+        "true",
+    ]);
+    assert_eq!(stdout(&output), expected);
+
+    // the other structures have no steps to show
+    let output = run(&["--explain", "--tree", "btree"], "");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn stats_of_the_binary_trees() {
+    let script = "insert 2\ninsert 1\ninsert 3\ninsert 4\nstats\n";
+    let output = run(&["--tree", "avl", "-"], script);
+    assert_eq!(
+        stdout(&output).lines().last().unwrap(),
+        "nodes 4 leaves 2 leaf depth 1..2 average 1.50 internal path length 4 height 3 optimal 3 \
+         ratio 1.00 balance factors -1:2 0:2"
+    );
+    let output = run(&["--tree", "rb", "-"], script);
+    assert!(stdout(&output).ends_with("ratio 1.00 black height 2\n"));
+
+    let output = run(&["--tree", "skiplist", "-"], "stats\n");
+    assert_eq!(stdout(&output), "error: a Skip List is not a binary tree\n");
+    assert_eq!(output.status.code(), Some(1));
+}
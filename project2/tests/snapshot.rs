@@ -0,0 +1,198 @@
+// Snapshot tests: trees written out with to_snapshot or save_to have to come back with the same
+// entries and still be valid, and damaged snapshots have to be turned away with an error rather
+// than a panic or a wrong tree.
+use project2::avl::{AVLMap, AVLTree};
+use project2::btree::BTree;
+use project2::rb::RedBlackTree;
+use project2::skiplist::SkipList;
+use project2::snapshot::{self, Codec, SnapshotError, FORMAT_VERSION};
+use project2::treap::Treap;
+
+fn scrambled(n: i64) -> impl Iterator<Item = i64> {
+    (0..n).map(move |i| (i * 7919) % (n * 2) - n)
+}
+
+#[test]
+fn trees_round_trip() {
+    for n in [0, 1, 2, 100, 1000] {
+        let rb: RedBlackTree<i64, String> = scrambled(n).map(|key| (key, format!("v{}", key))).collect();
+        let loaded = RedBlackTree::<i64, String>::from_snapshot(&rb.to_snapshot()).unwrap();
+        assert!(loaded.validate().is_ok());
+        assert!(loaded.iter().eq(rb.iter()));
+
+        let avl: AVLMap<i64, String> = scrambled(n).map(|key| (key, format!("v{}", key))).collect();
+        let loaded = AVLMap::<i64, String>::from_snapshot(&avl.to_snapshot()).unwrap();
+        assert!(loaded.validate().is_ok());
+        assert!(loaded.iter().eq(avl.iter()));
+
+        let set: AVLTree<i64> = scrambled(n).collect();
+        let loaded = AVLTree::<i64>::from_snapshot(&set.to_snapshot()).unwrap();
+        assert!(loaded.validate().is_ok());
+        assert!(loaded.iter().eq(set.iter()));
+    }
+}
+
+#[test]
+fn snapshots_load_into_any_tree() {
+    let set: AVLTree<u32> = (0..500).collect();
+    let rb = RedBlackTree::<u32>::from_snapshot(&set.to_snapshot()).unwrap();
+    assert!(rb.validate().is_ok());
+    assert!(rb.keys().eq(set.iter()));
+    assert_eq!(rb.to_snapshot(), set.to_snapshot());
+
+    let btree = BTree::<u32>::from_snapshot(&rb.to_snapshot()).unwrap();
+    assert!(btree.validate().is_ok());
+    assert!(btree.iter().eq(set.iter()));
+    assert_eq!(btree.to_snapshot(), set.to_snapshot());
+    let btree = BTree::<u32>::from_snapshot_with_order(&rb.to_snapshot(), 4).unwrap();
+    assert_eq!(btree.order(), 4);
+    assert!(btree.validate().is_ok());
+    assert_eq!(btree.to_snapshot(), set.to_snapshot());
+
+    let treap = Treap::<u32>::from_snapshot(&rb.to_snapshot()).unwrap();
+    assert!(treap.validate().is_ok());
+    assert_eq!(treap.to_snapshot(), set.to_snapshot());
+    let list = SkipList::<u32>::from_snapshot(&treap.to_snapshot()).unwrap();
+    assert!(list.validate().is_ok());
+    assert_eq!(list.to_snapshot(), set.to_snapshot());
+}
+
+#[test]
+fn save_and_load_through_a_file() {
+    let path = std::env::temp_dir().join(format!("project2-snapshot-{}.tree", std::process::id()));
+    let rb: RedBlackTree<u32> = (0..300).map(|i| i * 3).collect();
+    rb.save_to(&path).unwrap();
+    let loaded = RedBlackTree::<u32>::load_from(&path).unwrap();
+    assert!(loaded.keys().eq(rb.keys()));
+
+    let avl: AVLTree<u32> = (0..300).collect();
+    avl.save_to(&path).unwrap();
+    let loaded = AVLTree::<u32>::load_from(&path).unwrap();
+    assert!(loaded.iter().eq(avl.iter()));
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(matches!(AVLTree::<u32>::load_from(&path), Err(SnapshotError::Io(_))));
+}
+
+#[test]
+fn small_values_take_one_byte() {
+    let set: AVLTree<i32> = (-64..64).collect();
+    // magic, version, count, one byte per value, checksum
+    assert_eq!(set.to_snapshot().len(), 4 + 1 + 2 + 128 + 4);
+}
+
+#[test]
+fn every_codec_round_trips() {
+    fn round_trip<T: Codec + PartialEq + std::fmt::Debug>(values: &[T]) {
+        for value in values {
+            let mut bytes = Vec::new();
+            value.encode(&mut bytes);
+            let mut input = &bytes[..];
+            assert_eq!(&T::decode(&mut input).unwrap(), value);
+            assert!(input.is_empty());
+        }
+    }
+    round_trip(&[0u8, 127, 128, u8::MAX]);
+    round_trip(&[0u64, 300, u64::MAX]);
+    round_trip(&[0u128, u128::MAX]);
+    round_trip(&[0i8, -1, i8::MIN, i8::MAX]);
+    round_trip(&[0i64, -1, 1, -300, i64::MIN, i64::MAX]);
+    round_trip(&[i128::MIN, i128::MAX]);
+    round_trip(&[usize::MAX, 0]);
+    round_trip(&[isize::MIN, isize::MAX]);
+    round_trip(&[true, false]);
+    round_trip(&['a', 'é', '🌲']);
+    round_trip(&[String::new(), "red-black".to_string(), "ünïcode".to_string()]);
+}
+
+#[test]
+fn damaged_snapshots_are_rejected() {
+    let rb: RedBlackTree<u32, u32> = (0..100).map(|i| (i, i * i)).collect();
+    let bytes = rb.to_snapshot();
+    let load = |bytes: &[u8]| RedBlackTree::<u32, u32>::from_snapshot(bytes).map(|_| ());
+
+    // any single flipped bit is caught by the checksum
+    for i in 5..bytes.len() {
+        let mut damaged = bytes.clone();
+        damaged[i] ^= 0x10;
+        assert!(matches!(load(&damaged), Err(SnapshotError::Checksum { .. })), "byte {}", i);
+    }
+
+    let mut damaged = bytes.clone();
+    damaged[0] = b'X';
+    assert!(matches!(load(&damaged), Err(SnapshotError::BadMagic)));
+    assert!(matches!(load(b""), Err(SnapshotError::BadMagic)));
+
+    let mut damaged = bytes.clone();
+    damaged[4] = FORMAT_VERSION + 1;
+    assert!(matches!(load(&damaged), Err(SnapshotError::UnsupportedVersion(_))));
+
+    for len in [4, 5, 8, bytes.len() - 1] {
+        assert!(load(&bytes[..len]).is_err(), "cut at {}", len);
+    }
+    // a set snapshot has no values, reading it as a map runs out of bytes
+    let set: RedBlackTree<u32> = (0..100).collect();
+    assert!(matches!(load(&set.to_snapshot()), Err(SnapshotError::Truncated)));
+    // and a map snapshot read as a set has bytes left over, here the values happen to line up
+    // with the keys as one ascending run
+    let interleaved: RedBlackTree<u32, u32> = (0..100).map(|i| (2 * i, 2 * i + 1)).collect();
+    assert!(matches!(
+        RedBlackTree::<u32>::from_snapshot(&interleaved.to_snapshot()),
+        Err(SnapshotError::TrailingBytes)
+    ));
+    assert!(matches!(RedBlackTree::<u32>::from_snapshot(&bytes), Err(SnapshotError::Unsorted)));
+}
+
+// Entries that are out of order can only come from a snapshot written by something else, the
+// checksum is right but the tree would be wrong
+#[test]
+fn unsorted_snapshots_are_rejected() {
+    let mut body = b"TREE".to_vec();
+    body.push(FORMAT_VERSION);
+    for value in [3u32, 2, 1, 2] {
+        value.encode(&mut body);
+    }
+    let checksum = snapshot::crc32(&body);
+    body.extend_from_slice(&checksum.to_le_bytes());
+    assert!(matches!(AVLTree::<u32>::from_snapshot(&body), Err(SnapshotError::Unsorted)));
+}
+
+#[test]
+fn crc32_matches_the_standard_check_value() {
+    assert_eq!(snapshot::crc32(b"123456789"), 0xcbf4_3926);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_round_trip() {
+    let rb: RedBlackTree<u32, String> = (0..50).map(|i| (i, i.to_string())).collect();
+    let json = serde_json::to_string(&rb).unwrap();
+    assert!(json.starts_with(r#"[[0,"0"],[1,"1"]"#));
+    let loaded: RedBlackTree<u32, String> = serde_json::from_str(&json).unwrap();
+    assert!(loaded.validate().is_ok());
+    assert!(loaded.iter().eq(rb.iter()));
+
+    let set: AVLTree<i32> = (-20..20).collect();
+    let json = serde_json::to_string(&set).unwrap();
+    assert!(json.starts_with("[-20,-19,"));
+    let loaded: AVLTree<i32> = serde_json::from_str(&json).unwrap();
+    assert!(loaded.validate().is_ok());
+    assert!(loaded.iter().eq(set.iter()));
+
+    // the sets differ: a red-black set keeps the () of its (key, value) pairs and an AVL set
+    // writes its values alone, so neither reads what the other wrote
+    let rb_set: RedBlackTree<i32> = [2, 1, 3].into_iter().collect();
+    let json = serde_json::to_string(&rb_set).unwrap();
+    assert_eq!(json, "[[1,null],[2,null],[3,null]]");
+    let loaded: RedBlackTree<i32> = serde_json::from_str(&json).unwrap();
+    assert!(loaded.validate().is_ok());
+    assert!(loaded.iter().eq(rb_set.iter()));
+    assert!(serde_json::from_str::<AVLTree<i32>>(&json).is_err());
+    let avl_set: AVLTree<i32> = [2, 1, 3].into_iter().collect();
+    assert_eq!(serde_json::to_string(&avl_set).unwrap(), "[1,2,3]");
+    assert!(serde_json::from_str::<RedBlackTree<i32>>("[1,2,3]").is_err());
+
+    // input written by hand does not have to be in order
+    let map: AVLMap<u32, u32> = serde_json::from_str("[[3,30],[1,10],[2,20],[1,11]]").unwrap();
+    assert_eq!(map.iter().map(|(&k, &v)| (k, v)).collect::<Vec<_>>(), [(1, 11), (2, 20), (3, 30)]);
+}
@@ -0,0 +1,135 @@
+// Statistics tests: the figures of small trees whose shape is known are checked exactly, and for
+// larger random trees they have to agree with each other, with len, height and count_leaves, and
+// with the height bound of each tree type.
+use project2::{AVLTree, OrderedSet, RedBlackTree, Treap, TreeStats};
+use std::collections::BTreeMap;
+
+#[test]
+fn a_perfect_tree_is_as_low_as_it_can_be() {
+    let stats = AVLTree::from_sorted_iter(1..=7).stats();
+    assert_eq!(
+        stats,
+        TreeStats {
+            nodes: 7,
+            leaves: 4,
+            min_leaf_depth: 2,
+            max_leaf_depth: 2,
+            average_leaf_depth: 2.0,
+            internal_path_length: 10,
+            height: 3,
+            black_height: None,
+            balance_factors: Some(BTreeMap::from([(0, 7)])),
+        }
+    );
+    assert_eq!(stats.optimal_height(), 3);
+    assert_eq!(stats.height_ratio(), 1.0);
+}
+
+#[test]
+fn uneven_leaves() {
+    // 2 over 1 and 3, with 4 below 3
+    let stats = AVLTree::from_iter([2, 1, 3, 4]).stats();
+    assert_eq!(
+        (stats.leaves, stats.min_leaf_depth, stats.max_leaf_depth),
+        (2, 1, 2)
+    );
+    assert_eq!(stats.average_leaf_depth, 1.5);
+    assert_eq!(stats.internal_path_length, 4);
+    assert_eq!(
+        stats.balance_factors,
+        Some(BTreeMap::from([(-1, 2), (0, 2)]))
+    );
+
+    let stats = RedBlackTree::<u32>::from_iter([2, 1, 3, 4]).stats();
+    assert_eq!(
+        (stats.nodes, stats.height, stats.optimal_height()),
+        (4, 3, 3)
+    );
+    // 4 is red, every other node black
+    assert_eq!(stats.black_height, Some(2));
+    assert_eq!(stats.balance_factors, None);
+}
+
+#[test]
+fn empty_trees() {
+    let stats = RedBlackTree::<u32>::new().stats();
+    assert_eq!((stats.nodes, stats.leaves, stats.height), (0, 0, 0));
+    assert_eq!((stats.min_leaf_depth, stats.max_leaf_depth), (0, 0));
+    assert_eq!(stats.average_leaf_depth, 0.0);
+    assert_eq!(stats.optimal_height(), 0);
+    assert_eq!(stats.height_ratio(), 1.0);
+    assert_eq!(stats.black_height, Some(0));
+    assert_eq!(
+        AVLTree::<u32>::new().stats().balance_factors,
+        Some(BTreeMap::new())
+    );
+}
+
+// Fills the set with pseudo-random values, removing some on the way
+fn fill<S: OrderedSet<u32>>() -> S {
+    let mut set = S::new();
+    let mut state = 7u64;
+    for _ in 0..5_000 {
+        state = state
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        let value = (state >> 40) as u32 % 3_000;
+        if state.is_multiple_of(4) {
+            set.remove(&value);
+        } else {
+            set.insert(value);
+        }
+    }
+    set
+}
+
+fn consistent<S: OrderedSet<u32>>() -> TreeStats {
+    let set: S = fill();
+    let stats = set.stats().expect("a binary tree");
+    assert_eq!(stats.nodes, set.len());
+    assert_eq!(stats.leaves, set.count_leaves());
+    assert_eq!(stats.height as i32, set.height());
+    assert_eq!(stats.max_leaf_depth + 1, stats.height);
+    assert!(stats.min_leaf_depth as f64 <= stats.average_leaf_depth);
+    assert!(stats.average_leaf_depth <= stats.max_leaf_depth as f64);
+    // at most 2^d nodes sit at depth d
+    let mut least = 0;
+    let mut left = stats.nodes;
+    for depth in 0.. {
+        let level = left.min(1 << depth);
+        least += level * depth;
+        left -= level;
+        if left == 0 {
+            break;
+        }
+    }
+    assert!(stats.internal_path_length >= least);
+    assert!(stats.internal_path_length <= (stats.height - 1) * stats.nodes);
+    assert!(stats.height >= stats.optimal_height());
+    stats
+}
+
+#[test]
+fn red_black_figures_agree() {
+    let stats = consistent::<RedBlackTree<u32>>();
+    // a red-black tree is at most twice as tall as it has to be
+    assert!(stats.height_ratio() <= 2.0);
+    let black_height = stats.black_height.unwrap();
+    assert!(stats.height <= 2 * black_height + 1);
+}
+
+#[test]
+fn avl_figures_agree() {
+    let stats = consistent::<AVLTree<u32>>();
+    assert!(stats.height_ratio() <= 1.45);
+    let balance_factors = stats.balance_factors.unwrap();
+    assert!(balance_factors.keys().all(|balance| balance.abs() <= 1));
+    assert_eq!(balance_factors.values().sum::<usize>(), stats.nodes);
+}
+
+#[test]
+fn treap_figures_agree() {
+    let stats = consistent::<Treap<u32>>();
+    assert_eq!(stats.black_height, None);
+    assert_eq!(stats.balance_factors, None);
+}
@@ -0,0 +1,226 @@
+// Tracing tests: an observer writes down the steps of small inserts and removes whose rotations and
+// fix-up cases are known, and for longer random runs checks that the tree it is handed after every
+// step still holds all of its values in order.
+use project2::rb::NodeColor;
+use project2::{AVLTree, RedBlackTree, Step};
+use std::sync::{Arc, Mutex};
+
+type Steps = Arc<Mutex<Vec<Step<u32>>>>;
+
+fn owned(step: Step<&u32>) -> Step<u32> {
+    step.map(|key| *key)
+}
+
+fn watched_red_black() -> (RedBlackTree<u32>, Steps) {
+    let steps = Steps::default();
+    let mut tree = RedBlackTree::new();
+    let seen = Arc::clone(&steps);
+    tree.set_observer(move |step, _| seen.lock().unwrap().push(owned(step)));
+    (tree, steps)
+}
+
+fn watched_avl() -> (AVLTree<u32>, Steps) {
+    let steps = Steps::default();
+    let mut tree = AVLTree::new();
+    let seen = Arc::clone(&steps);
+    tree.set_observer(move |step, _| seen.lock().unwrap().push(owned(step)));
+    (tree, steps)
+}
+
+fn take(steps: &Steps) -> Vec<Step<u32>> {
+    std::mem::take(&mut *steps.lock().unwrap())
+}
+
+fn case(name: &'static str, at: u32) -> Step<u32> {
+    Step::Case { name, at }
+}
+
+fn recolor(node: u32, color: NodeColor) -> Step<u32> {
+    Step::Recolor { node, color }
+}
+
+#[test]
+fn red_black_insert_steps() {
+    let (mut tree, steps) = watched_red_black();
+    tree.insert(1, ());
+    assert_eq!(
+        take(&steps),
+        [
+            Step::Insert(1),
+            case("RecolorRoot", 1),
+            recolor(1, NodeColor::Black)
+        ]
+    );
+    tree.insert(2, ());
+    assert_eq!(take(&steps), [Step::Insert(2)]);
+    tree.insert(3, ());
+    assert_eq!(
+        take(&steps),
+        [
+            Step::Insert(3),
+            case("RotationRightRight", 3),
+            Step::RotateLeft(1),
+            recolor(2, NodeColor::Black),
+            recolor(1, NodeColor::Red),
+        ]
+    );
+    // an uncle that is red only moves colours around
+    tree.insert(4, ());
+    assert_eq!(
+        take(&steps)[..2],
+        [Step::Insert(4), case("RecolorUncleLeft", 4)]
+    );
+}
+
+#[test]
+fn red_black_remove_steps() {
+    let (mut tree, steps) = watched_red_black();
+    tree.extend([(2, ()), (1, ()), (3, ())]);
+    take(&steps);
+    // the root has two children, so its successor takes its place
+    tree.remove(&2);
+    assert_eq!(
+        take(&steps),
+        [Step::Replace { removed: 2, by: 3 }, Step::Remove(2)]
+    );
+    assert_eq!(tree.keys().copied().collect::<Vec<_>>(), [1, 3]);
+
+    // nothing is reported for a key that is not there, or without an observer
+    tree.remove(&7);
+    tree.clear_observer();
+    tree.insert(5, ());
+    assert!(take(&steps).is_empty());
+}
+
+#[test]
+fn avl_single_and_double_rotations() {
+    let (mut tree, steps) = watched_avl();
+    tree.extend([3, 2, 1]);
+    assert_eq!(
+        take(&steps),
+        [
+            Step::Insert(3),
+            Step::Insert(2),
+            Step::Insert(1),
+            case("LeftLeft", 3),
+            Step::RotateRight(3),
+        ]
+    );
+
+    let (mut tree, steps) = watched_avl();
+    tree.extend([3, 1, 2]);
+    assert_eq!(
+        take(&steps)[2..],
+        [
+            Step::Insert(2),
+            case("LeftRight", 3),
+            Step::RotateLeft(1),
+            Step::RotateRight(3),
+        ]
+    );
+
+    let (mut tree, steps) = watched_avl();
+    tree.extend([1, 3, 2]);
+    assert_eq!(
+        take(&steps)[2..],
+        [
+            Step::Insert(2),
+            case("RightLeft", 1),
+            Step::RotateRight(3),
+            Step::RotateLeft(1),
+        ]
+    );
+}
+
+#[test]
+fn avl_remove_steps() {
+    let (mut tree, steps) = watched_avl();
+    tree.extend([2, 1, 3, 0]);
+    take(&steps);
+    // 3 takes the place of 2 and leaves its left side two levels deeper than its right one
+    tree.delete(2);
+    assert_eq!(
+        take(&steps),
+        [
+            Step::Replace { removed: 2, by: 3 },
+            Step::Remove(2),
+            case("LeftLeft", 3),
+            Step::RotateRight(3),
+        ]
+    );
+    tree.delete(0);
+    assert_eq!(take(&steps), [Step::Remove(0)]);
+    tree.validate().unwrap();
+}
+
+// The values the AVL tree draws, from the top line down
+fn drawn(tree: &project2::AVLMap<u32, ()>) -> Vec<u32> {
+    let mut out = Vec::new();
+    tree.write_structure(&mut out).unwrap();
+    String::from_utf8(out)
+        .unwrap()
+        .lines()
+        .map(|line| {
+            line.trim_start()
+                .trim_start_matches("|-----")
+                .parse()
+                .unwrap()
+        })
+        .collect()
+}
+
+#[test]
+fn every_step_shows_a_whole_tree() {
+    let present = Arc::new(Mutex::new(0usize));
+    let mut avl = AVLTree::new();
+    let seen = Arc::clone(&present);
+    avl.set_observer(move |_, tree| {
+        let values = drawn(tree);
+        // right subtrees are drawn above their parent, so the values come largest first
+        assert!(
+            values.windows(2).all(|pair| pair[0] > pair[1]),
+            "{:?}",
+            values
+        );
+        assert!(values.len().abs_diff(*seen.lock().unwrap()) <= 1);
+    });
+    let mut red_black = RedBlackTree::new();
+    let seen = Arc::clone(&present);
+    let mut replacing = false;
+    red_black.set_observer(move |step, tree: &RedBlackTree<u32>| {
+        // from the replace until the remove, the removed value sits where the one replacing it was
+        match step {
+            Step::Replace { .. } => replacing = true,
+            Step::Remove(_) => replacing = false,
+            _ => {}
+        }
+        if replacing {
+            return;
+        }
+        let values: Vec<u32> = tree.keys().copied().collect();
+        assert!(
+            values.windows(2).all(|pair| pair[0] < pair[1]),
+            "{:?}",
+            values
+        );
+        assert!(values.len().abs_diff(*seen.lock().unwrap()) <= 1);
+    });
+
+    let mut state = 99u32;
+    for _ in 0..2_000 {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        let value = (state >> 16) % 300;
+        let len = avl.len();
+        *present.lock().unwrap() = len;
+        if state.is_multiple_of(3) {
+            avl.delete(value);
+            red_black.remove(&value);
+        } else {
+            avl.insert(value);
+            red_black.insert(value, ());
+        }
+        assert_eq!(avl.len(), red_black.len());
+    }
+    avl.validate().unwrap();
+    red_black.validate().unwrap();
+}